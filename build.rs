@@ -27,6 +27,12 @@ const RESOURCES: &[&str] = &[
     "icons/thickness.svg",
     "icons/grid.svg",
     "icons/free_grid.svg",
+    "icons/image.svg",
+    "icons/laser.svg",
+    "icons/highlighter.svg",
+    "icons/text.svg",
+    "icons/select.svg",
+    "icons/fill.svg",
 ];
 
 fn main() {
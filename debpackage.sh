@@ -23,6 +23,7 @@ install -Dm644 "LICENSE" "${DPKG_DIR}/usr/share/doc/${DPKG_BASENAME}/LICENSE"
 install -Dm644 "CHANGELOG.md" "${DPKG_DIR}/usr/share/doc/${DPKG_BASENAME}/changelog"
 gzip -n --best "${DPKG_DIR}/usr/share/doc/${DPKG_BASENAME}/changelog"
 install -Dm644 res/pizarra.desktop "${DPKG_DIR}/usr/share/applications/pizarra.desktop"
+install -Dm644 res/pizarra-mime.xml "${DPKG_DIR}/usr/share/mime/packages/pizarra.xml"
 install -Dm644 res/icons/tk.categulario.pizarra.svg "${DPKG_DIR}/usr/share/icons/hicolor/scalable/apps/pizarra.svg"
 
 cat > "${DPKG_DIR}/usr/share/doc/${DPKG_BASENAME}/copyright" <<EOF
@@ -38,6 +38,7 @@ package() {
     cd \"\$pkgname\"
     install -Dm755 target/release/pizarra \"\$pkgdir\"/usr/bin/pizarra
     install -Dm644 res/pizarra.desktop \"\$pkgdir\"/usr/share/applications/pizarra.desktop
+    install -Dm644 res/pizarra-mime.xml \"\$pkgdir\"/usr/share/mime/packages/pizarra.xml
     install -Dm644 res/icons/tk.categulario.pizarra.svg \"\$pkgdir\"/usr/share/icons/hicolor/scalable/apps/pizarra.svg
 
     install -Dm644 README.md \"\$pkgdir\"/usr/share/doc/pizarra/README.md
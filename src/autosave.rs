@@ -0,0 +1,162 @@
+//! Periodic copies of documents with unsaved changes, so a crash or a power
+//! outage doesn't take the drawing with it.
+//!
+//! Every window writes its copy to its own recovery file in the data
+//! directory, named after the process it belongs to, so the files of
+//! processes that are no longer running belong to windows that are gone.
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use gtk::glib::{self, Continue, SourceId};
+use pizarra::prelude::*;
+
+use crate::config::{self, AutosaveSettings};
+use crate::pages::Pages;
+
+static WINDOW_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn recovery_dir() -> PathBuf {
+    let mut dir = config::data_dir();
+
+    dir.push("recovery");
+
+    dir
+}
+
+/// Writes copies of a window's document to its recovery file
+pub struct Autosave {
+    path: PathBuf,
+    source: Option<SourceId>,
+}
+
+impl Autosave {
+    /// Starts copying the document every `settings.interval` seconds while it
+    /// has unsaved changes.
+    pub fn start(controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>, settings: &AutosaveSettings) -> Rc<RefCell<Autosave>> {
+        let dir = recovery_dir();
+        let path = dir.join(format!("{}-{}.svg", process::id(), WINDOW_COUNT.fetch_add(1, Ordering::SeqCst)));
+        let autosave = Rc::new(RefCell::new(Autosave { path, source: None }));
+
+        if !settings.enabled || settings.interval == 0 {
+            return autosave;
+        }
+
+        if let Err(e) = fs::create_dir_all(&dir) {
+            log::warn!("Autosave disabled, could not create {}: {}", dir.display(), e);
+
+            return autosave;
+        }
+
+        let weak = Rc::downgrade(&autosave);
+        let source = glib::timeout_add_seconds_local(settings.interval, move || {
+            if let Some(autosave) = weak.upgrade() {
+                autosave.borrow().tick(&controller, &pages);
+
+                Continue(true)
+            } else {
+                Continue(false)
+            }
+        });
+
+        autosave.borrow_mut().source = Some(source);
+
+        autosave
+    }
+
+    fn tick(&self, controller: &RefCell<Pizarra>, pages: &RefCell<Pages>) {
+        // If something else is using the document just wait for the next tick
+        let (mut controller, mut pages) = match (controller.try_borrow_mut(), pages.try_borrow_mut()) {
+            (Ok(controller), Ok(pages)) => (controller, pages),
+            _ => return,
+        };
+
+        match pages.save_status(controller.get_save_status()) {
+            SaveStatus::NewAndChanged | SaveStatus::Unsaved(_) => {
+                let svg = pages.document_svg(&mut controller);
+
+                if let Err(e) = self.write(&svg) {
+                    log::warn!("Could not write recovery file {}: {}", self.path.display(), e);
+                }
+            },
+            SaveStatus::NewAndEmpty | SaveStatus::Saved(_) => self.discard(),
+        }
+    }
+
+    /// Writes the copy through a temporary file next to the recovery file, so
+    /// if writing fails the previous copy is still there
+    fn write(&self, svg: &str) -> io::Result<()> {
+        let temporary = self.path.with_extension("svg.tmp");
+        let mut file = File::create(&temporary)?;
+
+        file.write_all(svg.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)
+    }
+
+    /// Removes the recovery file, for when the document no longer needs it
+    pub fn discard(&self) {
+        if self.path.is_file() {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+
+    /// Stops making copies and removes the recovery file
+    pub fn stop(&mut self) {
+        if let Some(source) = self.source.take() {
+            source.remove();
+        }
+
+        self.discard();
+    }
+}
+
+/// Tells if the process with the given id is running, when the system lets
+/// us know
+fn is_running(pid: u32) -> Option<bool> {
+    if pid == process::id() {
+        return Some(true);
+    }
+
+    if cfg!(target_os = "linux") {
+        Some(Path::new("/proc").join(pid.to_string()).exists())
+    } else {
+        None
+    }
+}
+
+/// Recovery files left behind by windows that are no longer running, most
+/// recent first. A file is abandoned if the process in its name is not
+/// running. Where that can't be known it is abandoned if it hasn't been
+/// written in three autosave intervals. Copies that were being written when
+/// their window died are left out, the previous one is still there.
+pub fn abandoned_files(settings: &AutosaveSettings) -> Vec<(PathBuf, SystemTime)> {
+    let threshold = Duration::from_secs(settings.interval.max(1) as u64 * 3);
+    let now = SystemTime::now();
+
+    let mut files: Vec<_> = fs::read_dir(recovery_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().extension().map(|ext| ext == "svg").unwrap_or(false))
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+            let pid = entry.file_name().to_string_lossy().split('-').next().and_then(|pid| pid.parse().ok());
+            let abandoned = match pid.and_then(is_running) {
+                Some(running) => !running,
+                None => now.duration_since(modified).unwrap_or_default() > threshold,
+            };
+
+            abandoned.then(|| (entry.path(), modified))
+        })
+        .collect();
+
+    files.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    files
+}
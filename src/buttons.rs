@@ -0,0 +1,194 @@
+//! Extra buttons of mice, pens and tablet pads, like the side buttons of a
+//! stylus or the back and forward buttons of a mouse. They can be assigned to
+//! actions in the `[buttons]` section of the configuration file or from
+//! _Editar → Botones…_, which captures the button to assign.
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use gdk::{EventMask, EventType, InputSource};
+use gtk::{Dialog, DialogFlags, Grid, Label, Button, ResponseType, Window};
+use gtk::prelude::*;
+use glib::clone;
+use serde::{Serialize, Deserialize};
+
+use crate::config::{self, ButtonSettings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ButtonAction {
+    Undo,
+    Redo,
+
+    /// Moves the view while the button is held
+    Pan,
+
+    /// Erases while the button is held
+    Eraser,
+
+    NextColor,
+    NextTool,
+}
+
+impl ButtonAction {
+    pub const ALL: &'static [ButtonAction] = &[
+        ButtonAction::Undo,
+        ButtonAction::Redo,
+        ButtonAction::Pan,
+        ButtonAction::Eraser,
+        ButtonAction::NextColor,
+        ButtonAction::NextTool,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ButtonAction::Undo => "Deshacer",
+            ButtonAction::Redo => "Rehacer",
+            ButtonAction::Pan => "Mover la vista (mantener)",
+            ButtonAction::Eraser => "Borrar (mantener)",
+            ButtonAction::NextColor => "Siguiente color",
+            ButtonAction::NextTool => "Siguiente herramienta",
+        }
+    }
+}
+
+/// Buttons by name and the action each one triggers, see [`button_name`]
+pub type Bindings = BTreeMap<String, ButtonAction>;
+
+/// Runs an action when its button is pressed (`true`) or released, with the
+/// position of the pointer on the canvas if it was there
+pub type RunAction = Rc<dyn Fn(ButtonAction, bool, Option<(f64, f64)>)>;
+
+/// Colors that `next-color` goes through when the palette has none
+pub const COLORS: &[[f64; 3]] = &[
+    [0.0, 0.0, 0.0],
+    [0.8, 0.0, 0.0],
+    [0.0, 0.4, 0.8],
+    [0.0, 0.6, 0.2],
+    [1.0, 0.6, 0.0],
+];
+
+/// Name of a button as used in the configuration: `mouse-N` or `stylus-N`.
+/// The left button of the mouse and the tip of the pen draw, so they have no
+/// name, and neither do fingers.
+pub fn button_name(source: Option<InputSource>, button: u32) -> Option<String> {
+    match source {
+        Some(InputSource::Touchscreen) => None,
+        _ if button <= 1 => None,
+        Some(InputSource::Pen | InputSource::Eraser) => Some(format!("stylus-{}", button)),
+        _ => Some(format!("mouse-{}", button)),
+    }
+}
+
+/// Name of a button of a tablet pad, `pad-N`
+pub fn pad_button_name(button: u32) -> String {
+    format!("pad-{}", button)
+}
+
+/// Name of the button of a press event, mouse, pen or pad
+fn event_button_name(event: &gdk::Event) -> Option<String> {
+    match event.event_type() {
+        EventType::ButtonPress => {
+            let button = event.downcast_ref::<gdk::EventButton>()?.button();
+
+            button_name(event.source_device().map(|d| d.source()), button)
+        },
+        EventType::PadButtonPress => Some(pad_button_name(event.downcast_ref::<gdk::EventPadButton>()?.button())),
+        _ => None,
+    }
+}
+
+fn assigned(bindings: &Bindings, action: ButtonAction) -> String {
+    let buttons: Vec<&str> = bindings.iter()
+        .filter(|(_, &a)| a == action)
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    if buttons.is_empty() {
+        "Ninguno".into()
+    } else {
+        buttons.join(", ")
+    }
+}
+
+/// Shows the dialog to assign buttons to actions. Changes apply right away
+/// and are written to the configuration file.
+pub fn settings_dialog<P: IsA<Window>>(window: &P, bindings: Rc<RefCell<Bindings>>) {
+    let dialog = Dialog::with_buttons(Some("Botones"), Some(window), DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT, &[("Cerrar", ResponseType::Close)]);
+    let grid = Grid::new();
+    let capturing: Rc<Cell<Option<ButtonAction>>> = Rc::new(Cell::new(None));
+    let labels: Rc<Vec<(ButtonAction, Label)>> = Rc::new(ButtonAction::ALL.iter().map(|&action| {
+        (action, Label::new(Some(&assigned(&bindings.borrow(), action))))
+    }).collect());
+    let refresh = clone!(@strong labels, @strong bindings => move || {
+        for (action, label) in labels.iter() {
+            label.set_text(&assigned(&bindings.borrow(), *action));
+        }
+    });
+
+    grid.set_row_spacing(6);
+    grid.set_column_spacing(12);
+    grid.set_border_width(12);
+    grid.attach(&Label::new(Some("Presiona «Asignar» y luego el botón del mouse, la pluma o la tableta")), 0, 0, 4, 1);
+
+    for (row, &(action, ref label)) in labels.iter().enumerate() {
+        let row = row as i32 + 1;
+        let assign = Button::with_label("Asignar");
+        let clear = Button::with_label("Quitar");
+
+        assign.connect_clicked(clone!(@strong capturing, @strong label => move |_btn| {
+            capturing.set(Some(action));
+            label.set_text("Presiona un botón…");
+        }));
+
+        clear.connect_clicked(clone!(@strong bindings, @strong refresh => move |_btn| {
+            bindings.borrow_mut().retain(|_, a| *a != action);
+            save(&bindings.borrow());
+            refresh();
+        }));
+
+        label.set_xalign(0.0);
+        grid.attach(&Label::new(Some(action.label())), 0, row, 1, 1);
+        grid.attach(label, 1, row, 1, 1);
+        grid.attach(&assign, 2, row, 1, 1);
+        grid.attach(&clear, 3, row, 1, 1);
+    }
+
+    dialog.add_events(EventMask::TABLET_PAD_MASK);
+    dialog.connect_event(clone!(@strong capturing, @strong bindings, @strong refresh => move |_dialog, event| {
+        let action = match capturing.get() {
+            Some(action) => action,
+            None => return Inhibit(false),
+        };
+
+        if event.event_type() == EventType::KeyPress {
+            capturing.set(None);
+            refresh();
+
+            return Inhibit(true);
+        }
+
+        match event_button_name(event) {
+            Some(name) => {
+                capturing.set(None);
+                bindings.borrow_mut().insert(name, action);
+                save(&bindings.borrow());
+                refresh();
+
+                Inhibit(true)
+            },
+            None => Inhibit(false),
+        }
+    }));
+
+    dialog.content_area().add(&grid);
+    dialog.show_all();
+    dialog.run();
+    dialog.close();
+}
+
+fn save(bindings: &Bindings) {
+    if let Err(e) = config::write_buttons(&ButtonSettings(bindings.clone())) {
+        log::error!("Could not save the buttons: {}", e);
+    }
+}
@@ -0,0 +1,131 @@
+//! Command line modes that do their work without opening a window, like
+//! `pizarra export dibujo.svg -o dibujo.png`.
+use std::path::PathBuf;
+
+use pizarra::prelude::*;
+
+use crate::config;
+use crate::logic::{open_file, export_drawing, ExportFormat, ExportPage};
+use crate::pages::Pages;
+
+const EXPORT_USAGE: &str = "Uso: pizarra export ENTRADA.svg [-o SALIDA.png] [--scale N] [--padding N]
+
+Opciones:
+    -o, --output ARCHIVO   Archivo de salida. Por defecto la entrada con extensión .png
+                           El formato depende de la extensión: png, pdf, ps o eps
+    -s, --scale N          Multiplica el tamaño de la imagen por N (por defecto 1)
+    -p, --padding N        Espacio alrededor del dibujo. Por defecto 'export_padding'
+                           de la configuración
+    -h, --help             Muestra este mensaje";
+
+/// Arguments of the `export` subcommand
+struct ExportArgs {
+    input: PathBuf,
+    output: PathBuf,
+    format: ExportFormat,
+    scale: f64,
+    padding: Option<f64>,
+}
+
+fn parse_number(flag: &str, value: Option<&String>) -> Result<f64, String> {
+    let value = value.ok_or_else(|| format!("Falta el valor de {}", flag))?;
+
+    match value.parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0.0 => Ok(n),
+        _ => Err(format!("El valor de {} debe ser un número positivo, no '{}'", flag, value)),
+    }
+}
+
+fn parse_export_args(args: &[String]) -> Result<ExportArgs, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut scale = 1.0;
+    let mut padding = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output = Some(PathBuf::from(args.next().ok_or("Falta el valor de --output")?));
+            },
+            "-s" | "--scale" => {
+                scale = parse_number(arg, args.next())?;
+
+                if scale == 0.0 {
+                    return Err("La escala no puede ser cero".into());
+                }
+            },
+            "-p" | "--padding" => {
+                padding = Some(parse_number(arg, args.next())?);
+            },
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("No conozco la opción '{}'", flag));
+            },
+            file => {
+                if input.is_some() {
+                    return Err(format!("Sobra el argumento '{}', solo puedo exportar un archivo a la vez", file));
+                }
+
+                input = Some(PathBuf::from(file));
+            },
+        }
+    }
+
+    let input = input.ok_or("Falta el archivo a exportar")?;
+    let output = output.unwrap_or_else(|| input.with_extension("png"));
+    let format = ExportFormat::from_path(&output)
+        .ok_or_else(|| format!("No sé exportar a '{}', usa una extensión png, pdf, ps o eps", output.display()))?;
+
+    Ok(ExportArgs { input, output, format, scale, padding })
+}
+
+/// Runs the `export` subcommand with the arguments that follow it and returns
+/// the exit code of the process.
+pub fn export(args: &[String]) -> i32 {
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", EXPORT_USAGE);
+
+        return 0;
+    }
+
+    let args = match parse_export_args(args) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{}\n\n{}", msg, EXPORT_USAGE);
+
+            return 2;
+        },
+    };
+
+    let config = match config::read() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+
+            return 1;
+        },
+    };
+    let mut controller = Pizarra::new(Vec2D::new_screen(1.0, 1.0), config);
+    let mut pages = Pages::new();
+
+    if let Err(e) = open_file(&mut controller, &mut pages, &args.input) {
+        eprintln!("{}: {}", args.input.display(), e);
+
+        return 1;
+    }
+
+    let padding = args.padding.map(Into::into).unwrap_or(controller.config().export_padding);
+    let others = pages.other_pages(config);
+    let all: Vec<ExportPage> = others.iter().map(|page| match page {
+        Some((other, extras)) => (other, extras),
+        None => (&controller, &pages.extras),
+    }).collect();
+
+    if let Err(e) = export_drawing(&all, &args.output, args.format, padding, args.scale) {
+        eprintln!("{}: {}", args.output.display(), e);
+
+        return 1;
+    }
+
+    0
+}
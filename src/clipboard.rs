@@ -0,0 +1,75 @@
+//! Copy and paste through the clipboard of the desktop. A board is copied both
+//! as a PNG image, for chats and documents, and as svg, which pizarra and
+//! vector editors paste as shapes.
+use gdk::Atom;
+use gtk::{Clipboard, TargetEntry, TargetFlags};
+use pizarra::prelude::*;
+
+use crate::extras::Extras;
+use crate::fill;
+use crate::images::PlacedImage;
+use crate::logic::{render_png, ExportError};
+
+const SVG_TARGET: &str = "image/svg+xml";
+const PNG_TARGET: &str = "image/png";
+
+/// What was found in the clipboard
+pub enum Pasted {
+    /// An svg document
+    Svg(String),
+    Image(PlacedImage),
+}
+
+fn clipboard() -> Clipboard {
+    Clipboard::get(&gdk::SELECTION_CLIPBOARD)
+}
+
+/// Puts a board in the clipboard as svg and as a PNG image
+pub fn copy(controller: &mut Pizarra, extras: &Extras) -> Result<(), ExportError> {
+    let padding = controller.config().export_padding;
+    let png = render_png((&*controller, extras), padding, 1.0)?;
+    let svg = extras.embed(&fill::core_svg(controller));
+    let targets = [
+        TargetEntry::new(SVG_TARGET, TargetFlags::empty(), 0),
+        TargetEntry::new(PNG_TARGET, TargetFlags::empty(), 1),
+    ];
+
+    clipboard().set_with_data(&targets, move |_clipboard, selection, info| {
+        if info == 0 {
+            selection.set(&Atom::intern(SVG_TARGET), 8, svg.as_bytes());
+        } else {
+            selection.set(&Atom::intern(PNG_TARGET), 8, &png);
+        }
+    });
+
+    Ok(())
+}
+
+/// Reads what can be pasted from the clipboard. Svg is preferred because it
+/// can be edited, then images and finally text that looks like svg.
+pub fn paste() -> Option<Pasted> {
+    let clipboard = clipboard();
+
+    if let Some(data) = clipboard.wait_for_contents(&Atom::intern(SVG_TARGET)) {
+        if let Ok(svg) = String::from_utf8(data.data()) {
+            return Some(Pasted::Svg(svg));
+        }
+    }
+
+    if let Some(pixbuf) = clipboard.wait_for_image() {
+        let image = pixbuf.save_to_bufferv("png", &[]).ok().and_then(|png| PlacedImage::from_bytes(png).ok());
+
+        if let Some(image) = image {
+            return Some(Pasted::Image(image));
+        }
+    }
+
+    let text = clipboard.wait_for_text()?;
+    let trimmed = text.trim_start();
+
+    if trimmed.starts_with("<svg") || trimmed.starts_with("<?xml") {
+        Some(Pasted::Svg(text.into()))
+    } else {
+        None
+    }
+}
@@ -1,63 +1,430 @@
 use std::env;
 use std::path::{Path, PathBuf};
-use std::fs::{File, create_dir_all};
-use std::io::{Read, Write};
+use std::fs::{self, File, create_dir_all};
+use std::io::{self, Write};
+use std::collections::BTreeMap;
 
 use directories::ProjectDirs;
+use serde::{Serialize, Deserialize};
 use pizarra::config::Config;
 
-/// Tries as hard as possible to read the current configuration. Retrieving
-/// the path to it from the environment or common locations.
-pub fn read() -> Config {
-    if let Ok(value) = env::var("PIZARRA_CONFIG") {
-        let config_path = PathBuf::from(value);
+use crate::buttons::{Bindings, ButtonAction};
+use crate::shortcuts;
+
+/// Settings of the autosave feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutosaveSettings {
+    pub enabled: bool,
+
+    /// Seconds between each copy of the document
+    pub interval: u32,
+}
 
-        return if config_path.is_file() {
-            read_from_toml(config_path)
-        } else {
-            create_and_return_config(&config_path)
-        };
+impl Default for AutosaveSettings {
+    fn default() -> AutosaveSettings {
+        AutosaveSettings {
+            enabled: true,
+            interval: 60,
+        }
+    }
+}
+
+/// Settings of the list of recent files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecentFilesSettings {
+    /// How many files are shown in the _open recent_ menu
+    pub length: usize,
+}
+
+impl Default for RecentFilesSettings {
+    fn default() -> RecentFilesSettings {
+        RecentFilesSettings {
+            length: 10,
+        }
+    }
+}
+
+/// Settings of the strokes drawn with a pen that reports pressure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PressureSettings {
+    /// With `false` the pen draws with a constant width like the mouse
+    pub enabled: bool,
+
+    /// Points `[pressure, factor]` of the curve that maps the pressure of the
+    /// pen, from 0 to 1, to a factor of the selected thickness. Pressures in
+    /// between are interpolated linearly.
+    pub curve: Vec<[f64; 2]>,
+
+    /// How much wider the stroke gets when the pen is fully tilted, 0 to
+    /// ignore the tilt
+    pub tilt: f64,
+}
+
+impl Default for PressureSettings {
+    fn default() -> PressureSettings {
+        PressureSettings {
+            enabled: true,
+            curve: vec![[0.0, 0.2], [0.5, 1.0], [1.0, 1.6]],
+            tilt: 0.0,
+        }
+    }
+}
+
+/// Settings of the touchscreen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TouchSettings {
+    /// With `false` a single finger pans the view instead of drawing. Two
+    /// fingers always zoom, pan and rotate.
+    pub draw_with_finger: bool,
+
+    /// Ignore the fingers and the mouse while a pen is near the screen, so
+    /// the hand can rest on it while writing
+    pub palm_rejection: bool,
+}
+
+impl Default for TouchSettings {
+    fn default() -> TouchSettings {
+        TouchSettings {
+            draw_with_finger: true,
+            palm_rejection: true,
+        }
+    }
+}
+
+/// Settings of the laser pointer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaserSettings {
+    /// Seconds the trail takes to fade away
+    pub fade: f64,
+
+    /// Color of the trail as `[red, green, blue]`, each from 0 to 1
+    pub color: [f64; 3],
+}
+
+impl Default for LaserSettings {
+    fn default() -> LaserSettings {
+        LaserSettings {
+            fade: 1.0,
+            color: [1.0, 0.1, 0.1],
+        }
+    }
+}
+
+/// Settings of the color palette of the header bar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaletteSettings {
+    /// Colors of the palette as `[red, green, blue]`, each from 0 to 1. The
+    /// first nine are also chosen with the keys `1` to `9`.
+    pub colors: Vec<[f64; 3]>,
+
+    /// How many of the last chosen colors are shown next to the palette, 0
+    /// to hide them
+    pub recent: usize,
+}
+
+impl Default for PaletteSettings {
+    fn default() -> PaletteSettings {
+        PaletteSettings {
+            colors: vec![
+                [1.0, 1.0, 1.0],
+                [0.0, 0.0, 0.0],
+                [0.8, 0.0, 0.0],
+                [0.0, 0.4, 0.8],
+                [0.0, 0.6, 0.2],
+                [1.0, 0.6, 0.0],
+                [0.6, 0.2, 0.8],
+                [1.0, 0.9, 0.0],
+            ],
+            recent: 5,
+        }
+    }
+}
+
+/// Actions assigned to the extra buttons of mice, pens and tablet pads, like
+/// `stylus-2 = "pan"`. See [`crate::buttons`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ButtonSettings(pub Bindings);
+
+impl Default for ButtonSettings {
+    fn default() -> ButtonSettings {
+        ButtonSettings(Bindings::from([
+            ("mouse-8".into(), ButtonAction::Undo),
+            ("mouse-9".into(), ButtonAction::Redo),
+        ]))
+    }
+}
+
+/// Keys of the actions of the menus and tools, like `undo = "<Control>z"`.
+/// See [`crate::shortcuts`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ShortcutSettings(pub BTreeMap<String, String>);
+
+impl Default for ShortcutSettings {
+    fn default() -> ShortcutSettings {
+        ShortcutSettings(shortcuts::defaults())
+    }
+}
+
+/// Settings that only concern this frontend. They live in the same file as
+/// the ones of the pizarra core, each group in its own table.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub autosave: AutosaveSettings,
+    pub recent_files: RecentFilesSettings,
+    pub pressure: PressureSettings,
+    pub touch: TouchSettings,
+    pub laser: LaserSettings,
+    pub palette: PaletteSettings,
+    pub buttons: ButtonSettings,
+    pub shortcuts: ShortcutSettings,
+}
+
+fn project_dirs() -> ProjectDirs {
+    ProjectDirs::from("tk", "categulario", "pizarra").expect("Could not determine project dirs for your platform")
+}
+
+/// Tries as hard as possible to find the path to the configuration file,
+/// retrieving it from the environment or common locations. The file may not
+/// exist.
+fn location() -> PathBuf {
+    if let Ok(value) = env::var("PIZARRA_CONFIG") {
+        return PathBuf::from(value);
     }
 
     // Next try from some known directories
-    if let Some(project_dirs) = ProjectDirs::from("tk", "categulario", "pizarra") {
-        let config_filename = {
-            let mut conf = project_dirs.config_dir().to_owned();
-            conf.push("config.toml");
-            conf
-        };
-
-        if config_filename.is_file() {
-            read_from_toml(config_filename)
-        } else {
-            let config_dir = project_dirs.config_dir();
+    let mut conf = project_dirs().config_dir().to_owned();
+
+    conf.push("config.toml");
 
+    conf
+}
+
+/// Path to the configuration file. If the file does not exist it is created
+/// with the default configuration.
+fn path() -> PathBuf {
+    let config_filename = location();
+
+    if !config_filename.is_file() {
+        if let Some(config_dir) = config_filename.parent() {
             create_dir_all(config_dir).unwrap();
-            create_and_return_config(&config_filename)
         }
-    } else {
-        panic!("Could not determine project dirs for your platform")
+
+        create_default_config(&config_filename);
     }
+
+    config_filename
+}
+
+/// Reads the configuration of the pizarra core, or its defaults if there's
+/// no configuration file. Unlike the settings it doesn't create the file, so
+/// the command line leaves no trace. Meant to be read once and passed to
+/// every controller.
+pub fn read() -> Result<Config, String> {
+    let path = location();
+
+    if !path.is_file() {
+        return Ok(Config::default());
+    }
+
+    read_toml(&path)?.try_into().map_err(|e| format!("La configuración de {} tiene errores: {}", path.display(), e))
+}
+
+/// Reads the settings of this frontend and describes the problems found in
+/// them. A section that can't be read is left with its defaults, and so is
+/// everything if the file can't be read at all.
+pub fn read_settings() -> (Settings, Vec<String>) {
+    let mut problems = Vec::new();
+    let config = read_toml(&path()).unwrap_or_else(|e| {
+        problems.push(e);
+        toml::Value::Table(Default::default())
+    });
+    let settings = Settings {
+        autosave: section(&config, "autosave", &mut problems),
+        recent_files: section(&config, "recent_files", &mut problems),
+        pressure: section(&config, "pressure", &mut problems),
+        touch: section(&config, "touch", &mut problems),
+        laser: section(&config, "laser", &mut problems),
+        palette: section(&config, "palette", &mut problems),
+        buttons: section(&config, "buttons", &mut problems),
+        shortcuts: section(&config, "shortcuts", &mut problems),
+    };
+
+    (settings, problems)
+}
+
+/// Reads a section of the settings, or its defaults if it is missing or has
+/// errors, in which case they are added to `problems`.
+fn section<T: Default + for<'de> Deserialize<'de>>(config: &toml::Value, name: &str, problems: &mut Vec<String>) -> T {
+    match config.get(name).cloned().map(toml::Value::try_into) {
+        Some(Ok(section)) => section,
+        Some(Err(e)) => {
+            problems.push(format!("La sección [{}] tiene errores y se usa la de por omisión: {}", name, e));
+
+            T::default()
+        },
+        None => T::default(),
+    }
+}
+
+/// The name of the table that the line opens, if it is a header like
+/// `[palette]`, `[palette.extra]` or `[[palette]]`
+fn table_header(line: &str) -> Option<&str> {
+    let line = line.split('#').next()?.trim();
+    let name = line.strip_prefix('[')?.strip_suffix(']')?;
+
+    Some(name.trim_start_matches('[').trim_end_matches(']').trim())
+}
+
+/// Puts `section`, the text of the table `name`, in place of the lines of
+/// that table in `contents`, or at the end if there is none. The rest of the
+/// text is left untouched. The comments and blank lines right before the next
+/// table are left too, as they tell about that table.
+fn replace_section(contents: &str, name: &str, section: &str) -> String {
+    let lines: Vec<&str> = contents.lines().collect();
+    let own = |header: &str| header == name || header.starts_with(&format!("{}.", name));
+    let start = match lines.iter().position(|line| table_header(line).map(own).unwrap_or(false)) {
+        Some(start) => start,
+        None if contents.trim().is_empty() => return section.into(),
+        None => return format!("{}\n\n{}", contents.trim_end(), section),
+    };
+    let mut end = lines[start + 1..].iter()
+        .position(|line| table_header(line).map(|header| !own(header)).unwrap_or(false))
+        .map(|i| start + 1 + i)
+        .unwrap_or(lines.len());
+
+    while end > start + 1 && (lines[end - 1].trim().is_empty() || lines[end - 1].trim_start().starts_with('#')) {
+        end -= 1;
+    }
+
+    let mut replaced: Vec<&str> = lines[..start].to_vec();
+
+    replaced.extend(section.lines());
+
+    if end < lines.len() {
+        replaced.push("");
+        replaced.extend(lines[end..].iter().skip_while(|line| line.trim().is_empty()));
+    }
+
+    replaced.join("\n") + "\n"
 }
 
-fn read_from_toml<P: AsRef<Path>>(path: P) -> Config {
-    let path: PathBuf = path.as_ref().into();
+/// Writes a file through a temporary one next to it, so if writing fails the
+/// file is left as it was
+fn write_replacing(path: &Path, contents: &str) -> io::Result<()> {
+    // the file may be a link to where the user keeps it
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    let mut temporary = path.clone().into_os_string();
 
-    let mut contents = String::new();
-    let mut file = File::open(&path).unwrap();
+    temporary.push(".tmp");
 
-    file.read_to_string(&mut contents).unwrap();
+    let mut file = File::create(&temporary)?;
 
-    toml::from_str(&contents).unwrap()
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temporary, &path)
 }
 
-/// Assume the configuration file does not exist, create a default one and
-/// return it.
-fn create_and_return_config(config_filename: &Path) -> Config {
-    let config = Config::default();
+/// Replaces a section of the configuration file. Only the lines of that
+/// section change, the comments and the order of the rest are kept.
+fn write_section<T: Serialize>(name: &str, section: &T) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let path = path();
+    let contents = fs::read_to_string(&path)?;
+    let value = toml::Value::try_from(section).map_err(|e| invalid(e.to_string()))?;
+    let table = toml::to_string(&BTreeMap::from([(name, &value)])).map_err(|e| invalid(e.to_string()))?;
+    let replaced = replace_section(&contents, name, &table);
+
+    // a section written in an unusual way, like an inline table, can't be
+    // replaced by its lines
+    match toml::from_str::<toml::Value>(&replaced) {
+        Ok(config) if config.get(name) == Some(&value) => write_replacing(&path, &replaced),
+        _ => Err(invalid(format!("No pude cambiar la sección [{}] de {} sin dañarla, cámbiala a mano", name, path.display()))),
+    }
+}
+
+/// Replaces the `[buttons]` section of the configuration file
+pub fn write_buttons(buttons: &ButtonSettings) -> io::Result<()> {
+    write_section("buttons", buttons)
+}
+
+/// Replaces the `[palette]` section of the configuration file, like after
+/// importing a palette
+pub fn write_palette(palette: &PaletteSettings) -> io::Result<()> {
+    write_section("palette", palette)
+}
+
+/// Directory where the application keeps its data, like recovery files. It
+/// is created if it doesn't exist.
+pub fn data_dir() -> PathBuf {
+    let data_dir = project_dirs().data_dir().to_owned();
+
+    create_dir_all(&data_dir).unwrap();
+
+    data_dir
+}
+
+/// Reads and parses the configuration file, describing what went wrong if
+/// that's not possible
+fn read_toml(path: &Path) -> Result<toml::Value, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("No pude leer {}: {}", path.display(), e))?;
+
+    toml::from_str(&contents).map_err(|e| format!("{} no es un archivo TOML válido: {}", path.display(), e))
+}
+
+/// Assume the configuration file does not exist and create a default one.
+fn create_default_config(config_filename: &Path) {
+    let mut config = toml::Value::try_from(Config::default()).unwrap();
+
+    if let (Some(table), toml::Value::Table(settings)) = (config.as_table_mut(), toml::Value::try_from(Settings::default()).unwrap()) {
+        table.extend(settings);
+    }
+
     let mut config_file = File::create(config_filename).unwrap();
 
     config_file.write_all(toml::to_string(&config).unwrap().as_bytes()).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTION: &str = "[palette]\nrecent = 3\n";
 
-    config
+    #[test]
+    fn only_the_section_is_replaced() {
+        let contents = "# my settings\nscroll_factor = 2.0\n\n[palette]\n# old colors\nrecent = 5\n\n# the pen\n[pressure]\nenabled = false\n";
+
+        assert_eq!(
+            replace_section(contents, "palette", SECTION),
+            "# my settings\nscroll_factor = 2.0\n\n[palette]\nrecent = 3\n\n# the pen\n[pressure]\nenabled = false\n",
+        );
+    }
+
+    #[test]
+    fn subtables_belong_to_the_section() {
+        let contents = "[palette]\nrecent = 5\n[palette.extra]\nx = 1\n[touch]\npalm_rejection = false\n";
+
+        assert_eq!(
+            replace_section(contents, "palette", SECTION),
+            "[palette]\nrecent = 3\n\n[touch]\npalm_rejection = false\n",
+        );
+    }
+
+    #[test]
+    fn a_missing_section_goes_at_the_end() {
+        assert_eq!(
+            replace_section("# my settings\nscroll_factor = 2.0\n", "palette", SECTION),
+            "# my settings\nscroll_factor = 2.0\n\n[palette]\nrecent = 3\n",
+        );
+        assert_eq!(replace_section("", "palette", SECTION), SECTION);
+    }
 }
@@ -0,0 +1,142 @@
+//! Finds out why a page could not be opened. The pizarra core only tells that
+//! an svg could not be read, so here the svg is checked first as xml and then
+//! element by element, which also tells what part of it can be saved.
+use std::fmt;
+use std::ops::Range;
+
+use pizarra::config::Config;
+use pizarra::prelude::*;
+use roxmltree::{Document, Node};
+
+/// Elements that are kept in every attempt because they are not shapes
+const NOT_SHAPES: &[&str] = &["style", "title", "desc", "metadata"];
+
+/// Something in a page that the core could not understand
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// Name of the element, empty if the problem is in the xml itself
+    pub element: String,
+    pub line: u32,
+    pub column: u32,
+    pub reason: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "línea {}, columna {}: ", self.line, self.column)?;
+
+        if !self.element.is_empty() {
+            write!(f, "<{}> ", self.element)?;
+        }
+
+        write!(f, "{}", self.reason)
+    }
+}
+
+/// What was found in a page that failed to open
+#[derive(Debug)]
+pub struct Diagnosis {
+    pub problems: Vec<Problem>,
+
+    /// The page without the elements that failed, if that is enough to open
+    /// it
+    pub salvaged: Option<String>,
+}
+
+fn problem(doc: &Document, node: Node, reason: String) -> Problem {
+    let pos = doc.text_pos_at(node.range().start);
+
+    Problem {
+        element: node.tag_name().name().into(),
+        line: pos.row,
+        column: pos.col,
+        reason,
+    }
+}
+
+/// The page with only the shapes for which `include` returns `true`
+fn rebuild(svg: &str, shapes: &[Range<usize>], include: impl Fn(usize) -> bool) -> String {
+    let mut page = String::with_capacity(svg.len());
+    let mut last = 0;
+
+    for (i, range) in shapes.iter().enumerate() {
+        page.push_str(&svg[last..range.start]);
+
+        if include(i) {
+            page.push_str(&svg[range.clone()]);
+        }
+
+        last = range.end;
+    }
+
+    page.push_str(&svg[last..]);
+
+    page
+}
+
+/// Looks for the parts of the page that prevent it from opening, trying them
+/// in a controller with the given configuration
+pub fn diagnose(svg: &str, config: Config) -> Diagnosis {
+    let doc = match Document::parse(svg) {
+        Ok(doc) => doc,
+        Err(e) => {
+            let pos = e.pos();
+
+            return Diagnosis {
+                problems: vec![Problem {
+                    element: String::new(),
+                    line: pos.row,
+                    column: pos.col,
+                    reason: e.to_string(),
+                }],
+                salvaged: None,
+            };
+        },
+    };
+
+    let root = doc.root_element();
+    let mut scratch = Pizarra::new(Vec2D::new_screen(1.0, 1.0), config);
+
+    // The elements without children are tried one at a time inside what is
+    // left of the document when all of them are removed
+    let shapes: Vec<Node> = root.descendants()
+        .filter(|n| n.is_element() && *n != root)
+        .filter(|n| !n.children().any(|c| c.is_element()))
+        .filter(|n| !NOT_SHAPES.contains(&n.tag_name().name()))
+        .collect();
+    let ranges: Vec<_> = shapes.iter().map(|n| n.range()).collect();
+
+    if let Err(e) = scratch.open(&rebuild(svg, &ranges, |_| false)) {
+        return Diagnosis {
+            problems: vec![problem(&doc, root, format!("{:?}", e))],
+            salvaged: None,
+        };
+    }
+
+    let mut failed = Vec::new();
+    let mut problems = Vec::new();
+
+    for (i, node) in shapes.iter().enumerate() {
+        if let Err(e) = scratch.open(&rebuild(svg, &ranges, |j| j == i)) {
+            failed.push(i);
+            problems.push(problem(&doc, *node, format!("{:?}", e)));
+        }
+    }
+
+    let salvaged = rebuild(svg, &ranges, |i| !failed.contains(&i));
+
+    match scratch.open(&salvaged) {
+        Ok(_) if !problems.is_empty() => Diagnosis { problems, salvaged: Some(salvaged) },
+        // every shape is fine alone but not all together, or they are fine
+        // together but the whole page is not. Only the core knows why.
+        Ok(_) => Diagnosis {
+            problems: vec![problem(&doc, root, "el documento no se puede interpretar aunque cada elemento por separado sí".into())],
+            salvaged: None,
+        },
+        Err(e) => {
+            problems.push(problem(&doc, root, format!("{:?}", e)));
+
+            Diagnosis { problems, salvaged: None }
+        },
+    }
+}
@@ -0,0 +1,222 @@
+//! What a page holds besides the shapes of the pizarra core: images, strokes
+//! drawn with pressure or the highlighter and text. They live next to each
+//! page, are drawn with it and travel in its svg as elements marked with a
+//! `data-pizarra-*` attribute, which are taken out before the svg reaches the
+//! core and put back when the page is stored. The layers that are not being
+//! drawn on travel with them, see [`crate::layers`].
+use cairo::{Context, Matrix};
+use pizarra::config::Config;
+use pizarra::prelude::*;
+
+use crate::graphics::Drawable;
+use crate::images::{self, PlacedImage};
+use crate::layers::{self, Layers};
+use crate::ink::{self, InkStroke};
+use crate::highlight::{self, HighlightStroke};
+use crate::text::{self, TextBox};
+use crate::selection::CoreSvg;
+
+#[derive(Debug, Clone, Default)]
+pub struct Extras {
+    /// Drawn beneath the shapes of the core
+    pub images: Vec<PlacedImage>,
+
+    /// Drawn above the shapes of the core
+    pub ink: Vec<InkStroke>,
+
+    /// Drawn above the shapes of the core, beneath the pressure strokes
+    pub highlights: Vec<HighlightStroke>,
+
+    /// Drawn above everything else
+    pub texts: Vec<TextBox>,
+
+    /// Layers of the page. The shapes of the core and the rest of the extras
+    /// are the ones of the active layer.
+    pub layers: Layers,
+}
+
+/// Takes out of the svg the elements that start with `marker`. Their
+/// attributes must not contain a `>` and the ones that are not self closing
+/// must not contain elements of their own kind.
+fn take(svg: &str, marker: &str) -> (String, Vec<String>) {
+    let mut rest = svg;
+    let mut stripped = String::with_capacity(svg.len());
+    let mut elements = Vec::new();
+    let name = marker[1..].split(' ').next().unwrap_or_default();
+    let closing = format!("</{}>", name);
+
+    while let Some(start) = rest.find(marker) {
+        let end = match rest[start..].find('>') {
+            Some(tag_end) if rest[start..start + tag_end].ends_with('/') => start + tag_end + 1,
+            Some(tag_end) => match rest[start + tag_end..].find(&closing) {
+                Some(end) => start + tag_end + end + closing.len(),
+                None => break,
+            },
+            None => break,
+        };
+
+        stripped.push_str(&rest[..start]);
+        elements.push(rest[start..end].to_owned());
+        rest = &rest[end..];
+    }
+
+    stripped.push_str(rest);
+
+    (stripped, elements)
+}
+
+/// Puts `beneath` right after the opening tag of the svg and `above` right
+/// before its closing tag
+fn insert(svg: &str, beneath: &str, above: &str) -> String {
+    let tag_end = svg.find("<svg").and_then(|start| svg[start..].find('>').map(|end| start + end));
+
+    match (tag_end, svg.rfind("</svg>")) {
+        // an empty drawing may be a self closing `<svg/>`
+        (Some(end), _) if svg[..end].ends_with('/') => format!("{}>{}{}\n</svg>{}", &svg[..end - 1], beneath, above, &svg[end + 1..]),
+        (Some(end), Some(close)) if close > end => format!("{}{}{}{}{}", &svg[..=end], beneath, &svg[end + 1..close], above, &svg[close..]),
+        _ => svg.into(),
+    }
+}
+
+impl Extras {
+    /// Takes the extras out of the svg of a page, leaving what the core reads.
+    /// The layers that are not active are read with the given configuration.
+    pub fn split(svg: &str, config: Config) -> (String, Extras) {
+        let (mut stripped, groups) = take(svg, layers::MARKER);
+        let (layers, content) = Layers::from_groups(&groups, config);
+
+        // the contents of the active layer go where the layers were
+        if let Some(at) = svg.find(layers::MARKER) {
+            stripped.insert_str(at, &content);
+        }
+
+        let (svg, images) = take(&stripped, images::MARKER);
+        let (svg, ink) = take(&svg, ink::MARKER);
+        let (svg, highlights) = take(&svg, highlight::MARKER);
+        let (svg, texts) = take(&svg, text::MARKER);
+
+        (svg, Extras {
+            images: images.iter().filter_map(|e| PlacedImage::from_svg(e)).collect(),
+            ink: ink.iter().filter_map(|e| InkStroke::from_svg(e)).collect(),
+            highlights: highlights.iter().filter_map(|e| HighlightStroke::from_svg(e)).collect(),
+            texts: texts.iter().filter_map(|e| TextBox::from_svg(e)).collect(),
+            layers,
+        })
+    }
+
+    /// The elements of the extras that go beneath the shapes of the core and
+    /// the ones that go above them
+    pub fn elements(&self) -> (String, String) {
+        let beneath = self.images.iter().map(|image| format!("\n{}", image.to_svg())).collect();
+        let above = self.highlights.iter().map(HighlightStroke::to_svg)
+            .chain(self.ink.iter().map(InkStroke::to_svg))
+            .chain(self.texts.iter().filter(|text| !text.text.is_empty()).map(TextBox::to_svg))
+            .map(|element| format!("\n{}", element))
+            .collect();
+
+        (beneath, above)
+    }
+
+    /// Puts the extras in the svg of a page: images right after the opening
+    /// tag so they are beneath the strokes and the rest at the end. If the
+    /// page has layers each one is a group that holds its shapes and extras,
+    /// in the place of the shapes of the core.
+    pub fn embed(&self, svg: &str) -> String {
+        if !self.layers.is_plain() {
+            let groups = format!("\n{}", self.layers.to_svg(&layers::active_content(svg, self)));
+
+            return match CoreSvg::parse(svg) {
+                Some(core) => CoreSvg { shapes: vec![groups], ..core }.to_svg(),
+                None => insert(svg, "", &groups),
+            };
+        }
+
+        if self.images.is_empty() && self.ink.is_empty() && self.highlights.is_empty() && self.texts.is_empty() {
+            return svg.into();
+        }
+
+        let (beneath, above) = self.elements();
+
+        insert(svg, &beneath, &above)
+    }
+
+    pub fn bounds(&self) -> impl Iterator<Item=[Vec2D<WorldUnit>; 2]> + '_ {
+        self.images.iter().map(PlacedImage::bounds)
+            .chain(self.ink.iter().filter_map(InkStroke::bounds))
+            .chain(self.highlights.iter().filter_map(HighlightStroke::bounds))
+            .chain(self.texts.iter().filter_map(TextBox::bounds))
+    }
+
+    /// Moves everything by the given amount of world units
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        for image in self.images.iter_mut() {
+            image.x += dx;
+            image.y += dy;
+        }
+
+        for stroke in self.ink.iter_mut() {
+            stroke.translate(dx, dy);
+        }
+
+        for stroke in self.highlights.iter_mut() {
+            stroke.translate(dx, dy);
+        }
+
+        for text in self.texts.iter_mut() {
+            text.translate(dx, dy);
+        }
+    }
+
+    /// Adds the extras of another page, like pasted ones
+    pub fn append(&mut self, other: &mut Extras) {
+        self.images.append(&mut other.images);
+        self.ink.append(&mut other.ink);
+        self.highlights.append(&mut other.highlights);
+        self.texts.append(&mut other.texts);
+    }
+
+    /// Draws what goes beneath the shapes of the core, with the transform of
+    /// the page.
+    pub fn draw_beneath(&self, ctx: &Context, t: Transform) {
+        ctx.save().unwrap();
+        ctx.transform(Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0));
+
+        for image in self.images.iter() {
+            image.draw(ctx);
+        }
+
+        ctx.restore().unwrap();
+    }
+
+    /// Draws what goes above the shapes of the core
+    pub fn draw_above(&self, ctx: &Context, t: Transform) {
+        for stroke in self.highlights.iter() {
+            stroke.draw(ctx, t);
+        }
+
+        for stroke in self.ink.iter() {
+            stroke.draw(ctx, t);
+        }
+
+        for text in self.texts.iter() {
+            text.draw(ctx, t);
+        }
+    }
+}
+
+/// Bounds of the shapes and extras of a page together, leaving out the hidden
+/// layers
+pub fn page_bounds(controller: &Pizarra, extras: &Extras) -> Option<[Vec2D<WorldUnit>; 2]> {
+    let active: Vec<_> = if extras.layers.active_visible() {
+        controller.get_bounds().into_iter().chain(extras.bounds()).collect()
+    } else {
+        Vec::new()
+    };
+
+    active.into_iter().chain(extras.layers.bounds()).reduce(|[a1, a2], [b1, b2]| {
+        [
+            Vec2D::new_world(a1.x.val().min(b1.x.val()), a1.y.val().min(b1.y.val())),
+            Vec2D::new_world(a2.x.val().max(b2.x.val()), a2.y.val().max(b2.y.val())),
+        ]
+    })
+}
@@ -0,0 +1,67 @@
+//! Fill of closed shapes. The core draws and reads back shapes with a fill
+//! but has no way to give one to the shapes being drawn, so when one of the
+//! closed tools finishes a shape the page gets the fill here, see
+//! [`crate::pages::Pages::fill_last_shape`].
+//!
+//! The core also writes the fill without its alpha, so the svg of the core is
+//! always taken through [`core_svg`], which puts it back.
+use pizarra::prelude::*;
+use pizarra::draw_commands::DrawCommand;
+use serde::{Serialize, Deserialize};
+
+use crate::selection::CoreSvg;
+
+/// Fill of the closed shapes, kept apart from the color of their stroke
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fill {
+    /// Shapes are drawn without fill if `false`
+    pub enabled: bool,
+
+    pub color: [f64; 3],
+    pub alpha: u8,
+}
+
+impl Default for Fill {
+    fn default() -> Fill {
+        Fill {
+            enabled: false,
+            color: [0.8, 0.8, 0.8],
+            alpha: 255,
+        }
+    }
+}
+
+impl Fill {
+    /// The color of new shapes, if they have a fill
+    pub fn color(&self) -> Option<Color> {
+        let [r, g, b] = self.color;
+
+        Some(Color::from_float_rgb(r, g, b).with_alpha(self.alpha)).filter(|_| self.enabled)
+    }
+}
+
+/// The svg of the core with the alpha of the fills, which it leaves out
+pub fn core_svg(controller: &Pizarra) -> String {
+    let svg = controller.to_svg();
+    let mut core = match CoreSvg::parse(&svg) {
+        Some(core) => core,
+        None => return svg,
+    };
+    let fills = controller.draw_commands_for_drawing().into_iter().map(|command| match command {
+        DrawCommand::Path { style, .. } => style.fill,
+        DrawCommand::Ellipse { style, .. } => style.fill,
+        _ => None,
+    });
+
+    // the core writes the shapes in the same order it draws them
+    for (shape, fill) in core.shapes.iter_mut().zip(fills) {
+        if let Some(color) = fill.filter(|color| color.alpha() < 255) {
+            let css = format!("fill:{};", color.css());
+
+            *shape = shape.replacen(&css, &format!("{}fill-opacity:{};", css, color.float_alpha()), 1);
+        }
+    }
+
+    core.to_svg()
+}
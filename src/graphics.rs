@@ -1,6 +1,7 @@
 use std::f64::consts::PI;
+use std::time::Instant;
 
-use cairo::{Context, LineCap, LineJoin, Matrix};
+use cairo::{Context, LineCap, LineJoin, Matrix, Operator};
 
 use pizarra::{
     draw_commands::DrawCommand, transform::Transform,
@@ -8,6 +9,16 @@ use pizarra::{
     point::{Vec2D, Unit, WorldUnit}, style::Style, geom::Ellipse,
 };
 
+use crate::ink::InkStroke;
+use crate::highlight::HighlightStroke;
+use crate::text::TextBox;
+use crate::laser::Laser;
+
+/// Width in pixels of the bright center of the laser trail and of the glow
+/// around it
+const LASER_WIDTH: f64 = 3.0;
+const LASER_GLOW: f64 = 14.0;
+
 fn draw_path<T: Unit>(ctx: &Context, commands: &[PathCommand<T>], style: Style<T>) {
     for point in commands.iter() {
         match *point {
@@ -120,3 +131,101 @@ impl Drawable for DrawCommand {
         }
     }
 }
+
+impl Drawable for InkStroke {
+    fn draw(&self, ctx: &Context, t: Transform) {
+        let outline = self.outline();
+        let color = self.color;
+
+        ctx.save().unwrap();
+        ctx.transform(Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0));
+
+        for (i, &(x, y)) in outline.iter().enumerate() {
+            if i == 0 {
+                ctx.move_to(x, y);
+            } else {
+                ctx.line_to(x, y);
+            }
+        }
+
+        ctx.close_path();
+        ctx.set_source_rgba(color.float_r(), color.float_g(), color.float_b(), color.float_alpha());
+        ctx.fill().unwrap();
+        ctx.restore().unwrap();
+    }
+}
+
+impl Drawable for HighlightStroke {
+    /// Multiplies the stroke with what is beneath it. The whole stroke is one
+    /// path so it doesn't get darker where it crosses itself.
+    fn draw(&self, ctx: &Context, t: Transform) {
+        let color = self.color;
+
+        ctx.save().unwrap();
+        ctx.transform(Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0));
+
+        for (i, &(x, y)) in self.points.iter().enumerate() {
+            if i == 0 {
+                ctx.move_to(x, y);
+            } else {
+                ctx.line_to(x, y);
+            }
+        }
+
+        ctx.set_operator(Operator::Multiply);
+        ctx.set_line_cap(LineCap::Butt);
+        ctx.set_line_join(LineJoin::Round);
+        ctx.set_line_width(self.width);
+        ctx.set_source_rgba(color.float_r(), color.float_g(), color.float_b(), color.float_alpha());
+        ctx.stroke().unwrap();
+        ctx.restore().unwrap();
+    }
+}
+
+impl Drawable for TextBox {
+    fn draw(&self, ctx: &Context, t: Transform) {
+        let color = self.color;
+
+        ctx.save().unwrap();
+        ctx.transform(Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0));
+
+        // laid out after the transform so Pango hints for the real size
+        let layout = self.layout(ctx);
+
+        ctx.move_to(self.x, self.y);
+        ctx.set_source_rgba(color.float_r(), color.float_g(), color.float_b(), color.float_alpha());
+        pangocairo::show_layout(ctx, &layout);
+        ctx.restore().unwrap();
+    }
+}
+
+impl Drawable for Laser {
+    /// Draws the trails in pixels, so they look the same at any zoom. Each
+    /// segment fades with the oldest of its points.
+    fn draw(&self, ctx: &Context, t: Transform) {
+        let now = Instant::now();
+        let matrix = Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0);
+        let [r, g, b] = self.settings.color;
+
+        ctx.save().unwrap();
+        ctx.set_line_cap(LineCap::Round);
+
+        for (width, alpha) in [(LASER_GLOW, 0.25), (LASER_WIDTH, 1.0)] {
+            ctx.set_line_width(width);
+
+            for trail in self.trails.iter() {
+                for pair in trail.windows(2) {
+                    let (x1, y1) = matrix.transform_point(pair[0].x, pair[0].y);
+                    let (x2, y2) = matrix.transform_point(pair[1].x, pair[1].y);
+
+                    ctx.move_to(x1, y1);
+                    ctx.line_to(x2, y2);
+                    ctx.set_source_rgba(r, g, b, alpha * self.remaining(&pair[0], now));
+                    ctx.stroke().unwrap();
+                }
+            }
+        }
+
+        ctx.restore().unwrap();
+    }
+}
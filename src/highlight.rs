@@ -0,0 +1,131 @@
+//! The highlighter. Its strokes are wide, have flat ends and are multiplied
+//! with what is beneath them, so text and lines under them keep their color
+//! and overlapping strokes don't get darker than the highlighter. The core
+//! can't blend strokes like that, so they are drawn by this frontend like the
+//! pressure strokes.
+//!
+//! In files they are `<path>` elements with `mix-blend-mode: multiply`, so
+//! other programs show them the same way.
+use pizarra::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::ink::{hex_color, parse_hex_color};
+use crate::selection::Restyle;
+
+/// Marks the `<path>` elements written by us
+pub const MARKER: &str = "<path data-pizarra-highlight=";
+
+/// Color and width of the highlighter, kept apart from the ones of the other
+/// tools
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Highlighter {
+    pub color: [f64; 3],
+    pub alpha: u8,
+
+    /// Width of the strokes in world units
+    pub width: f64,
+}
+
+impl Default for Highlighter {
+    fn default() -> Highlighter {
+        Highlighter {
+            color: [1.0, 0.9, 0.2],
+            alpha: 255,
+            width: 16.0,
+        }
+    }
+}
+
+impl Highlighter {
+    pub fn color(&self) -> Color {
+        let [r, g, b] = self.color;
+
+        Color::from_float_rgb(r, g, b).with_alpha(self.alpha)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HighlightStroke {
+    /// Points in world coordinates
+    pub points: Vec<(f64, f64)>,
+    pub color: Color,
+    pub width: f64,
+}
+
+impl HighlightStroke {
+    pub fn new(highlighter: &Highlighter) -> HighlightStroke {
+        HighlightStroke {
+            points: Vec::new(),
+            color: highlighter.color(),
+            width: highlighter.width,
+        }
+    }
+
+    pub fn push(&mut self, point: (f64, f64)) {
+        if self.points.last() != Some(&point) {
+            self.points.push(point);
+        }
+    }
+
+    pub fn bounds(&self) -> Option<[Vec2D<WorldUnit>; 2]> {
+        let half = self.width / 2.0;
+        let &(x, y) = self.points.first()?;
+        let (x1, y1, x2, y2) = self.points.iter().fold((x, y, x, y), |(x1, y1, x2, y2), &(x, y)| {
+            (x1.min(x), y1.min(y), x2.max(x), y2.max(y))
+        });
+
+        Some([Vec2D::new_world(x1 - half, y1 - half), Vec2D::new_world(x2 + half, y2 + half)])
+    }
+
+    /// Tells if the stroke passes within `radius` of the given point
+    pub fn touches(&self, (x, y): (f64, f64), radius: f64) -> bool {
+        self.points.iter().any(|&(px, py)| (px - x).hypot(py - y) <= radius + self.width / 2.0)
+    }
+
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        for point in self.points.iter_mut() {
+            point.0 += dx;
+            point.1 += dy;
+        }
+    }
+
+    /// Changes the color, alpha or width of the stroke
+    pub fn restyle(&mut self, restyle: Restyle) {
+        match restyle {
+            Restyle::Color([r, g, b]) => self.color = Color::from_float_rgb(r, g, b).with_alpha(self.color.alpha()),
+            Restyle::Alpha(alpha) => self.color = self.color.with_alpha(alpha),
+            Restyle::Width(width) => self.width = width,
+            Restyle::Fill(_) => {},
+        }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let points: Vec<String> = self.points.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+        let path: Vec<String> = self.points.iter().map(|(x, y)| format!("{} {}", x, y)).collect();
+
+        format!(
+            "{}\"{}\" fill=\"none\" stroke=\"{}\" stroke-opacity=\"{}\" stroke-width=\"{}\" stroke-linecap=\"butt\" stroke-linejoin=\"round\" style=\"mix-blend-mode:multiply\" d=\"M {}\"/>",
+            MARKER, points.join(" "), hex_color(self.color), self.color.float_alpha(), self.width, path.join(" L "),
+        )
+    }
+
+    pub fn from_svg(element: &str) -> Option<HighlightStroke> {
+        let doc = roxmltree::Document::parse(element).ok()?;
+        let node = doc.root_element();
+        let opacity = node.attribute("stroke-opacity").and_then(|o| o.parse().ok()).unwrap_or(1.0);
+        let color = parse_hex_color(node.attribute("stroke")?, opacity)?;
+        let width = node.attribute("stroke-width")?.parse().ok()?;
+        let points = node.attribute("data-pizarra-highlight")?.split_whitespace().map(|point| {
+            let (x, y) = point.split_once(',')?;
+
+            Some((x.parse().ok()?, y.parse().ok()?))
+        }).collect::<Option<Vec<_>>>()?;
+
+        if points.is_empty() {
+            return None;
+        }
+
+        Some(HighlightStroke { points, color, width })
+    }
+}
@@ -0,0 +1,293 @@
+//! Raster images placed on the canvas, like screenshots to annotate. The core
+//! only knows about strokes, so images are kept with the [`crate::extras`] of
+//! each page and drawn beneath the strokes.
+//!
+//! In files they are `<image>` elements with the picture embedded as a data
+//! uri.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use cairo::{Context, Matrix};
+use gdk::gdk_pixbuf::{Pixbuf, PixbufLoader};
+use gdk::prelude::*;
+use gtk::glib;
+use pizarra::prelude::*;
+
+/// Marks the `<image>` elements written by us
+pub const MARKER: &str = "<image data-pizarra-image=\"\"";
+
+/// Size of the handle used to scale the selected image, in pixels
+const HANDLE_SIZE: f64 = 10.0;
+
+/// Reasons why an image could not be inserted
+#[derive(Debug)]
+pub enum ImageError {
+    Read(io::Error),
+
+    /// Only PNG and JPEG are supported
+    Format,
+
+    Decode(glib::Error),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Read(e) => write!(f, "No pude leer la imagen: {}", e),
+            ImageError::Format => write!(f, "Solo puedo insertar imágenes PNG y JPEG"),
+            ImageError::Decode(e) => write!(f, "La imagen está dañada: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlacedImage {
+    /// The file as it was read, so it is saved without encoding it again.
+    /// Shared with the copies kept to undo changes to the image.
+    data: Rc<[u8]>,
+    mime: &'static str,
+    pixbuf: Pixbuf,
+
+    /// Top left corner in world coordinates
+    pub x: f64,
+    pub y: f64,
+
+    /// Size in world units
+    pub width: f64,
+    pub height: f64,
+}
+
+fn mime_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else {
+        None
+    }
+}
+
+impl PlacedImage {
+    /// Decodes a PNG or JPEG image. It is placed at the origin with its size
+    /// in pixels.
+    pub fn from_bytes(data: Vec<u8>) -> Result<PlacedImage, ImageError> {
+        let mime = mime_type(&data).ok_or(ImageError::Format)?;
+        let loader = PixbufLoader::new();
+
+        loader.write(&data).map_err(ImageError::Decode)?;
+        loader.close().map_err(ImageError::Decode)?;
+
+        let pixbuf = loader.pixbuf().ok_or(ImageError::Format)?;
+
+        Ok(PlacedImage {
+            x: 0.0,
+            y: 0.0,
+            width: pixbuf.width() as f64,
+            height: pixbuf.height() as f64,
+            data: data.into(),
+            mime,
+            pixbuf,
+        })
+    }
+
+    pub fn from_file(filename: &Path) -> Result<PlacedImage, ImageError> {
+        PlacedImage::from_bytes(fs::read(filename).map_err(ImageError::Read)?)
+    }
+
+    /// The image scaled to fit in a box of the given size, keeping its
+    /// proportions, with its center at the given point.
+    pub fn fitted(mut self, center: (f64, f64), max_width: f64, max_height: f64) -> PlacedImage {
+        let scale = (max_width / self.width).min(max_height / self.height).min(1.0);
+
+        self.width *= scale;
+        self.height *= scale;
+        self.x = center.0 - self.width / 2.0;
+        self.y = center.1 - self.height / 2.0;
+
+        self
+    }
+
+    pub fn bounds(&self) -> [Vec2D<WorldUnit>; 2] {
+        [
+            Vec2D::new_world(self.x, self.y),
+            Vec2D::new_world(self.x + self.width, self.y + self.height),
+        ]
+    }
+
+    fn contains(&self, (x, y): (f64, f64)) -> bool {
+        x >= self.x && x <= self.x + self.width && y >= self.y && y <= self.y + self.height
+    }
+
+    /// Draws the image in a context that is already in world coordinates
+    pub fn draw(&self, ctx: &Context) {
+        if self.width <= 0.0 || self.height <= 0.0 {
+            return;
+        }
+
+        ctx.save().unwrap();
+        ctx.translate(self.x, self.y);
+        ctx.scale(self.width / self.pixbuf.width() as f64, self.height / self.pixbuf.height() as f64);
+        ctx.set_source_pixbuf(&self.pixbuf, 0.0, 0.0);
+        ctx.paint().unwrap();
+        ctx.restore().unwrap();
+    }
+
+    pub fn to_svg(&self) -> String {
+        format!(
+            "{} x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" href=\"data:{};base64,{}\"/>",
+            MARKER, self.x, self.y, self.width, self.height, self.mime, glib::base64_encode(&self.data),
+        )
+    }
+
+    pub fn from_svg(element: &str) -> Option<PlacedImage> {
+        let doc = roxmltree::Document::parse(element).ok()?;
+        let node = doc.root_element();
+        let number = |name: &str| node.attribute(name).and_then(|v| v.parse::<f64>().ok());
+        let href = node.attribute("href")?;
+        let (_, data) = href.split_once(";base64,")?;
+        let image = PlacedImage::from_bytes(glib::base64_decode(data)).ok()?;
+
+        Some(PlacedImage {
+            x: number("x")?,
+            y: number("y")?,
+            width: number("width")?,
+            height: number("height")?,
+            ..image
+        })
+    }
+}
+
+/// Converts a point of the screen to world coordinates
+pub fn to_world(t: Transform, (x, y): (f64, f64)) -> (f64, f64) {
+    match Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0).try_invert() {
+        Ok(inverse) => inverse.transform_point(x, y),
+        Err(_) => (x, y),
+    }
+}
+
+/// Size of the visible part of the page in world units
+pub fn view_size(controller: &Pizarra) -> (f64, f64) {
+    let t = controller.get_transform();
+    let dimensions = controller.get_dimensions();
+    let (x1, y1) = to_world(t, (0.0, 0.0));
+    let (x2, y2) = to_world(t, (dimensions.x.val(), dimensions.y.val()));
+
+    ((x2 - x1).abs(), (y2 - y1).abs())
+}
+
+enum Drag {
+    /// Moving the image, grabbed at this offset from its corner
+    Move { dx: f64, dy: f64 },
+
+    /// Scaling the image from its bottom right corner
+    Scale,
+}
+
+/// Selection, moving and scaling of the images of the current page with the
+/// mouse. Used while the image tool is selected.
+#[derive(Default)]
+pub struct ImageEditor {
+    pub selected: Option<usize>,
+    drag: Option<Drag>,
+
+    /// The dragged image as it was when the drag began
+    before: Option<PlacedImage>,
+}
+
+impl ImageEditor {
+    fn handle_at(image: &PlacedImage, t: Transform, screen: (f64, f64)) -> bool {
+        let corner = Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0).transform_point(image.x + image.width, image.y + image.height);
+
+        (corner.0 - screen.0).abs() <= HANDLE_SIZE && (corner.1 - screen.1).abs() <= HANDLE_SIZE
+    }
+
+    /// Selects the image under the pointer, the topmost if several overlap,
+    /// and starts dragging it.
+    pub fn press(&mut self, images: &[PlacedImage], t: Transform, screen: (f64, f64)) {
+        let world = to_world(t, screen);
+
+        if let Some(image) = self.selected.and_then(|i| images.get(i)) {
+            if ImageEditor::handle_at(image, t, screen) {
+                self.drag = Some(Drag::Scale);
+                self.before = Some(image.clone());
+
+                return;
+            }
+        }
+
+        self.selected = images.iter().rposition(|image| image.contains(world));
+        self.drag = self.selected.map(|i| Drag::Move {
+            dx: world.0 - images[i].x,
+            dy: world.1 - images[i].y,
+        });
+        self.before = self.selected.map(|i| images[i].clone());
+    }
+
+    /// Moves or scales the selected image. Returns `true` if it changed.
+    pub fn motion(&mut self, images: &mut [PlacedImage], t: Transform, screen: (f64, f64)) -> bool {
+        let image = match self.selected.and_then(|i| images.get_mut(i)) {
+            Some(image) => image,
+            None => return false,
+        };
+        let (x, y) = to_world(t, screen);
+
+        match self.drag {
+            Some(Drag::Move { dx, dy }) => {
+                image.x = x - dx;
+                image.y = y - dy;
+            },
+            Some(Drag::Scale) => {
+                // keep the proportions of the picture
+                let ratio = image.height / image.width;
+                let width = (x - image.x).max((y - image.y) / ratio).max(1.0);
+
+                image.width = width;
+                image.height = width * ratio;
+            },
+            None => return false,
+        }
+
+        true
+    }
+
+    /// Ends the drag. Returns the index of the image that was dragged and
+    /// how it was before, if it moved.
+    pub fn release(&mut self, images: &[PlacedImage]) -> Option<(usize, PlacedImage)> {
+        self.drag.take()?;
+
+        let before = self.before.take()?;
+        let index = self.selected?;
+        let after = images.get(index)?;
+
+        if (before.x, before.y, before.width, before.height) == (after.x, after.y, after.width, after.height) {
+            return None;
+        }
+
+        Some((index, before))
+    }
+
+    /// Draws the outline of the selected image and its scale handle
+    pub fn draw(&self, images: &[PlacedImage], ctx: &Context, t: Transform) {
+        let image = match self.selected.and_then(|i| images.get(i)) {
+            Some(image) => image,
+            None => return,
+        };
+        let matrix = Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0);
+        let (x1, y1) = matrix.transform_point(image.x, image.y);
+        let (x2, y2) = matrix.transform_point(image.x + image.width, image.y + image.height);
+
+        ctx.save().unwrap();
+        ctx.set_source_rgb(0.2, 0.5, 1.0);
+        ctx.set_line_width(1.0);
+        ctx.set_dash(&[4.0, 4.0], 0.0);
+        ctx.rectangle(x1.min(x2), y1.min(y2), (x2 - x1).abs(), (y2 - y1).abs());
+        ctx.stroke().unwrap();
+        ctx.set_dash(&[], 0.0);
+        ctx.rectangle(x2 - HANDLE_SIZE / 2.0, y2 - HANDLE_SIZE / 2.0, HANDLE_SIZE, HANDLE_SIZE);
+        ctx.fill().unwrap();
+        ctx.restore().unwrap();
+    }
+}
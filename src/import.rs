@@ -0,0 +1,1194 @@
+//! Import of svg files made by other programs, like Inkscape or other
+//! whiteboards. The pizarra core only reads back the svg that it writes, so
+//! here a general svg is walked and every shape in it is rewritten as a path
+//! made of absolute `M`, `L`, `C` and `Z` commands with its styles resolved.
+//! Circles and ellipses stay ellipses unless a transform flattens them.
+//!
+//! Whatever can't be converted is skipped or approximated and counted in an
+//! [`ImportSummary`] instead of failing the whole import.
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fmt::{self, Write};
+
+use roxmltree::{Document, Node};
+
+use crate::selection::Restyle;
+
+/// Constant used to approximate a quarter of a circle with a cubic curve
+const KAPPA: f64 = 0.552_284_749_8;
+
+/// Elements that are not drawn by themselves, skipped without complaining
+const IGNORED: &[&str] = &[
+    "defs", "style", "title", "desc", "metadata", "namedview", "script",
+    "linearGradient", "radialGradient", "pattern", "clipPath", "mask",
+    "marker", "symbol", "filter",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+impl Point {
+    fn new(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    fn lerp(self, other: Point, t: f64) -> Point {
+        Point::new(self.x + (other.x - self.x) * t, self.y + (other.y - self.y) * t)
+    }
+
+    /// This point mirrored through `center`, for smooth curves
+    fn reflect(self, center: Point) -> Point {
+        Point::new(2.0 * center.x - self.x, 2.0 * center.y - self.y)
+    }
+}
+
+/// An affine transform `[a, b, c, d, e, f]` like svg's `matrix()`
+#[derive(Debug, Copy, Clone)]
+struct Affine([f64; 6]);
+
+impl Affine {
+    const IDENTITY: Affine = Affine([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn translate(x: f64, y: f64) -> Affine {
+        Affine([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    fn scale(x: f64, y: f64) -> Affine {
+        Affine([x, 0.0, 0.0, y, 0.0, 0.0])
+    }
+
+    fn rotate(degrees: f64) -> Affine {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+
+        Affine([cos, sin, -sin, cos, 0.0, 0.0])
+    }
+
+    /// The transform that applies `other` first and then `self`
+    fn then(&self, other: &Affine) -> Affine {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+
+        Affine([
+            a * a2 + c * b2,
+            b * a2 + d * b2,
+            a * c2 + c * d2,
+            b * c2 + d * d2,
+            a * e2 + c * f2 + e,
+            b * e2 + d * f2 + f,
+        ])
+    }
+
+    fn apply(&self, p: Point) -> Point {
+        let [a, b, c, d, e, f] = self.0;
+
+        Point::new(a * p.x + c * p.y + e, b * p.x + d * p.y + f)
+    }
+
+    /// How much lengths grow on average, used to scale stroke widths
+    fn scale_factor(&self) -> f64 {
+        let [a, b, c, d, ..] = self.0;
+
+        (a * d - b * c).abs().sqrt()
+    }
+}
+
+/// A circle or ellipse as the core writes it: center, radii and the rotation
+/// of its axes in degrees
+#[derive(Debug, Copy, Clone)]
+struct Ellipse {
+    center: Point,
+    rx: f64,
+    ry: f64,
+    angle: f64,
+}
+
+impl Ellipse {
+    /// The ellipse with `transform` applied, which is still an ellipse unless
+    /// it is flattened into a line, in which case this is `None`.
+    fn transformed(&self, transform: &Affine) -> Option<Ellipse> {
+        // the new radii and axes are the ones of the image of a unit circle
+        // under A = transform·rotation·scale(rx, ry), found with A·Aᵀ
+        let [a, b, c, d, ..] = transform.then(&Affine::rotate(self.angle)).0;
+        let (a, b, c, d) = (a * self.rx, b * self.rx, c * self.ry, d * self.ry);
+        let (s00, s01, s11) = (a * a + c * c, a * b + c * d, b * b + d * d);
+        let mean = (s00 + s11) / 2.0;
+        let spread = (((s00 - s11) / 2.0).powi(2) + s01 * s01).sqrt();
+        let rx = (mean + spread).sqrt();
+        let ry = (mean - spread).max(0.0).sqrt();
+
+        if !ry.is_normal() || ry <= rx * 1e-9 {
+            return None;
+        }
+
+        Some(Ellipse {
+            center: transform.apply(self.center),
+            rx,
+            ry,
+            angle: (2.0 * s01).atan2(s00 - s11).to_degrees() / 2.0,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Segment {
+    Move(Point),
+    Line(Point),
+    Cubic(Point, Point, Point),
+    Close,
+}
+
+/// What was imported and what had to be left out
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Number of shapes that made it to the drawing
+    pub shapes: usize,
+
+    /// Elements that were not imported, by name
+    pub skipped: BTreeMap<String, usize>,
+
+    /// Things that were imported but don't look exactly like the original
+    pub approximated: BTreeMap<&'static str, usize>,
+}
+
+impl ImportSummary {
+    fn skip(&mut self, element: &str) {
+        *self.skipped.entry(element.into()).or_default() += 1;
+    }
+
+    fn approximate(&mut self, what: &'static str) {
+        *self.approximated.entry(what).or_default() += 1;
+    }
+
+    /// Tells if everything in the file was imported as it was
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty() && self.approximated.is_empty()
+    }
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Importé {} figuras.", self.shapes)?;
+
+        if !self.skipped.is_empty() {
+            let list: Vec<_> = self.skipped.iter().map(|(name, count)| format!("{} <{}>", count, name)).collect();
+
+            write!(f, "\n\nOmití los elementos que no sé dibujar: {}.", list.join(", "))?;
+        }
+
+        if !self.approximated.is_empty() {
+            let list: Vec<_> = self.approximated.iter().map(|(what, count)| format!("{} {}", count, what)).collect();
+
+            write!(f, "\n\nAproximé: {}.", list.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A color with its opacity
+#[derive(Debug, Copy, Clone)]
+struct Paint {
+    rgb: [u8; 3],
+    opacity: f64,
+}
+
+/// Presentation properties, the inherited ones already resolved
+#[derive(Debug, Copy, Clone)]
+struct Style {
+    fill: Option<[u8; 3]>,
+    fill_opacity: f64,
+    stroke: Option<[u8; 3]>,
+    stroke_opacity: f64,
+    stroke_width: f64,
+
+    /// `opacity` of this element and all its ancestors multiplied
+    opacity: f64,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            fill: Some([0, 0, 0]),
+            fill_opacity: 1.0,
+            stroke: None,
+            stroke_opacity: 1.0,
+            stroke_width: 1.0,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl Style {
+    fn fill(&self) -> Option<Paint> {
+        self.fill.map(|rgb| Paint { rgb, opacity: self.fill_opacity * self.opacity })
+    }
+
+    fn stroke(&self) -> Option<Paint> {
+        self.stroke.map(|rgb| Paint { rgb, opacity: self.stroke_opacity * self.opacity })
+    }
+}
+
+fn named_color(name: &str) -> Option<[u8; 3]> {
+    Some(match name {
+        "black" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "red" => [255, 0, 0],
+        "lime" => [0, 255, 0],
+        "green" => [0, 128, 0],
+        "blue" => [0, 0, 255],
+        "yellow" => [255, 255, 0],
+        "cyan" | "aqua" => [0, 255, 255],
+        "magenta" | "fuchsia" => [255, 0, 255],
+        "gray" | "grey" => [128, 128, 128],
+        "silver" => [192, 192, 192],
+        "maroon" => [128, 0, 0],
+        "olive" => [128, 128, 0],
+        "navy" => [0, 0, 128],
+        "purple" => [128, 0, 128],
+        "teal" => [0, 128, 128],
+        "orange" => [255, 165, 0],
+        "brown" => [165, 42, 42],
+        "pink" => [255, 192, 203],
+        _ => return None,
+    })
+}
+
+/// Result of reading a paint value. Gradients and patterns are replaced by
+/// their fallback color or a gray.
+enum ParsedPaint {
+    None,
+    Color([u8; 3]),
+    Approximated([u8; 3]),
+}
+
+fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let value = value.trim();
+
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits: Vec<u8> = hex.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect::<Option<_>>()?;
+
+        return match digits[..] {
+            [r, g, b] => Some([r * 17, g * 17, b * 17]),
+            [r1, r2, g1, g2, b1, b2] => Some([r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2]),
+            _ => None,
+        };
+    }
+
+    if let Some(args) = value.strip_prefix("rgb(").and_then(|v| v.strip_suffix(')')) {
+        let channels: Vec<u8> = args.split(',').map(|c| {
+            let c = c.trim();
+
+            if let Some(percent) = c.strip_suffix('%') {
+                percent.trim().parse::<f64>().ok().map(|p| (p * 2.55).round().clamp(0.0, 255.0) as u8)
+            } else {
+                c.parse::<f64>().ok().map(|n| n.round().clamp(0.0, 255.0) as u8)
+            }
+        }).collect::<Option<_>>()?;
+
+        return match channels[..] {
+            [r, g, b] => Some([r, g, b]),
+            _ => None,
+        };
+    }
+
+    named_color(&value.to_lowercase())
+}
+
+fn parse_paint(value: &str) -> Option<ParsedPaint> {
+    let value = value.trim();
+
+    match value {
+        "none" | "transparent" => Some(ParsedPaint::None),
+        "currentColor" => Some(ParsedPaint::Color([0, 0, 0])),
+        _ if value.starts_with("url(") => {
+            let fallback = value.find(')').and_then(|end| parse_color(&value[end + 1..]));
+
+            Some(ParsedPaint::Approximated(fallback.unwrap_or([128, 128, 128])))
+        },
+        _ => parse_color(value).map(ParsedPaint::Color),
+    }
+}
+
+/// A length in user units. Units other than pixels are converted with the
+/// usual 96 dpi, percentages can't be resolved and are taken as numbers.
+fn parse_length(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let units = [("px", 1.0), ("pt", 4.0 / 3.0), ("pc", 16.0), ("mm", 96.0 / 25.4), ("cm", 96.0 / 2.54), ("in", 96.0), ("%", 1.0)];
+
+    for (suffix, factor) in units {
+        if let Some(number) = value.strip_suffix(suffix) {
+            return number.trim().parse::<f64>().ok().map(|n| n * factor);
+        }
+    }
+
+    value.parse().ok()
+}
+
+/// The value of a property, from the `style` attribute or the presentation
+/// attribute of the same name.
+fn property<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    let from_style = node.attribute("style").and_then(|style| {
+        // the last declaration wins
+        style.split(';').rev().find_map(|decl| {
+            let (key, value) = decl.split_once(':')?;
+
+            (key.trim() == name).then(|| value.trim())
+        })
+    });
+
+    from_style.or_else(|| node.attribute(name))
+}
+
+fn resolve_style(node: Node, parent: &Style, summary: &mut ImportSummary) -> Style {
+    let mut style = *parent;
+
+    for (name, slot) in [("fill", &mut style.fill), ("stroke", &mut style.stroke)] {
+        match property(node, name).and_then(parse_paint) {
+            Some(ParsedPaint::None) => *slot = None,
+            Some(ParsedPaint::Color(rgb)) => *slot = Some(rgb),
+            Some(ParsedPaint::Approximated(rgb)) => {
+                summary.approximate("degradados o patrones como color sólido");
+                *slot = Some(rgb);
+            },
+            None => {},
+        }
+    }
+
+    let number = |name: &str| property(node, name).and_then(|v| v.trim().parse::<f64>().ok()).map(|n| n.clamp(0.0, 1.0));
+
+    if let Some(width) = property(node, "stroke-width").and_then(parse_length) {
+        style.stroke_width = width;
+    }
+
+    if let Some(opacity) = number("fill-opacity") {
+        style.fill_opacity = opacity;
+    }
+
+    if let Some(opacity) = number("stroke-opacity") {
+        style.stroke_opacity = opacity;
+    }
+
+    if let Some(opacity) = number("opacity") {
+        style.opacity *= opacity;
+    }
+
+    style
+}
+
+/// Reads the numbers, commands and flags of path data and point lists
+struct Tokens<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(s: &'a str) -> Tokens<'a> {
+        Tokens { s: s.as_bytes(), i: 0 }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.i < self.s.len() && (self.s[self.i].is_ascii_whitespace() || self.s[self.i] == b',') {
+            self.i += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+
+        self.i >= self.s.len()
+    }
+
+    fn at_number(&mut self) -> bool {
+        self.skip_separators();
+
+        matches!(self.s.get(self.i), Some(b'0'..=b'9' | b'.' | b'-' | b'+'))
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+
+        let c = *self.s.get(self.i)?;
+
+        if c.is_ascii_alphabetic() {
+            self.i += 1;
+
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    fn number(&mut self) -> Option<f64> {
+        self.skip_separators();
+
+        let start = self.i;
+        let digits = |tokens: &mut Tokens| {
+            while matches!(tokens.s.get(tokens.i), Some(b'0'..=b'9')) {
+                tokens.i += 1;
+            }
+        };
+
+        if matches!(self.s.get(self.i), Some(b'-' | b'+')) {
+            self.i += 1;
+        }
+
+        digits(self);
+
+        if self.s.get(self.i) == Some(&b'.') {
+            self.i += 1;
+            digits(self);
+        }
+
+        if matches!(self.s.get(self.i), Some(b'e' | b'E')) && matches!(self.s.get(self.i + 1), Some(b'0'..=b'9' | b'-' | b'+')) {
+            self.i += 2;
+            digits(self);
+        }
+
+        std::str::from_utf8(&self.s[start..self.i]).ok()?.parse().ok()
+    }
+
+    /// Arc flags are a single digit that may not be separated from what
+    /// follows
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+
+        let flag = match self.s.get(self.i)? {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+
+        self.i += 1;
+
+        Some(flag)
+    }
+
+    fn point(&mut self) -> Option<Point> {
+        Some(Point::new(self.number()?, self.number()?))
+    }
+}
+
+/// Approximates an elliptical arc with cubic curves, following the
+/// conversion from endpoint to center parameterization of the svg spec.
+fn arc_to_cubics(from: Point, rx: f64, ry: f64, rotation: f64, large: bool, sweep: bool, to: Point) -> Vec<Segment> {
+    let (mut rx, mut ry) = (rx.abs(), ry.abs());
+
+    if from == to {
+        return Vec::new();
+    }
+
+    if rx == 0.0 || ry == 0.0 {
+        return vec![Segment::Line(to)];
+    }
+
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let dx2 = (from.x - to.x) / 2.0;
+    let dy2 = (from.y - to.y) / 2.0;
+    let x1 = cos * dx2 + sin * dy2;
+    let y1 = -sin * dx2 + cos * dy2;
+
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let num = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let den = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let sign = if large == sweep { -1.0 } else { 1.0 };
+    let coef = sign * (num / den).max(0.0).sqrt();
+    let cx1 = coef * rx * y1 / ry;
+    let cy1 = -coef * ry * x1 / rx;
+    let cx = cos * cx1 - sin * cy1 + (from.x + to.x) / 2.0;
+    let cy = sin * cx1 + cos * cy1 + (from.y + to.y) / 2.0;
+
+    let angle = |ux: f64, uy: f64, vx: f64, vy: f64| (ux * vy - uy * vx).atan2(ux * vx + uy * vy);
+    let start = angle(1.0, 0.0, (x1 - cx1) / rx, (y1 - cy1) / ry);
+    let mut delta = angle((x1 - cx1) / rx, (y1 - cy1) / ry, (-x1 - cx1) / rx, (-y1 - cy1) / ry);
+
+    if !sweep && delta > 0.0 {
+        delta -= 2.0 * PI;
+    } else if sweep && delta < 0.0 {
+        delta += 2.0 * PI;
+    }
+
+    let point_at = |a: f64| Point::new(
+        cx + rx * a.cos() * cos - ry * a.sin() * sin,
+        cy + rx * a.cos() * sin + ry * a.sin() * cos,
+    );
+    let derivative_at = |a: f64| Point::new(
+        -rx * a.sin() * cos - ry * a.cos() * sin,
+        -rx * a.sin() * sin + ry * a.cos() * cos,
+    );
+
+    let count = (delta.abs() / (PI / 2.0)).ceil().max(1.0) as usize;
+    let step = delta / count as f64;
+    let t = 4.0 / 3.0 * (step / 4.0).tan();
+
+    (0..count).map(|i| {
+        let a1 = start + step * i as f64;
+        let a2 = a1 + step;
+        let (p1, d1) = (point_at(a1), derivative_at(a1));
+        let (p2, d2) = (point_at(a2), derivative_at(a2));
+        let end = if i == count - 1 { to } else { p2 };
+
+        Segment::Cubic(
+            Point::new(p1.x + t * d1.x, p1.y + t * d1.y),
+            Point::new(p2.x - t * d2.x, p2.y - t * d2.y),
+            end,
+        )
+    }).collect()
+}
+
+/// Where a path is while it is being parsed
+struct PathState {
+    segments: Vec<Segment>,
+    current: Point,
+
+    /// Start of the current subpath, where `Z` goes back to
+    start: Point,
+
+    /// Second control point of the previous curve, for `S` and `T`
+    last_cubic: Option<Point>,
+    last_quad: Option<Point>,
+}
+
+impl PathState {
+    /// Reads the arguments of one command and adds its segments. Returns the
+    /// command that repeats if more numbers follow.
+    fn command(&mut self, tokens: &mut Tokens, command: u8) -> Option<Option<u8>> {
+        let relative = command.is_ascii_lowercase();
+        let current = self.current;
+        let offset = |p: Point| if relative { Point::new(p.x + current.x, p.y + current.y) } else { p };
+        let mut next = Some(command);
+        let mut cubic = None;
+        let mut quad = None;
+
+        match command.to_ascii_uppercase() {
+            b'M' => {
+                self.current = offset(tokens.point()?);
+                self.start = self.current;
+                self.segments.push(Segment::Move(self.current));
+
+                // following pairs are implicit line-tos
+                next = Some(if relative { b'l' } else { b'L' });
+            },
+            b'L' => {
+                self.current = offset(tokens.point()?);
+                self.segments.push(Segment::Line(self.current));
+            },
+            b'H' => {
+                let x = tokens.number()?;
+
+                self.current = Point::new(if relative { current.x + x } else { x }, current.y);
+                self.segments.push(Segment::Line(self.current));
+            },
+            b'V' => {
+                let y = tokens.number()?;
+
+                self.current = Point::new(current.x, if relative { current.y + y } else { y });
+                self.segments.push(Segment::Line(self.current));
+            },
+            b'C' | b'S' => {
+                let c1 = if command.eq_ignore_ascii_case(&b'C') {
+                    offset(tokens.point()?)
+                } else {
+                    self.last_cubic.map(|c| c.reflect(current)).unwrap_or(current)
+                };
+                let c2 = offset(tokens.point()?);
+                let end = offset(tokens.point()?);
+
+                self.segments.push(Segment::Cubic(c1, c2, end));
+                self.current = end;
+                cubic = Some(c2);
+            },
+            b'Q' | b'T' => {
+                let control = if command.eq_ignore_ascii_case(&b'Q') {
+                    offset(tokens.point()?)
+                } else {
+                    self.last_quad.map(|c| c.reflect(current)).unwrap_or(current)
+                };
+                let end = offset(tokens.point()?);
+
+                // a quadratic curve is a cubic one with its control points two
+                // thirds of the way to the quadratic control point
+                self.segments.push(Segment::Cubic(current.lerp(control, 2.0 / 3.0), end.lerp(control, 2.0 / 3.0), end));
+                self.current = end;
+                quad = Some(control);
+            },
+            b'A' => {
+                let rx = tokens.number()?;
+                let ry = tokens.number()?;
+                let rotation = tokens.number()?;
+                let large = tokens.flag()?;
+                let sweep = tokens.flag()?;
+                let end = offset(tokens.point()?);
+
+                self.segments.extend(arc_to_cubics(current, rx, ry, rotation, large, sweep, end));
+                self.current = end;
+            },
+            b'Z' => {
+                self.segments.push(Segment::Close);
+                self.current = self.start;
+                next = None;
+            },
+            _ => return None,
+        }
+
+        self.last_cubic = cubic;
+        self.last_quad = quad;
+
+        Some(next)
+    }
+}
+
+/// Parses path data into absolute segments. Like browsers do, everything
+/// up to the first error is kept.
+fn parse_path(data: &str) -> Vec<Segment> {
+    let mut tokens = Tokens::new(data);
+    let mut state = PathState {
+        segments: Vec::new(),
+        current: Point::new(0.0, 0.0),
+        start: Point::new(0.0, 0.0),
+        last_cubic: None,
+        last_quad: None,
+    };
+    let mut command = None;
+
+    while !tokens.at_end() {
+        if let Some(c) = tokens.command() {
+            command = Some(c);
+        } else if !tokens.at_number() {
+            break;
+        }
+
+        command = match command.and_then(|c| state.command(&mut tokens, c)) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    state.segments
+}
+
+fn parse_points(data: &str) -> Vec<Point> {
+    let mut tokens = Tokens::new(data);
+    let mut points = Vec::new();
+
+    while !tokens.at_end() {
+        match tokens.point() {
+            Some(p) => points.push(p),
+            None => break,
+        }
+    }
+
+    points
+}
+
+/// Parses a transform list like `translate(10 20) rotate(45)`
+fn parse_transform(value: &str) -> Affine {
+    let mut result = Affine::IDENTITY;
+    let mut rest = value;
+
+    while let Some(open) = rest.find('(') {
+        let name = rest[..open].trim().trim_start_matches(',').trim();
+        let close = match rest[open..].find(')') {
+            Some(close) => open + close,
+            None => break,
+        };
+        let mut tokens = Tokens::new(&rest[open + 1..close]);
+        let mut args = Vec::new();
+
+        while let Some(n) = tokens.number() {
+            args.push(n);
+        }
+
+        let transform = match (name, &args[..]) {
+            ("matrix", &[a, b, c, d, e, f]) => Affine([a, b, c, d, e, f]),
+            ("translate", &[x]) => Affine::translate(x, 0.0),
+            ("translate", &[x, y]) => Affine::translate(x, y),
+            ("scale", &[s]) => Affine::scale(s, s),
+            ("scale", &[x, y]) => Affine::scale(x, y),
+            ("rotate", &[a]) => Affine::rotate(a),
+            ("rotate", &[a, x, y]) => Affine::translate(x, y).then(&Affine::rotate(a)).then(&Affine::translate(-x, -y)),
+            ("skewX", &[a]) => Affine([1.0, 0.0, a.to_radians().tan(), 1.0, 0.0, 0.0]),
+            ("skewY", &[a]) => Affine([1.0, a.to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+            _ => Affine::IDENTITY,
+        };
+
+        result = result.then(&transform);
+        rest = &rest[close + 1..];
+    }
+
+    result
+}
+
+fn ellipse(cx: f64, cy: f64, rx: f64, ry: f64) -> Vec<Segment> {
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let p = Point::new;
+
+    vec![
+        Segment::Move(p(cx + rx, cy)),
+        Segment::Cubic(p(cx + rx, cy + ky), p(cx + kx, cy + ry), p(cx, cy + ry)),
+        Segment::Cubic(p(cx - kx, cy + ry), p(cx - rx, cy + ky), p(cx - rx, cy)),
+        Segment::Cubic(p(cx - rx, cy - ky), p(cx - kx, cy - ry), p(cx, cy - ry)),
+        Segment::Cubic(p(cx + kx, cy - ry), p(cx + rx, cy - ky), p(cx + rx, cy)),
+        Segment::Close,
+    ]
+}
+
+fn polyline(points: &[Point], closed: bool) -> Vec<Segment> {
+    let mut segments: Vec<_> = points.iter().enumerate().map(|(i, &p)| {
+        if i == 0 { Segment::Move(p) } else { Segment::Line(p) }
+    }).collect();
+
+    if closed && !segments.is_empty() {
+        segments.push(Segment::Close);
+    }
+
+    segments
+}
+
+/// Circles and ellipses as such, in their own coordinates
+fn ellipse_element(node: Node) -> Option<Ellipse> {
+    let length = |name: &str| node.attribute(name).and_then(parse_length).unwrap_or(0.0);
+    let center = Point::new(length("cx"), length("cy"));
+
+    match node.tag_name().name() {
+        "circle" => Some(Ellipse { center, rx: length("r"), ry: length("r"), angle: 0.0 }),
+        "ellipse" => Some(Ellipse { center, rx: length("rx"), ry: length("ry"), angle: 0.0 }),
+        _ => None,
+    }
+}
+
+/// The geometry of a shape element in its own coordinates
+fn geometry(node: Node, summary: &mut ImportSummary) -> Option<Vec<Segment>> {
+    let length = |name: &str| node.attribute(name).and_then(parse_length).unwrap_or(0.0);
+
+    Some(match node.tag_name().name() {
+        "path" => parse_path(node.attribute("d")?),
+        "line" => polyline(&[Point::new(length("x1"), length("y1")), Point::new(length("x2"), length("y2"))], false),
+        "polyline" => polyline(&parse_points(node.attribute("points")?), false),
+        "polygon" => polyline(&parse_points(node.attribute("points")?), true),
+        "rect" => {
+            let (x, y, w, h) = (length("x"), length("y"), length("width"), length("height"));
+
+            if node.attribute("rx").or_else(|| node.attribute("ry")).is_some() {
+                summary.approximate("rectángulos redondeados con esquinas rectas");
+            }
+
+            polyline(&[Point::new(x, y), Point::new(x + w, y), Point::new(x + w, y + h), Point::new(x, y + h)], true)
+        },
+        "circle" | "ellipse" => {
+            let Ellipse { center, rx, ry, .. } = ellipse_element(node)?;
+
+            ellipse(center.x, center.y, rx, ry)
+        },
+        _ => return None,
+    })
+}
+
+/// A shape found in the document, with what is needed to write it
+struct Shape {
+    segments: Vec<Segment>,
+
+    /// Circles and ellipses, whose segments are only used if they can't be
+    /// written as an ellipse
+    ellipse: Option<Ellipse>,
+
+    transform: Affine,
+    style: Style,
+}
+
+impl Shape {
+    /// Every point of the shape, control points included, in the
+    /// coordinates of the document
+    fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        self.segments.iter().flat_map(|segment| match *segment {
+            Segment::Move(p) | Segment::Line(p) => vec![p],
+            Segment::Cubic(c1, c2, p) => vec![c1, c2, p],
+            Segment::Close => vec![],
+        }).map(|p| self.transform.apply(p))
+    }
+}
+
+/// Writes the shape as an element the core can read. Coordinates are written
+/// with all their precision, as shapes may be written again and again while
+/// they are edited.
+fn write_shape(out: &mut String, shape: &Shape, offset: &Affine) {
+    let Shape { segments, style, .. } = shape;
+    let transform = offset.then(&shape.transform);
+    let mut css = String::new();
+
+    match style.fill() {
+        Some(Paint { rgb: [r, g, b], opacity }) => write!(css, "fill:#{:02x}{:02x}{:02x};fill-opacity:{:.3};", r, g, b, opacity).unwrap(),
+        None => css.push_str("fill:none;"),
+    }
+
+    if let Some(Paint { rgb: [r, g, b], opacity }) = style.stroke() {
+        let width = style.stroke_width * transform.scale_factor();
+
+        write!(css, "stroke:#{:02x}{:02x}{:02x};stroke-opacity:{:.3};stroke-width:{};", r, g, b, opacity, width).unwrap();
+    }
+
+    if let Some(Ellipse { center: c, rx, ry, angle }) = shape.ellipse.and_then(|ellipse| ellipse.transformed(&transform)) {
+        writeln!(out, r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" transform="rotate({} {} {})" style="{}"/>"#, c.x, c.y, rx, ry, angle, c.x, c.y, css).unwrap();
+
+        return;
+    }
+
+    let mut d = String::new();
+
+    for segment in segments {
+        match *segment {
+            Segment::Move(p) => {
+                let p = transform.apply(p);
+
+                write!(d, "M {} {} ", p.x, p.y).unwrap();
+            },
+            Segment::Line(p) => {
+                let p = transform.apply(p);
+
+                write!(d, "L {} {} ", p.x, p.y).unwrap();
+            },
+            Segment::Cubic(c1, c2, p) => {
+                let (c1, c2, p) = (transform.apply(c1), transform.apply(c2), transform.apply(p));
+
+                write!(d, "C {} {} {} {} {} {} ", c1.x, c1.y, c2.x, c2.y, p.x, p.y).unwrap();
+            },
+            Segment::Close => d.push_str("Z "),
+        }
+    }
+
+    writeln!(out, r#"<path d="{}" style="{}"/>"#, d.trim_end(), css).unwrap();
+}
+
+fn walk(node: Node, transform: &Affine, parent: &Style, shapes: &mut Vec<Shape>, summary: &mut ImportSummary) {
+    for child in node.children().filter(|n| n.is_element()) {
+        let name = child.tag_name().name();
+
+        if IGNORED.contains(&name) {
+            continue;
+        }
+
+        if property(child, "display") == Some("none") || property(child, "visibility") == Some("hidden") {
+            continue;
+        }
+
+        let transform = match child.attribute("transform") {
+            Some(t) => transform.then(&parse_transform(t)),
+            None => *transform,
+        };
+        let style = resolve_style(child, parent, summary);
+
+        if matches!(name, "g" | "a" | "svg" | "switch") {
+            if name == "svg" && (child.attribute("x").is_some() || child.attribute("y").is_some()) {
+                summary.approximate("svg anidados sin su posición");
+            }
+
+            walk(child, &transform, &style, shapes, summary);
+
+            continue;
+        }
+
+        match geometry(child, summary) {
+            Some(segments) if segments.len() > 1 => {
+                if style.fill.is_none() && style.stroke.is_none() {
+                    continue;
+                }
+
+                shapes.push(Shape { segments, ellipse: ellipse_element(child), transform, style });
+                summary.shapes += 1;
+            },
+            Some(_) => {}, // empty or degenerate, nothing to draw
+            None => summary.skip(name),
+        }
+    }
+}
+
+fn parse(svg: &str) -> Result<(Vec<Shape>, ImportSummary), roxmltree::Error> {
+    let doc = Document::parse(svg)?;
+    let root = doc.root_element();
+    let mut summary = ImportSummary::default();
+    let mut shapes = Vec::new();
+
+    let style = resolve_style(root, &Style::default(), &mut summary);
+    let transform = root.attribute("transform").map(parse_transform).unwrap_or(Affine::IDENTITY);
+
+    walk(root, &transform, &style, &mut shapes, &mut summary);
+
+    Ok((shapes, summary))
+}
+
+fn write(shapes: &[Shape], offset: &Affine) -> String {
+    let mut out = String::from("<svg xmlns=\"http://www.w3.org/2000/svg\">\n");
+
+    for shape in shapes {
+        write_shape(&mut out, shape, offset);
+    }
+
+    out.push_str("</svg>\n");
+
+    out
+}
+
+/// Converts an svg made by any program into one the pizarra core can open
+pub fn convert(svg: &str) -> Result<(String, ImportSummary), roxmltree::Error> {
+    let (shapes, summary) = parse(svg)?;
+
+    Ok((write(&shapes, &Affine::IDENTITY), summary))
+}
+
+/// Like [`convert`] but the shapes are moved so the center of their bounds
+/// lands at `center`. Also returns how much they were moved.
+pub fn convert_centered(svg: &str, center: (f64, f64)) -> Result<(String, ImportSummary, (f64, f64)), roxmltree::Error> {
+    let (shapes, summary) = parse(svg)?;
+    let bounds = shapes.iter().flat_map(Shape::points).fold(None, |bounds: Option<(Point, Point)>, p| {
+        Some(match bounds {
+            Some((min, max)) => (Point::new(min.x.min(p.x), min.y.min(p.y)), Point::new(max.x.max(p.x), max.y.max(p.y))),
+            None => (p, p),
+        })
+    });
+    let offset = match bounds {
+        Some((min, max)) => (center.0 - (min.x + max.x) / 2.0, center.1 - (min.y + max.y) / 2.0),
+        None => (0.0, 0.0),
+    };
+
+    Ok((write(&shapes, &Affine::translate(offset.0, offset.1)), summary, offset))
+}
+
+/// Parses a piece of svg, like a single element taken out of a document
+fn parse_fragment(fragment: &str) -> Vec<Shape> {
+    let svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\">{}</svg>", fragment);
+
+    parse(&svg).map(|(shapes, _)| shapes).unwrap_or_default()
+}
+
+/// Every point of the shapes in a piece of svg, control points included.
+/// Used to tell if they are inside an area.
+pub fn fragment_points(fragment: &str) -> Vec<(f64, f64)> {
+    parse_fragment(fragment).iter().flat_map(|shape| shape.points().map(|p| (p.x, p.y)).collect::<Vec<_>>()).collect()
+}
+
+/// Rewrites the shapes in a piece of svg as ones the core can read, moved
+/// by the transform `[a, b, c, d, e, f]` like svg's `matrix()`.
+pub fn transform_fragment(fragment: &str, matrix: [f64; 6]) -> String {
+    let mut out = String::new();
+
+    for shape in parse_fragment(fragment).iter() {
+        write_shape(&mut out, shape, &Affine(matrix));
+    }
+
+    out
+}
+
+/// Rewrites the shapes in a piece of svg as ones the core can read, with
+/// their stroke or fill changed. Shapes without stroke get the color and
+/// alpha in their fill.
+pub fn restyle_fragment(fragment: &str, restyle: Restyle) -> String {
+    let mut out = String::new();
+
+    for mut shape in parse_fragment(fragment) {
+        let style = &mut shape.style;
+        let stroked = style.stroke.is_some();
+
+        // the opacity of the whole shape goes to each paint, so setting the
+        // alpha of one doesn't change the other
+        style.fill_opacity *= style.opacity;
+        style.stroke_opacity *= style.opacity;
+        style.opacity = 1.0;
+
+        match restyle {
+            Restyle::Color([r, g, b]) => {
+                let rgb = Some([(r * 255.0).round() as u8, (g * 255.0).round() as u8, (b * 255.0).round() as u8]);
+
+                if stroked {
+                    style.stroke = rgb;
+                } else {
+                    style.fill = rgb;
+                }
+            },
+            Restyle::Alpha(alpha) if stroked => style.stroke_opacity = alpha as f64 / 255.0,
+            Restyle::Alpha(alpha) => style.fill_opacity = alpha as f64 / 255.0,
+            Restyle::Width(width) => style.stroke_width = width / shape.transform.scale_factor().max(f64::EPSILON),
+            Restyle::Fill(fill) => {
+                style.fill = fill.map(|color| [color.r, color.g, color.b]);
+                style.fill_opacity = fill.map(|color| color.float_alpha()).unwrap_or(1.0);
+            },
+        }
+
+        write_shape(&mut out, &shape, &Affine::IDENTITY);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Point, b: (f64, f64)) -> bool {
+        (a.x - b.0).abs() < 1e-6 && (a.y - b.1).abs() < 1e-6
+    }
+
+    /// Where each segment ends, `None` for `Z`
+    fn ends(segments: &[Segment]) -> Vec<Option<Point>> {
+        segments.iter().map(|segment| match *segment {
+            Segment::Move(p) | Segment::Line(p) | Segment::Cubic(_, _, p) => Some(p),
+            Segment::Close => None,
+        }).collect()
+    }
+
+    #[test]
+    fn arcs_too_small_to_reach_their_end_are_scaled() {
+        // a radius of 1 can't go from 0 to 10, so it becomes a half circle of
+        // radius 5
+        let segments = parse_path("M 0 0 A 1 1 0 0 1 10 0");
+        let last = ends(&segments).last().copied().flatten().unwrap();
+
+        assert!(close(last, (10.0, 0.0)));
+        assert_eq!(segments.len(), 3);
+
+        let lowest = segments.iter().filter_map(|segment| match *segment {
+            Segment::Cubic(_, _, p) => Some(p.y),
+            _ => None,
+        }).fold(0.0, f64::min);
+
+        assert!((lowest + 5.0).abs() < 1e-6, "{}", lowest);
+    }
+
+    #[test]
+    fn arcs_without_radius_are_lines() {
+        let segments = parse_path("M 0 0 A 0 5 0 0 1 10 0");
+
+        assert!(matches!(segments[1], Segment::Line(p) if close(p, (10.0, 0.0))));
+    }
+
+    #[test]
+    fn relative_commands_are_made_absolute() {
+        let segments = parse_path("m 10 10 5 0 v 5 h -5 l 0 -5 z m 1 1 l 1 1");
+        let ends: Vec<_> = ends(&segments).into_iter().map(|p| p.map(|p| (p.x, p.y))).collect();
+
+        assert_eq!(ends, vec![
+            Some((10.0, 10.0)),
+            Some((15.0, 10.0)),
+            Some((15.0, 15.0)),
+            Some((10.0, 15.0)),
+            Some((10.0, 10.0)),
+            None,
+            // after `z` relative commands start where the subpath began
+            Some((11.0, 11.0)),
+            Some((12.0, 12.0)),
+        ]);
+    }
+
+    #[test]
+    fn smooth_curves_reflect_the_previous_control_point() {
+        let segments = parse_path("M 0 0 C 0 10 10 10 10 0 s 10 -10 10 0");
+
+        match segments[2] {
+            Segment::Cubic(c1, c2, end) => {
+                assert!(close(c1, (10.0, -10.0)));
+                assert!(close(c2, (20.0, -10.0)));
+                assert!(close(end, (20.0, 0.0)));
+            },
+            other => panic!("expected a curve, got {:?}", other),
+        }
+
+        // without a previous curve the control point is the current point
+        match parse_path("M 5 5 S 10 10 15 5")[1] {
+            Segment::Cubic(c1, _, _) => assert!(close(c1, (5.0, 5.0))),
+            other => panic!("expected a curve, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn smooth_quadratic_curves_reflect_the_previous_control_point() {
+        let segments = parse_path("M 0 0 Q 5 10 10 0 T 20 0");
+
+        // the reflected control point is (15, -10), two thirds of the way
+        // from each end
+        match segments[2] {
+            Segment::Cubic(c1, c2, _) => {
+                assert!(close(c1, (10.0 + 5.0 * 2.0 / 3.0, -10.0 * 2.0 / 3.0)));
+                assert!(close(c2, (20.0 - 5.0 * 2.0 / 3.0, -10.0 * 2.0 / 3.0)));
+            },
+            other => panic!("expected a curve, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nested_transforms_apply_from_the_inside_out() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg">
+            <g transform="translate(10 0)">
+                <g transform="scale(2)">
+                    <line x1="1" y1="1" x2="2" y2="3" stroke="black" transform="translate(1 0)"/>
+                </g>
+            </g>
+        </svg>"#;
+        let (shapes, _) = parse(svg).unwrap();
+        let points: Vec<_> = shapes[0].points().collect();
+
+        assert!(close(points[0], (14.0, 2.0)));
+        assert!(close(points[1], (16.0, 6.0)));
+    }
+
+    #[test]
+    fn transforms_scale_the_stroke_width() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"><g transform="scale(3)"><line x1="0" y1="0" x2="1" y2="1" stroke="black" stroke-width="2"/></g></svg>"#;
+        let (converted, _) = convert(svg).unwrap();
+
+        assert!(converted.contains("stroke-width:6;"), "{}", converted);
+    }
+
+    #[test]
+    fn rotated_ellipses_stay_ellipses() {
+        let fragment = r#"<ellipse cx="10" cy="20" rx="5" ry="3" transform="rotate(30 10 20)" style="fill:none;stroke:#ff0000;stroke-width:2"/>"#;
+        let moved = transform_fragment(fragment, [2.0, 0.0, 0.0, 2.0, 1.0, 0.0]);
+
+        assert!(moved.starts_with(r#"<ellipse cx="21" cy="40" rx="10" ry="6" transform="rotate(29.99"#), "{}", moved);
+
+        // flattened into a line it can only be a path
+        let flattened = transform_fragment(fragment, [1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+
+        assert!(flattened.starts_with("<path"), "{}", flattened);
+    }
+
+    #[test]
+    fn the_summary_tells_what_was_skipped_and_approximated() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg">
+            <defs><linearGradient id="g"/></defs>
+            <text x="0" y="0">hola</text>
+            <text x="0" y="10">adiós</text>
+            <image href="foto.png"/>
+            <rect x="0" y="0" width="10" height="10" rx="2" fill="red"/>
+            <circle cx="0" cy="0" r="5" fill="blue"/>
+            <line x1="0" y1="0" x2="0" y2="0" stroke="black" fill="none" style="display:none"/>
+        </svg>"#;
+        let (_, summary) = convert(svg).unwrap();
+
+        assert_eq!(summary.shapes, 2);
+        assert_eq!(summary.skipped.get("text"), Some(&2));
+        assert_eq!(summary.skipped.get("image"), Some(&1));
+        assert_eq!(summary.skipped.get("linearGradient"), None);
+        assert_eq!(summary.approximated.get("rectángulos redondeados con esquinas rectas"), Some(&1));
+        assert!(!summary.is_complete());
+
+        let message = summary.to_string();
+
+        assert!(message.contains("Omití los elementos que no sé dibujar: 1 <image>, 2 <text>."), "{}", message);
+        assert!(message.contains("Aproximé: 1 rectángulos redondeados con esquinas rectas."), "{}", message);
+    }
+
+    #[test]
+    fn a_plain_drawing_is_complete() {
+        let (_, summary) = convert(r#"<svg xmlns="http://www.w3.org/2000/svg"><path d="M 0 0 L 1 1" stroke="black"/></svg>"#).unwrap();
+
+        assert_eq!(summary.shapes, 1);
+        assert!(summary.is_complete());
+    }
+}
@@ -0,0 +1,216 @@
+//! Strokes drawn with a pen that reports pressure, like the one of a graphics
+//! tablet. The core draws every stroke with a single width, so while the pen
+//! tool is used with such a device the points are recorded here, each one with
+//! its own width, and the stroke is drawn as a filled outline. The mouse keeps
+//! drawing through the core.
+//!
+//! In files they are `<path>` elements with the outline, so other programs
+//! show them, and the recorded points in an attribute so they can be read
+//! back exactly.
+use std::f64::consts::PI;
+
+use pizarra::prelude::*;
+
+use crate::config::PressureSettings;
+use crate::selection::Restyle;
+
+/// Marks the `<path>` elements written by us
+pub const MARKER: &str = "<path data-pizarra-ink=";
+
+/// Distance in pixels at which the eraser removes a stroke
+pub const ERASE_RADIUS: f64 = 8.0;
+
+/// Segments used to draw half a circle at the ends of a stroke
+const CAP_SEGMENTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InkPoint {
+    /// Position in world coordinates
+    pub x: f64,
+    pub y: f64,
+
+    /// Width of the stroke at this point, in world units
+    pub width: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct InkStroke {
+    pub points: Vec<InkPoint>,
+    pub color: Color,
+}
+
+/// Width of the stroke for the given pressure, from 0 to 1, and tilt, whose
+/// components go from -1 to 1.
+pub fn width(settings: &PressureSettings, stroke: f64, pressure: f64, tilt: Option<(f64, f64)>) -> f64 {
+    let pressure = pressure.clamp(0.0, 1.0);
+    let curve = &settings.curve;
+    let factor = match curve.iter().position(|&[p, _]| p >= pressure) {
+        _ if curve.is_empty() => 1.0,
+        Some(0) => curve[0][1],
+        Some(i) => {
+            let [p1, f1] = curve[i - 1];
+            let [p2, f2] = curve[i];
+
+            f1 + (f2 - f1) * (pressure - p1) / (p2 - p1)
+        },
+        None => curve[curve.len() - 1][1],
+    };
+    let tilt = tilt.map(|(x, y)| x.hypot(y).min(1.0)).unwrap_or(0.0);
+
+    (stroke * factor * (1.0 + settings.tilt * tilt)).max(0.1)
+}
+
+/// The color as `#rrggbb`, its alpha goes in an opacity attribute
+pub fn hex_color(color: Color) -> String {
+    let channel = |c: f64| (c * 255.0).round() as u8;
+
+    format!("#{:02x}{:02x}{:02x}", channel(color.float_r()), channel(color.float_g()), channel(color.float_b()))
+}
+
+/// Reads a color written by [`hex_color`] and its opacity
+pub fn parse_hex_color(hex: &str, opacity: f64) -> Option<Color> {
+    let hex = hex.strip_prefix('#').filter(|h| h.len() == 6)?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok().map(|c| c as f64 / 255.0);
+
+    Some(Color::from_float_rgb(channel(0)?, channel(2)?, channel(4)?).with_alpha((opacity.clamp(0.0, 1.0) * 255.0).round() as u8))
+}
+
+impl InkStroke {
+    pub fn new(color: Color) -> InkStroke {
+        InkStroke {
+            points: Vec::new(),
+            color,
+        }
+    }
+
+    /// Adds a point at the end of the stroke. If it is at the same place as
+    /// the last one only its width is kept.
+    pub fn push(&mut self, point: InkPoint) {
+        match self.points.last_mut() {
+            Some(last) if last.x == point.x && last.y == point.y => last.width = last.width.max(point.width),
+            _ => self.points.push(point),
+        }
+    }
+
+    /// The polygon that surrounds the stroke, with round ends
+    pub fn outline(&self) -> Vec<(f64, f64)> {
+        let points = &self.points;
+
+        if points.len() == 1 {
+            let p = points[0];
+
+            return (0..CAP_SEGMENTS * 2).map(|i| {
+                let angle = PI * i as f64 / CAP_SEGMENTS as f64;
+
+                (p.x + angle.cos() * p.width / 2.0, p.y + angle.sin() * p.width / 2.0)
+            }).collect();
+        }
+
+        // direction of the stroke at each point, from its neighbours
+        let directions: Vec<(f64, f64)> = (0..points.len()).map(|i| {
+            let prev = points[i.saturating_sub(1)];
+            let next = points[(i + 1).min(points.len() - 1)];
+            let (dx, dy) = (next.x - prev.x, next.y - prev.y);
+            let length = dx.hypot(dy);
+
+            if length > 0.0 { (dx / length, dy / length) } else { (1.0, 0.0) }
+        }).collect();
+
+        let side = |i: usize, sign: f64| {
+            let (dx, dy) = directions[i];
+            let p = points[i];
+
+            (p.x - dy * sign * p.width / 2.0, p.y + dx * sign * p.width / 2.0)
+        };
+        let cap = |i: usize, start: f64| {
+            let (dx, dy) = directions[i];
+            let p = points[i];
+            let base = dy.atan2(dx) + start;
+
+            (1..CAP_SEGMENTS).map(move |s| {
+                let angle = base - PI * s as f64 / CAP_SEGMENTS as f64;
+
+                (p.x + angle.cos() * p.width / 2.0, p.y + angle.sin() * p.width / 2.0)
+            })
+        };
+        let last = points.len() - 1;
+        let mut outline: Vec<_> = (0..points.len()).map(|i| side(i, 1.0)).collect();
+
+        outline.extend(cap(last, PI / 2.0));
+        outline.extend((0..points.len()).rev().map(|i| side(i, -1.0)));
+        outline.extend(cap(0, -PI / 2.0));
+
+        outline
+    }
+
+    pub fn bounds(&self) -> Option<[Vec2D<WorldUnit>; 2]> {
+        let half = |p: &InkPoint| p.width / 2.0;
+        let first = self.points.first()?;
+        let init = (first.x - half(first), first.y - half(first), first.x + half(first), first.y + half(first));
+        let (x1, y1, x2, y2) = self.points.iter().fold(init, |(x1, y1, x2, y2), p| {
+            (x1.min(p.x - half(p)), y1.min(p.y - half(p)), x2.max(p.x + half(p)), y2.max(p.y + half(p)))
+        });
+
+        Some([Vec2D::new_world(x1, y1), Vec2D::new_world(x2, y2)])
+    }
+
+    /// Tells if the stroke passes within `radius` of the given point
+    pub fn touches(&self, (x, y): (f64, f64), radius: f64) -> bool {
+        self.points.iter().any(|p| (p.x - x).hypot(p.y - y) <= radius + p.width / 2.0)
+    }
+
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        for point in self.points.iter_mut() {
+            point.x += dx;
+            point.y += dy;
+        }
+    }
+
+    /// Changes the color, alpha or width of the stroke. The width is the one
+    /// of its widest point, the rest keep their proportion.
+    pub fn restyle(&mut self, restyle: Restyle) {
+        match restyle {
+            Restyle::Color([r, g, b]) => self.color = Color::from_float_rgb(r, g, b).with_alpha(self.color.alpha()),
+            Restyle::Alpha(alpha) => self.color = self.color.with_alpha(alpha),
+            Restyle::Width(width) => {
+                let widest = self.points.iter().map(|p| p.width).fold(0.0, f64::max);
+
+                if widest > 0.0 {
+                    for point in self.points.iter_mut() {
+                        point.width *= width / widest;
+                    }
+                }
+            },
+            Restyle::Fill(_) => {},
+        }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let points: Vec<String> = self.points.iter().map(|p| format!("{},{},{}", p.x, p.y, p.width)).collect();
+        let outline: Vec<String> = self.outline().iter().map(|(x, y)| format!("{} {}", x, y)).collect();
+
+        format!(
+            "{}\"{}\" fill=\"{}\" fill-opacity=\"{}\" d=\"M {} Z\"/>",
+            MARKER, points.join(" "), hex_color(self.color), self.color.float_alpha(), outline.join(" L "),
+        )
+    }
+
+    pub fn from_svg(element: &str) -> Option<InkStroke> {
+        let doc = roxmltree::Document::parse(element).ok()?;
+        let node = doc.root_element();
+        let opacity = node.attribute("fill-opacity").and_then(|o| o.parse().ok()).unwrap_or(1.0);
+        let color = parse_hex_color(node.attribute("fill")?, opacity)?;
+        let points = node.attribute("data-pizarra-ink")?.split_whitespace().map(|point| {
+            let mut values = point.split(',').map(|v| v.parse::<f64>().ok());
+            let (x, y, width) = (values.next()??, values.next()??, values.next()??);
+
+            Some(InkPoint { x, y, width })
+        }).collect::<Option<Vec<_>>>()?;
+
+        if points.is_empty() {
+            return None;
+        }
+
+        Some(InkStroke { points, color })
+    }
+}
@@ -0,0 +1,136 @@
+//! The laser pointer, to point at things while explaining. It leaves a glowing
+//! trail that fades away after a while and never becomes part of the drawing,
+//! so it doesn't touch the undo history nor the unsaved changes.
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use gtk::glib::{self, Continue};
+use gtk::DrawingArea;
+use gtk::prelude::*;
+use pizarra::prelude::*;
+
+use crate::config::LaserSettings;
+use crate::images::to_world;
+
+/// Time between redraws while the trail fades
+const FRAME: Duration = Duration::from_millis(33);
+
+#[derive(Debug, Clone, Copy)]
+pub struct LaserPoint {
+    /// Position in world coordinates, so the trail stays where it was drawn
+    /// if the view moves
+    pub x: f64,
+    pub y: f64,
+
+    pub time: Instant,
+}
+
+pub struct Laser {
+    pub settings: LaserSettings,
+
+    /// The trails that haven't faded yet, the last one is being drawn while
+    /// `pointing`
+    pub trails: Vec<Vec<LaserPoint>>,
+
+    pointing: bool,
+    fading: bool,
+}
+
+impl Laser {
+    pub fn new(settings: LaserSettings) -> Laser {
+        Laser {
+            settings,
+            trails: Vec::new(),
+            pointing: false,
+            fading: false,
+        }
+    }
+
+    /// Starts a trail at the given position of the screen
+    pub fn press(&mut self, t: Transform, screen: (f64, f64)) {
+        let (x, y) = to_world(t, screen);
+
+        self.trails.push(vec![LaserPoint { x, y, time: Instant::now() }]);
+        self.pointing = true;
+    }
+
+    /// Continues the trail, returns `false` if there is none being drawn
+    pub fn motion(&mut self, t: Transform, screen: (f64, f64)) -> bool {
+        if !self.pointing {
+            return false;
+        }
+
+        let (x, y) = to_world(t, screen);
+
+        if let Some(trail) = self.trails.last_mut() {
+            trail.push(LaserPoint { x, y, time: Instant::now() });
+        }
+
+        true
+    }
+
+    pub fn release(&mut self) {
+        self.pointing = false;
+    }
+
+    /// How much of a point is left, from 1 when it was just drawn to 0 when
+    /// it has faded away
+    pub fn remaining(&self, point: &LaserPoint, now: Instant) -> f64 {
+        if self.settings.fade <= 0.0 {
+            return 0.0;
+        }
+
+        (1.0 - now.duration_since(point.time).as_secs_f64() / self.settings.fade).max(0.0)
+    }
+
+    /// Forgets the points that faded away. Returns `true` while there is
+    /// something left to draw.
+    fn fade(&mut self) -> bool {
+        let now = Instant::now();
+        let fade = Duration::from_secs_f64(self.settings.fade.max(0.0));
+        let last = self.trails.len().saturating_sub(1);
+        let pointing = self.pointing;
+
+        for trail in self.trails.iter_mut() {
+            trail.retain(|p| now.duration_since(p.time) < fade);
+        }
+
+        let mut index = 0;
+
+        self.trails.retain(|trail| {
+            let keep = !trail.is_empty() || (pointing && index == last);
+
+            index += 1;
+
+            keep
+        });
+
+        !self.trails.is_empty()
+    }
+}
+
+/// Redraws the drawing area while the trails fade away. Does nothing if it is
+/// already doing so.
+pub fn animate(laser: &Rc<RefCell<Laser>>, dw: &DrawingArea) {
+    if std::mem::replace(&mut laser.borrow_mut().fading, true) {
+        return;
+    }
+
+    let weak = Rc::downgrade(laser);
+    let dw = dw.clone();
+
+    glib::timeout_add_local(FRAME, move || {
+        let laser = match weak.upgrade() {
+            Some(laser) => laser,
+            None => return Continue(false),
+        };
+        let mut laser = laser.borrow_mut();
+        let left = laser.fade();
+
+        dw.queue_draw();
+        laser.fading = left;
+
+        Continue(left)
+    });
+}
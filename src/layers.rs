@@ -0,0 +1,307 @@
+//! Layers of a page. Only one layer at a time, the active one, lives in the
+//! controller and the extras of the page; the rest wait here as the svg of
+//! their shapes and extras, ready to be drawn beneath or above it. Hidden
+//! layers are not drawn nor exported and locked ones can't be drawn on.
+//!
+//! In files every layer is a `<g>` element marked with `data-pizarra-layer`
+//! that holds its shapes and extras, so they can be told apart when the page
+//! is opened again. Pages with a single visible and unlocked layer are saved
+//! as plain svg, like before there were layers.
+use cairo::Context;
+use pizarra::config::Config;
+use pizarra::draw_commands::DrawCommand;
+use pizarra::prelude::*;
+
+use crate::extras::Extras;
+use crate::graphics::Drawable;
+use crate::selection::CoreSvg;
+use crate::text::escape;
+
+/// Marks the `<g>` elements written by us
+pub const MARKER: &str = "<g data-pizarra-layer=";
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+
+    /// Shapes of the core and elements of the extras, as they go inside the
+    /// group. Empty for the active layer, whose contents are elsewhere.
+    content: String,
+
+    /// What is drawn for this layer, read from `content`
+    commands: Vec<DrawCommand>,
+    extras: Extras,
+    bounds: Option<[Vec2D<WorldUnit>; 2]>,
+}
+
+/// The contents of the active layer: the shapes of the core and the extras
+/// of the page
+pub fn active_content(core_svg: &str, extras: &Extras) -> String {
+    let shapes = CoreSvg::parse(core_svg).map(|core| core.shapes.join("\n")).unwrap_or_default();
+    let (beneath, above) = extras.elements();
+
+    format!("{}\n{}{}", beneath, shapes, above)
+}
+
+/// The svg that the core reads and the extras of the contents of a layer
+pub fn open_content(content: &str, config: Config) -> (String, Extras) {
+    Extras::split(&format!("<svg xmlns=\"http://www.w3.org/2000/svg\">{}\n</svg>", content), config)
+}
+
+impl Layer {
+    pub fn new(name: &str) -> Layer {
+        Layer {
+            name: name.into(),
+            visible: true,
+            locked: false,
+            content: String::new(),
+            commands: Vec::new(),
+            extras: Extras::default(),
+            bounds: None,
+        }
+    }
+
+    /// Replaces what the layer holds, see [`active_content`]
+    pub fn set_content(&mut self, content: String, config: Config) {
+        let (svg, extras) = open_content(&content, config);
+        let mut scratch = Pizarra::new(Vec2D::new_screen(1.0, 1.0), config);
+
+        // layers were written by us so they should always open
+        scratch.open(&svg).ok();
+
+        self.commands = scratch.draw_commands_for_drawing();
+        self.bounds = crate::extras::page_bounds(&scratch, &extras);
+        self.extras = extras;
+        self.content = content;
+    }
+
+    /// Takes what the layer holds, leaving it empty
+    pub fn take_content(&mut self) -> String {
+        self.commands.clear();
+        self.extras = Extras::default();
+        self.bounds = None;
+
+        std::mem::take(&mut self.content)
+    }
+
+    /// Adds shapes and extras above the ones it has
+    pub fn append(&mut self, content: &str, config: Config) {
+        let content = format!("{}\n{}", self.content, content);
+
+        self.set_content(content, config);
+    }
+
+    pub fn draw(&self, ctx: &Context, t: Transform) {
+        self.extras.draw_beneath(ctx, t);
+
+        for command in self.commands.iter() {
+            command.draw(ctx, t);
+        }
+
+        self.extras.draw_above(ctx, t);
+    }
+
+    fn to_svg(&self, content: &str, active: bool) -> String {
+        format!(
+            "{}\"{}\" data-pizarra-visible=\"{}\" data-pizarra-locked=\"{}\" data-pizarra-active=\"{}\"{}>{}\n</g>",
+            MARKER, escape(&self.name), self.visible, self.locked, active,
+            if self.visible { "" } else { " display=\"none\"" }, content,
+        )
+    }
+
+    /// Reads a group written by [`Layer::to_svg`] and tells if it was the
+    /// active layer
+    fn from_svg(element: &str, config: Config) -> Option<(Layer, bool)> {
+        let doc = roxmltree::Document::parse(element).ok()?;
+        let node = doc.root_element();
+        let flag = |name: &str| node.attribute(name) == Some("true");
+        let mut layer = Layer::new(node.attribute("data-pizarra-layer")?);
+
+        layer.visible = flag("data-pizarra-visible");
+        layer.locked = flag("data-pizarra-locked");
+
+        if let (Some(start), Some(end)) = (element.find('>'), element.rfind("</g>")) {
+            if start < end {
+                layer.set_content(element[start + 1..end].trim().to_owned(), config);
+            }
+        }
+
+        Some((layer, flag("data-pizarra-active")))
+    }
+}
+
+/// The layers of a page, bottom first. A page that never had layers has an
+/// empty list, which is the same as a single visible and unlocked layer.
+#[derive(Debug, Clone, Default)]
+pub struct Layers {
+    pub list: Vec<Layer>,
+
+    /// Index of the layer in the controller and the rest of the extras
+    pub active: usize,
+}
+
+impl Layers {
+    /// Name of a new layer
+    pub fn name(number: usize) -> String {
+        format!("Capa {}", number)
+    }
+
+    /// Tells if the page can be saved as a plain svg
+    pub fn is_plain(&self) -> bool {
+        match self.list.as_slice() {
+            [] => true,
+            [layer] => layer.visible && !layer.locked && layer.name == Layers::name(1),
+            _ => false,
+        }
+    }
+
+    /// Gives the page its first layer if it had none
+    pub fn ensure(&mut self) {
+        if self.list.is_empty() {
+            self.list.push(Layer::new(&Layers::name(1)));
+            self.active = 0;
+        }
+    }
+
+    pub fn active_visible(&self) -> bool {
+        self.list.get(self.active).map(|layer| layer.visible).unwrap_or(true)
+    }
+
+    /// Tells if the active layer can be drawn on, which is not the case if
+    /// it is locked or hidden
+    pub fn active_editable(&self) -> bool {
+        self.list.get(self.active).map(|layer| layer.visible && !layer.locked).unwrap_or(true)
+    }
+
+    /// Adds an empty layer above the active one and makes it active
+    pub fn add(&mut self) -> bool {
+        let name = (1..).map(Layers::name).find(|name| self.list.iter().all(|layer| &layer.name != name)).unwrap_or_default();
+
+        self.active = (self.active + 1).min(self.list.len());
+        self.list.insert(self.active, Layer::new(&name));
+
+        true
+    }
+
+    /// Removes a layer with everything in it. The one beneath becomes active
+    /// if it was the active one. The last layer can't be removed.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if self.list.len() < 2 || index >= self.list.len() {
+            return false;
+        }
+
+        self.list.remove(index);
+
+        if index < self.active || (index == self.active && index > 0) {
+            self.active -= 1;
+        }
+
+        true
+    }
+
+    /// Moves a layer one place up (`1`) or down (`-1`)
+    pub fn move_layer(&mut self, index: usize, offset: isize) -> bool {
+        let target = index as isize + offset;
+
+        if index >= self.list.len() || target < 0 || target as usize >= self.list.len() {
+            return false;
+        }
+
+        let target = target as usize;
+
+        self.list.swap(index, target);
+
+        if self.active == index {
+            self.active = target;
+        } else if self.active == target {
+            self.active = index;
+        }
+
+        true
+    }
+
+    pub fn set_visible(&mut self, index: usize, visible: bool) -> bool {
+        match self.list.get_mut(index) {
+            Some(layer) if layer.visible != visible => {
+                layer.visible = visible;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn set_locked(&mut self, index: usize, locked: bool) -> bool {
+        match self.list.get_mut(index) {
+            Some(layer) if layer.locked != locked => {
+                layer.locked = locked;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Takes the layers out of the svg of a page. Returns the contents of the
+    /// active one, which go to the controller and the rest of the extras.
+    pub fn from_groups(groups: &[String], config: Config) -> (Layers, String) {
+        let mut list = Vec::new();
+        let mut active = 0;
+
+        for (layer, is_active) in groups.iter().filter_map(|group| Layer::from_svg(group, config)) {
+            if is_active {
+                active = list.len();
+            }
+
+            list.push(layer);
+        }
+
+        if list.is_empty() {
+            return (Layers::default(), String::new());
+        }
+
+        let content = list[active].take_content();
+
+        (Layers { list, active }, content)
+    }
+
+    /// Every layer as a group, the active one with the given contents
+    pub fn to_svg(&self, active_content: &str) -> String {
+        self.list.iter().enumerate().map(|(i, layer)| {
+            if i == self.active {
+                layer.to_svg(active_content, true)
+            } else {
+                layer.to_svg(&layer.content, false)
+            }
+        }).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Draws the visible layers under the active one
+    pub fn draw_beneath(&self, ctx: &Context, t: Transform) {
+        for layer in self.list.iter().take(self.active).filter(|layer| layer.visible) {
+            layer.draw(ctx, t);
+        }
+    }
+
+    /// Draws the visible layers over the active one
+    pub fn draw_above(&self, ctx: &Context, t: Transform) {
+        for layer in self.list.iter().skip(self.active + 1).filter(|layer| layer.visible) {
+            layer.draw(ctx, t);
+        }
+    }
+
+    /// Tells if the layers other than the active one hold nothing, hidden or
+    /// not
+    pub fn others_empty(&self) -> bool {
+        self.list.iter().enumerate().all(|(i, layer)| i == self.active || layer.content.trim().is_empty())
+    }
+
+    /// Bounds of the visible layers other than the active one
+    pub fn bounds(&self) -> impl Iterator<Item=[Vec2D<WorldUnit>; 2]> + '_ {
+        let active = self.active;
+
+        self.list.iter().enumerate()
+            .filter(move |&(i, layer)| i != active && layer.visible)
+            .filter_map(|(_, layer)| layer.bounds)
+    }
+}
@@ -0,0 +1,199 @@
+//! The panel that lists the layers of the page, top first. Each row shows and
+//! hides its layer, locks it and takes the selection to it, and clicking a row
+//! makes its layer the one that is drawn on. The buttons below add a layer and
+//! move or remove the active one.
+//!
+//! The panel doesn't change the page itself, it tells what was asked with a
+//! [`LayerAction`].
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::glib;
+use gtk::{Box as GtkBox, Builder, Button, Image, Label, ListBox, ListBoxRow, Orientation, Revealer, ToggleButton};
+use gtk::prelude::*;
+
+use crate::layers::Layers;
+
+/// Something the user asked from the panel. Indices are the ones of
+/// [`Layers::list`], bottom first.
+#[derive(Debug, Clone, Copy)]
+pub enum LayerAction {
+    Activate(usize),
+    SetVisible(usize, bool),
+    SetLocked(usize, bool),
+
+    /// Moves the selected shapes to the layer
+    MoveSelection(usize),
+
+    Add,
+
+    /// Moves the active layer up (`1`) or down (`-1`)
+    Move(isize),
+
+    /// Removes the active layer
+    Remove,
+}
+
+/// What the rows show: name, visibility and lock of every layer, bottom
+/// first, and the active one
+#[derive(Debug, Clone, PartialEq)]
+struct Shown {
+    layers: Vec<(String, bool, bool)>,
+    active: usize,
+}
+
+impl Shown {
+    fn new(layers: &Layers) -> Shown {
+        // a page without layers is a page with one
+        if layers.list.is_empty() {
+            return Shown {
+                layers: vec![(Layers::name(1), true, false)],
+                active: 0,
+            };
+        }
+
+        Shown {
+            layers: layers.list.iter().map(|layer| (layer.name.clone(), layer.visible, layer.locked)).collect(),
+            active: layers.active,
+        }
+    }
+}
+
+pub struct LayersPanel {
+    revealer: Revealer,
+    list: ListBox,
+    remove_btn: Button,
+    up_btn: Button,
+    down_btn: Button,
+    on_action: Rc<dyn Fn(LayerAction)>,
+    shown: RefCell<Option<Shown>>,
+}
+
+/// A button with a symbolic icon that toggles between two states
+fn toggle(active: bool, icons: (&str, &str), tooltip: &str) -> ToggleButton {
+    let btn = ToggleButton::new();
+    let image = Image::from_icon_name(Some(if active { icons.0 } else { icons.1 }), gtk::IconSize::Button);
+
+    btn.set_image(Some(&image));
+    btn.set_active(active);
+    btn.set_can_focus(false);
+    btn.set_relief(gtk::ReliefStyle::None);
+    btn.set_tooltip_text(Some(tooltip));
+
+    btn
+}
+
+impl LayersPanel {
+    pub fn new(builder: &Builder, on_action: impl Fn(LayerAction) + 'static) -> Rc<LayersPanel> {
+        let panel = Rc::new(LayersPanel {
+            revealer: builder.object("layers-panel").expect("no layers panel"),
+            list: builder.object("layers-list").expect("no layers list"),
+            remove_btn: builder.object("layer-delete-btn").expect("no delete layer button"),
+            up_btn: builder.object("layer-up-btn").expect("no layer up button"),
+            down_btn: builder.object("layer-down-btn").expect("no layer down button"),
+            on_action: Rc::new(on_action),
+            shown: RefCell::new(None),
+        });
+        let add_btn: Button = builder.object("layer-add-btn").expect("no add layer button");
+
+        for (btn, action) in [
+            (&add_btn, LayerAction::Add),
+            (&panel.up_btn, LayerAction::Move(1)),
+            (&panel.down_btn, LayerAction::Move(-1)),
+            (&panel.remove_btn, LayerAction::Remove),
+        ] {
+            let on_action = panel.on_action.clone();
+
+            btn.connect_clicked(move |_btn| on_action(action));
+        }
+
+        let weak = Rc::downgrade(&panel);
+
+        panel.list.connect_row_activated(move |_list, row| {
+            if let Some(panel) = weak.upgrade() {
+                let count = panel.shown.borrow().as_ref().map(|shown| shown.layers.len()).unwrap_or(0);
+                let row = row.index();
+
+                if row >= 0 && (row as usize) < count {
+                    (panel.on_action)(LayerAction::Activate(count - 1 - row as usize));
+                }
+            }
+        });
+
+        panel
+    }
+
+    pub fn set_revealed(&self, revealed: bool) {
+        self.revealer.set_reveal_child(revealed);
+    }
+
+    /// Shows the given layers. Called every time the page is drawn, so the
+    /// rows are built again only if something changed, and later as this
+    /// may be called from a handler of the rows.
+    pub fn sync(self: &Rc<Self>, layers: &Layers) {
+        let shown = Shown::new(layers);
+
+        if self.shown.borrow().as_ref() == Some(&shown) {
+            return;
+        }
+
+        *self.shown.borrow_mut() = Some(shown.clone());
+
+        let weak = Rc::downgrade(self);
+
+        glib::idle_add_local_once(move || {
+            if let Some(panel) = weak.upgrade() {
+                panel.build_rows(&shown);
+            }
+        });
+    }
+
+    fn build_rows(&self, shown: &Shown) {
+        for row in self.list.children() {
+            self.list.remove(&row);
+        }
+
+        for (index, (name, visible, locked)) in shown.layers.iter().enumerate().rev() {
+            let row = ListBoxRow::new();
+            let hbox = GtkBox::new(Orientation::Horizontal, 2);
+            let visible_btn = toggle(*visible, ("view-reveal-symbolic", "view-conceal-symbolic"), "Muestra u oculta la capa");
+            let lock_btn = toggle(*locked, ("changes-prevent-symbolic", "changes-allow-symbolic"), "Bloquea la capa para que no se pueda dibujar en ella");
+            let label = Label::new(Some(name.as_str()));
+            let move_btn = Button::from_icon_name(Some("go-jump-symbolic"), gtk::IconSize::Button);
+
+            label.set_xalign(0.0);
+            label.set_hexpand(true);
+            move_btn.set_can_focus(false);
+            move_btn.set_relief(gtk::ReliefStyle::None);
+            move_btn.set_tooltip_text(Some("Mover la selección a esta capa"));
+            move_btn.set_sensitive(index != shown.active && !*locked);
+
+            let on_action = self.on_action.clone();
+            visible_btn.connect_toggled(move |btn| on_action(LayerAction::SetVisible(index, btn.is_active())));
+
+            let on_action = self.on_action.clone();
+            lock_btn.connect_toggled(move |btn| on_action(LayerAction::SetLocked(index, btn.is_active())));
+
+            let on_action = self.on_action.clone();
+            move_btn.connect_clicked(move |_btn| on_action(LayerAction::MoveSelection(index)));
+
+            hbox.pack_start(&visible_btn, false, false, 0);
+            hbox.pack_start(&lock_btn, false, false, 0);
+            hbox.pack_start(&label, true, true, 0);
+            hbox.pack_start(&move_btn, false, false, 0);
+            row.add(&hbox);
+            row.show_all();
+            self.list.add(&row);
+
+            if index == shown.active {
+                self.list.select_row(Some(&row));
+            }
+        }
+
+        let count = shown.layers.len();
+
+        self.remove_btn.set_sensitive(count > 1);
+        self.up_btn.set_sensitive(shown.active + 1 < count);
+        self.down_btn.set_sensitive(shown.active > 0);
+    }
+}
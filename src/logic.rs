@@ -1,5 +1,6 @@
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::fmt;
 use std::fs::File;
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -7,14 +8,24 @@ use std::cell::RefCell;
 use gtk::{
     ApplicationWindow, DrawingArea, FileChooserNative, FileChooserAction,
     ResponseType, HeaderBar, MessageDialog, DialogFlags, MessageType,
-    ButtonsType, Window,
+    ButtonsType, Window, FileFilter,
 };
 use gtk::prelude::*;
-use cairo::{ImageSurface, Context};
+use cairo::{ImageSurface, PdfSurface, PsSurface, Surface, Context, Format};
 
 use pizarra::prelude::*;
 
 use crate::graphics::Drawable;
+use crate::pages::{Pages, split_pages};
+use crate::recent;
+use crate::project;
+use crate::diagnose::{diagnose, Problem};
+use crate::import;
+use crate::images;
+use crate::extras::{self, Extras};
+use crate::fill;
+use crate::clipboard::{self, Pasted};
+use crate::selection::Selector;
 
 fn ensure_extension(filename: &Path, extension: &str) -> PathBuf {
     if let Some(ext) = filename.extension() {
@@ -28,8 +39,9 @@ fn ensure_extension(filename: &Path, extension: &str) -> PathBuf {
     }
 }
 
-pub fn set_subtitle(header_bar: &HeaderBar, save_status: &SaveStatus) {
-    match save_status {
+/// Shows the save status of the document in the header bar
+pub fn set_subtitle(header_bar: &HeaderBar, controller: &Pizarra, pages: &Pages) {
+    match pages.save_status(controller.get_save_status()) {
         SaveStatus::NewAndEmpty => {
             header_bar.set_title(Some("Pizarra"));
             header_bar.set_subtitle(None);
@@ -49,7 +61,7 @@ pub fn set_subtitle(header_bar: &HeaderBar, save_status: &SaveStatus) {
     }
 }
 
-fn dialog(window: &ApplicationWindow, message: &str, msg_type: MessageType) {
+pub fn dialog<P: IsA<Window>>(window: &P, message: &str, msg_type: MessageType) {
     let message_dialog = MessageDialog::new(
         Some(window),
         DialogFlags::DESTROY_WITH_PARENT,
@@ -64,20 +76,35 @@ fn dialog(window: &ApplicationWindow, message: &str, msg_type: MessageType) {
     message_dialog.hide();
 }
 
-fn save_to_svg_logic(controller: Rc<RefCell<Pizarra>>, filename: &Path) -> std::io::Result<()> {
-    let svg_data = controller.borrow_mut().to_svg();
-    let svgfilename = ensure_extension(filename, "svg");
-    let mut svgfile = File::create(&svgfilename)?;
+/// Saves the document to the given file. Files ending in `.pizarra` get a
+/// `.pizarra` file, anything else becomes an svg. Returns the name of the file
+/// that was written, whose extension may not be the given one.
+fn save_logic(controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>, filename: &Path) -> std::io::Result<PathBuf> {
+    let is_project = filename.extension().map(|ext| ext.to_string_lossy().to_lowercase() == project::EXTENSION).unwrap_or(false);
 
-    svgfile.write_all(svg_data.as_bytes())?;
+    let filename = if is_project {
+        project::write(filename, &mut controller.borrow_mut(), &mut pages.borrow_mut())?;
 
-    controller.borrow_mut().set_saved(svgfilename);
+        filename.into()
+    } else {
+        let filename = ensure_extension(filename, "svg");
+        let svg_data = pages.borrow_mut().document_svg(&mut controller.borrow_mut());
+        let mut svgfile = File::create(&filename)?;
 
-    Ok(())
+        svgfile.write_all(svg_data.as_bytes())?;
+
+        filename
+    };
+
+    recent::add(&filename);
+    controller.borrow_mut().set_saved(filename.clone());
+    pages.borrow_mut().mark_saved();
+
+    Ok(filename)
 }
 
-pub fn save_to_svg_logic_with_error_dialg(window: &ApplicationWindow, controller: Rc<RefCell<Pizarra>>, filename: &Path) -> Inhibit {
-    match save_to_svg_logic(controller, filename) {
+pub fn save_logic_with_error_dialog(window: &ApplicationWindow, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>, filename: &Path) -> Inhibit {
+    match save_logic(controller, pages, filename) {
         Ok(_) => Inhibit(false),
         Err(e) => {
             dialog(window, &format!("Falló esto:\n\n{}", e), MessageType::Error);
@@ -88,24 +115,41 @@ pub fn save_to_svg_logic_with_error_dialg(window: &ApplicationWindow, controller
 }
 
 /// Implements the logic of the _save-as_ feature
-fn save_as_logic(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>) -> std::io::Result<()>
+fn save_as_logic(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>) -> std::io::Result<()>
 {
     let save_file_chooser = FileChooserNative::new(Some("Guardar"), Some(window), FileChooserAction::Save, Some("Guardar"), Some("Cancelar"));
+    let pizarra_filter = FileFilter::new();
+    let svg_filter = FileFilter::new();
+
+    pizarra_filter.set_name(Some("Documento de Pizarra"));
+    pizarra_filter.add_pattern(&format!("*.{}", project::EXTENSION));
+    svg_filter.set_name(Some("Imagen SVG"));
+    svg_filter.add_pattern("*.svg");
+    save_file_chooser.add_filter(&svg_filter);
+    save_file_chooser.add_filter(&pizarra_filter);
+
     let res = save_file_chooser.run();
 
     if res == ResponseType::Accept {
         if let Some(filename) = save_file_chooser.filename() {
-            save_to_svg_logic(controller.clone(), &filename)?;
-            set_subtitle(header_bar, controller.borrow().get_save_status());
+            // An extension typed by hand wins over the selected filter
+            let filename = if filename.extension().is_none() && save_file_chooser.filter().as_ref() == Some(&pizarra_filter) {
+                filename.with_extension(project::EXTENSION)
+            } else {
+                filename
+            };
+
+            save_logic(controller.clone(), pages.clone(), &filename)?;
+            set_subtitle(header_bar, &controller.borrow(), &pages.borrow());
         }
     }
 
     Ok(())
 }
 
-pub fn save_as_with_error_dialog(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>) -> Result<(), ()>
+pub fn save_as_with_error_dialog(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>) -> Result<(), ()>
 {
-    match save_as_logic(window, header_bar, controller) {
+    match save_as_logic(window, header_bar, controller, pages) {
         Ok(_) => Ok(()),
         Err(e) => {
             dialog(window, &format!("Falló esto:\n\n{}", e), MessageType::Error);
@@ -115,52 +159,604 @@ pub fn save_as_with_error_dialog(window: &ApplicationWindow, header_bar: &Header
     }
 }
 
+/// Reasons why a drawing could not be loaded from a file
+#[derive(Debug)]
+pub enum OpenError {
+    /// The file could not be opened, most likely it doesn't exist
+    Open(io::Error),
+
+    /// The file exists but its contents could not be read
+    Read(io::Error),
+
+    /// The contents of the file are not a drawing that we understand
+    Parse(ParseError),
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::Open(e) => write!(f, "No pude abrir el archivo: {}", e),
+            OpenError::Read(e) => write!(f, "No pude leer los contenidos del archivo: {}", e),
+            OpenError::Parse(e) => write!(f, "No pude interpretar el formato de este archivo:\n{}", e),
+        }
+    }
+}
+
+/// Pages of a file that the core could not read and why
+#[derive(Debug)]
+pub struct ParseError {
+    /// Problems found in every page that failed, next to the index of the
+    /// page
+    pub problems: Vec<(usize, Problem)>,
+
+    /// Every page of the file with the elements that failed left out, if
+    /// that was enough for all of them to open
+    pub salvaged: Option<Vec<String>>,
+
+    /// Page that would be shown if the file had opened
+    pub current: usize,
+
+    /// How many pages the file has
+    pub pages: usize,
+}
+
+impl ParseError {
+    /// One line per problem, up to `limit` of them
+    pub fn summary(&self, limit: usize) -> String {
+        let mut lines: Vec<_> = self.problems.iter().take(limit).map(|(page, problem)| {
+            if self.pages > 1 {
+                format!("página {}, {}", page + 1, problem)
+            } else {
+                problem.to_string()
+            }
+        }).collect();
+
+        if self.problems.len() > limit {
+            lines.push(format!("…y {} problemas más", self.problems.len() - limit));
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.summary(self.problems.len()))
+    }
+}
+
+/// Loads the given pages showing the one at `current`. If any of them can't
+/// be read the document is left untouched.
+fn load_pages(controller: &mut Pizarra, pages: &mut Pages, svgs: Vec<String>, current: usize) -> Result<(), ParseError> {
+    let config = controller.config();
+    let mut scratch = Pizarra::new(Vec2D::new_screen(1.0, 1.0), config);
+
+    // extras are ours, the core only sees the rest
+    let (stripped, mut page_extras): (Vec<_>, Vec<_>) = svgs.iter().map(|svg| Extras::split(svg, config)).unzip();
+    let failed: Vec<usize> = (0..svgs.len()).filter(|&i| scratch.open(&stripped[i]).is_err()).collect();
+
+    if failed.is_empty() && controller.open(&stripped[current]).is_ok() {
+        pages.replace_at(svgs, current, page_extras.swap_remove(current));
+        pages.restore_tool(controller);
+
+        return Ok(());
+    }
+
+    let mut problems = Vec::new();
+    let mut salvaged = Some(svgs.clone());
+
+    // the current page opened fine in the scratch controller but not in the
+    // real one, so only the core knows
+    if failed.is_empty() {
+        problems.push((current, Problem {
+            element: "svg".into(),
+            line: 1,
+            column: 1,
+            reason: "no se pudo cargar la página".into(),
+        }));
+        salvaged = None;
+    }
+
+    for page in failed {
+        let diagnosis = diagnose(&stripped[page], config);
+
+        problems.extend(diagnosis.problems.into_iter().map(|problem| (page, problem)));
+
+        salvaged = salvaged.zip(diagnosis.salvaged).map(|(mut all, svg)| {
+            all[page] = page_extras[page].embed(&svg);
+            all
+        });
+    }
+
+    Err(ParseError {
+        problems,
+        salvaged,
+        current,
+        pages: svgs.len(),
+    })
+}
+
+/// Reads the given file and loads it in the controller and pages, replacing
+/// whatever was there. `.pizarra` files also bring back the session they were
+/// saved with.
+pub fn open_file(controller: &mut Pizarra, pages: &mut Pages, filename: &Path) -> Result<(), OpenError> {
+    let mut file = File::open(filename).map_err(OpenError::Open)?;
+    let mut contents = Vec::new();
+
+    file.read_to_end(&mut contents).map_err(OpenError::Read)?;
+
+    // the file is recognized by its contents and not its name
+    if project::is_project(&contents) {
+        let project = project::read(&contents).map_err(OpenError::Read)?;
+
+        load_pages(controller, pages, project.pages, project.current_page).map_err(OpenError::Parse)?;
+        project.session.restore(controller, pages);
+
+        return Ok(());
+    }
+
+    let contents = String::from_utf8(contents)
+        .map_err(|e| OpenError::Read(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    let svgs = split_pages(&contents).into_iter().map(String::from).collect();
+
+    load_pages(controller, pages, svgs, 0).map_err(OpenError::Parse)
+}
+
+/// Loads the parts of a file that could be read. The result is a new
+/// document, so saving it doesn't overwrite the original file.
+pub fn open_salvaged(controller: &mut Pizarra, pages: &mut Pages, error: ParseError) -> bool {
+    if let Some(svgs) = error.salvaged {
+        if load_pages(controller, pages, svgs, error.current).is_ok() {
+            pages.mark_changed();
+
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Tells the user what could not be read from a file and asks if the rest
+/// should be imported. Returns `true` if so.
+fn parse_error_dialog(window: &ApplicationWindow, error: &ParseError) -> bool {
+    let message = format!("No pude interpretar algunas partes de este archivo :(\n\n{}", error.summary(8));
+
+    if error.salvaged.is_none() {
+        dialog(window, &message, MessageType::Error);
+
+        return false;
+    }
+
+    let message_dialog = MessageDialog::new(
+        Some(window),
+        DialogFlags::DESTROY_WITH_PARENT,
+        MessageType::Warning,
+        ButtonsType::None,
+        &format!("{}\n\n¿Quieres importar lo que sí pude leer? Quedará como un dibujo nuevo.", message),
+    );
+
+    message_dialog.add_button("Importar", ResponseType::Yes);
+    message_dialog.add_button("Cancelar", ResponseType::Cancel);
+
+    let response = message_dialog.run();
+
+    message_dialog.hide();
+
+    response == ResponseType::Yes
+}
+
+/// Opens the given file in the window, telling the user if something went
+/// wrong.
+pub fn open_path_logic(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>, surface: Rc<RefCell<ImageSurface>>, dwb: Rc<RefCell<DrawingArea>>, filename: &Path) {
+    let ans = { open_file(&mut controller.borrow_mut(), &mut pages.borrow_mut(), filename) };
+
+    match ans {
+        Ok(_) => {
+            recent::add(filename);
+            controller.borrow_mut().set_saved(filename.into());
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+            set_subtitle(header_bar, &controller.borrow(), &pages.borrow());
+        },
+        Err(OpenError::Open(e)) => dialog(window, &format!("No pude abrir ese archivo :(\n\n{}", e), MessageType::Error),
+        Err(OpenError::Read(e)) => dialog(window, &format!("No pude leer los contenidos del archivo :(\n\n{}", e), MessageType::Error),
+        Err(OpenError::Parse(e)) => {
+            if parse_error_dialog(window, &e) && open_salvaged(&mut controller.borrow_mut(), &mut pages.borrow_mut(), e) {
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+                set_subtitle(header_bar, &controller.borrow(), &pages.borrow());
+            }
+        },
+    }
+}
+
 /// Logic of the open dialog
-pub fn open_logic(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>, surface: Rc<RefCell<ImageSurface>>, dwb: Rc<RefCell<DrawingArea>>) {
+pub fn open_logic(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>, surface: Rc<RefCell<ImageSurface>>, dwb: Rc<RefCell<DrawingArea>>) {
     let open_file_chooser = FileChooserNative::new(Some("Abrir"), Some(window), FileChooserAction::Open, Some("Abrir"), Some("Cancelar"));
     let res = open_file_chooser.run();
 
     if res == ResponseType::Accept {
         if let Some(filename) = open_file_chooser.filename() {
-            if let Ok(mut file) = File::open(&filename) {
-                let mut svg = String::new();
-
-                if file.read_to_string(&mut svg).is_ok() {
-                    let ans = { controller.borrow_mut().open(&svg) };
-                    if ans.is_ok() {
-                        controller.borrow_mut().set_saved(filename);
-                        invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
-                        set_subtitle(header_bar, controller.borrow().get_save_status());
-                    } else {
-                        dialog(window, "No pude interpretar el formato de este archivo :(", MessageType::Error);
-                    }
-                } else {
-                    dialog(window, "No pude leer los contenidos del archivo :(", MessageType::Error);
-                }
-            } else {
-                dialog(window, "Ese archivo no existe :(", MessageType::Error);
+            open_path_logic(window, header_bar, controller, pages, surface, dwb, &filename);
+        }
+    }
+}
+
+/// Converts an svg made by another program and adds it to the document as a
+/// new page. If the document is new and empty the drawing takes the place of
+/// its only page instead.
+fn import_file(controller: &mut Pizarra, pages: &mut Pages, filename: &Path) -> Result<import::ImportSummary, String> {
+    let contents = std::fs::read_to_string(filename).map_err(|e| e.to_string())?;
+    let (mut svg, mut summary) = import::convert(&contents).map_err(|e| e.to_string())?;
+    let mut scratch = Pizarra::new(Vec2D::new_screen(1.0, 1.0), controller.config());
+
+    // the converted shapes should always open, but if the core rejects some
+    // of them the rest are still imported
+    if scratch.open(&svg).is_err() {
+        let diagnosis = diagnose(&svg, controller.config());
+
+        svg = diagnosis.salvaged.ok_or_else(|| {
+            diagnosis.problems.iter().map(Problem::to_string).collect::<Vec<_>>().join("\n")
+        })?;
+        summary.shapes -= diagnosis.problems.len().min(summary.shapes);
+        *summary.skipped.entry("path".into()).or_default() += diagnosis.problems.len();
+    }
+
+    if pages.count() == 1 && matches!(pages.save_status(controller.get_save_status()), SaveStatus::NewAndEmpty) {
+        controller.open(&svg).map_err(|e| format!("{:?}", e))?;
+        pages.replace(vec![svg]);
+        pages.mark_changed();
+    } else {
+        pages.insert(controller, svg);
+    }
+
+    Ok(summary)
+}
+
+/// Logic of the _import svg_ dialog
+pub fn import_logic(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>, surface: Rc<RefCell<ImageSurface>>, dwb: Rc<RefCell<DrawingArea>>) {
+    let import_file_chooser = FileChooserNative::new(Some("Importar SVG"), Some(window), FileChooserAction::Open, Some("Importar"), Some("Cancelar"));
+    let filter = FileFilter::new();
+
+    filter.set_name(Some("Imagen SVG"));
+    filter.add_pattern("*.svg");
+    import_file_chooser.add_filter(&filter);
+
+    if import_file_chooser.run() != ResponseType::Accept {
+        return;
+    }
+
+    let filename = match import_file_chooser.filename() {
+        Some(filename) => filename,
+        None => return,
+    };
+
+    let ans = { import_file(&mut controller.borrow_mut(), &mut pages.borrow_mut(), &filename) };
+
+    match ans {
+        Ok(summary) => {
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+            set_subtitle(header_bar, &controller.borrow(), &pages.borrow());
+
+            if !summary.is_complete() {
+                dialog(window, &summary.to_string(), MessageType::Info);
             }
+        },
+        Err(e) => dialog(window, &format!("No pude importar este archivo :(\n\n{}", e), MessageType::Error),
+    }
+}
+
+/// Copies the selection to the clipboard, or the current page if nothing is
+/// selected
+pub fn copy_logic(window: &ApplicationWindow, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>, selector: Rc<RefCell<Selector>>) {
+    let revision = pages.borrow().revision();
+
+    selector.borrow_mut().validate(revision);
+
+    let selected = selector.borrow().selection.as_ref().map(|selection| {
+        selection.only(&fill::core_svg(&controller.borrow()), &pages.borrow().extras)
+    });
+    let copied = match selected {
+        Some((svg, extras)) => {
+            let mut scratch = Pizarra::new(Vec2D::new_screen(1.0, 1.0), controller.borrow().config());
+
+            // the shapes come from the core so it should read them back
+            match scratch.open(&svg) {
+                Ok(_) => clipboard::copy(&mut scratch, &extras),
+                Err(_) => clipboard::copy(&mut controller.borrow_mut(), &pages.borrow().extras),
+            }
+        },
+        None => clipboard::copy(&mut controller.borrow_mut(), &pages.borrow().extras),
+    };
+
+    if let Err(e) = copied {
+        dialog(window, &format!("No pude copiar el dibujo :(\n\n{}", e), MessageType::Error);
+    }
+}
+
+/// Pastes what is in the clipboard centered at the given point of the screen.
+/// Svg becomes shapes of the current page and pictures are inserted as
+/// images.
+pub fn paste_logic(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>, surface: Rc<RefCell<ImageSurface>>, dwb: Rc<RefCell<DrawingArea>>, at: (f64, f64)) {
+    let center = images::to_world(controller.borrow().get_transform(), at);
+
+    match clipboard::paste() {
+        Some(Pasted::Svg(svg)) => {
+            let (svg, mut pasted) = Extras::split(&svg, controller.borrow().config());
+            let (shapes, summary, (dx, dy)) = match import::convert_centered(&svg, center) {
+                Ok(converted) => converted,
+                Err(e) => {
+                    dialog(window, &format!("No pude pegar este dibujo :(\n\n{}", e), MessageType::Error);
+                    return;
+                },
+            };
+
+            pasted.translate(dx, dy);
+
+            if !pages.borrow_mut().merge_into_current(&mut controller.borrow_mut(), &shapes, pasted) {
+                dialog(window, "No pude pegar este dibujo :(", MessageType::Error);
+                return;
+            }
+
+            if !summary.is_complete() {
+                dialog(window, &summary.to_string(), MessageType::Info);
+            }
+        },
+        Some(Pasted::Image(image)) => {
+            let (width, height) = images::view_size(&controller.borrow());
+
+            pages.borrow_mut().add_image(image.fitted(center, width * 0.8, height * 0.8));
+        },
+        None => return,
+    }
+
+    invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+    set_subtitle(header_bar, &controller.borrow(), &pages.borrow());
+}
+
+/// Things that can go wrong while exporting a drawing to an image
+#[derive(Debug)]
+pub enum ExportError {
+    /// The drawing has no shapes, so it has no bounds
+    EmptyDrawing,
+
+    /// Cairo failed to create the image or to write it to disk
+    Io(cairo::IoError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::EmptyDrawing => write!(f, "El dibujo está vacío, no hay nada que exportar"),
+            ExportError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<cairo::Error> for ExportError {
+    fn from(e: cairo::Error) -> ExportError {
+        ExportError::Io(e.into())
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> ExportError {
+        ExportError::Io(e.into())
+    }
+}
+
+impl From<cairo::IoError> for ExportError {
+    fn from(e: cairo::IoError) -> ExportError {
+        ExportError::Io(e)
+    }
+}
+
+/// File formats a drawing can be exported to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExportFormat {
+    Png,
+    Pdf,
+    Ps,
+    Eps,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 4] = [ExportFormat::Png, ExportFormat::Pdf, ExportFormat::Ps, ExportFormat::Eps];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Ps => "ps",
+            ExportFormat::Eps => "eps",
         }
     }
+
+    /// Human readable name, used for the filters of the export dialog
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "Imagen PNG",
+            ExportFormat::Pdf => "Documento PDF",
+            ExportFormat::Ps => "PostScript",
+            ExportFormat::Eps => "PostScript encapsulado (EPS)",
+        }
+    }
+
+    /// Guesses the format from the extension of the given file name
+    pub fn from_path(filename: &Path) -> Option<ExportFormat> {
+        let ext = filename.extension()?.to_string_lossy().to_lowercase();
+
+        ExportFormat::ALL.iter().copied().find(|f| f.extension() == ext)
+    }
+}
+
+/// A page to export: its shapes and its extras
+pub type ExportPage<'a> = (&'a Pizarra, &'a Extras);
+
+fn page_size(bounds: [Vec2D<WorldUnit>; 2], padding: WorldUnit, scale: f64) -> (f64, f64) {
+    let [topleft, bottomright] = bounds;
+    let dimensions = ((bottomright - topleft).abs() + Vec2D::new(padding * 2.0.into(), padding * 2.0.into())) * scale;
+
+    (dimensions.x.val().ceil(), dimensions.y.val().ceil())
+}
+
+/// Renders a page to the surface and returns the context used, so multi-page
+/// surfaces can emit the page.
+fn render_page(surface: &Surface, (controller, extras): ExportPage, topleft: Vec2D<WorldUnit>, padding: WorldUnit, scale: f64) -> Result<Context, ExportError> {
+    let context = Context::new(surface)?;
+
+    context.scale(scale, scale);
+
+    render_drawing(controller, extras, &context, topleft, padding);
+
+    Ok(context)
+}
+
+/// Writes a single page to a file in a format that only holds one page
+fn export_single_page(page: ExportPage, bounds: [Vec2D<WorldUnit>; 2], filename: &Path, format: ExportFormat, padding: WorldUnit, scale: f64) -> Result<(), ExportError> {
+    let (width, height) = page_size(bounds, padding, scale);
+
+    if format == ExportFormat::Eps {
+        let surface = PsSurface::new(width, height, filename)?;
+
+        surface.set_eps(true);
+        render_page(&surface, page, bounds[0], padding, scale)?;
+        surface.finish();
+        surface.status()?;
+    } else {
+        let surface = ImageSurface::create(Format::ARgb32, width as i32, height as i32)?;
+
+        render_page(&surface, page, bounds[0], padding, scale)?;
+        surface.write_to_png(&mut File::create(filename)?)?;
+    }
+
+    Ok(())
+}
+
+/// Renders a page as a PNG image in memory, like the ones written by
+/// [`export_drawing`], for example to put it in the clipboard.
+pub fn render_png(page: ExportPage, padding: WorldUnit, scale: f64) -> Result<Vec<u8>, ExportError> {
+    let bounds = extras::page_bounds(page.0, page.1).ok_or(ExportError::EmptyDrawing)?;
+    let (width, height) = page_size(bounds, padding, scale);
+    let surface = ImageSurface::create(Format::ARgb32, width as i32, height as i32)?;
+    let mut png = Vec::new();
+
+    render_page(&surface, page, bounds[0], padding, scale)?;
+    surface.write_to_png(&mut png)?;
+
+    Ok(png)
+}
+
+/// `dibujo.png` becomes `dibujo-3.png` for the third page
+fn numbered_filename(filename: &Path, number: usize) -> PathBuf {
+    let stem = filename.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut numbered = format!("{}-{}", stem, number);
+
+    if let Some(ext) = filename.extension() {
+        numbered.push('.');
+        numbered.push_str(&ext.to_string_lossy());
+    }
+
+    filename.with_file_name(numbered)
+}
+
+/// Writes the given pages to a file in the given format. Each page is sized to
+/// the bounds of its drawing plus `padding` at every side, everything
+/// multiplied by `scale`. PNG dimensions are in pixels while the rest are
+/// vector formats whose dimensions are in points.
+///
+/// PDF and PostScript produce a single document with a page for each board,
+/// PNG and EPS produce a numbered file for each board. Empty boards are
+/// skipped.
+pub fn export_drawing(pages: &[ExportPage], filename: &Path, format: ExportFormat, padding: WorldUnit, scale: f64) -> Result<(), ExportError> {
+    let pages: Vec<_> = pages.iter().filter_map(|&page| extras::page_bounds(page.0, page.1).map(|bounds| (page, bounds))).collect();
+
+    if pages.is_empty() {
+        return Err(ExportError::EmptyDrawing);
+    }
+
+    match format {
+        ExportFormat::Png | ExportFormat::Eps => {
+            if let [(page, bounds)] = pages[..] {
+                export_single_page(page, bounds, filename, format, padding, scale)?;
+            } else {
+                for (i, &(page, bounds)) in pages.iter().enumerate() {
+                    export_single_page(page, bounds, &numbered_filename(filename, i + 1), format, padding, scale)?;
+                }
+            }
+        },
+        ExportFormat::Pdf => {
+            let (width, height) = page_size(pages[0].1, padding, scale);
+            let surface = PdfSurface::new(width, height, filename)?;
+
+            for &(page, bounds) in pages.iter() {
+                let (width, height) = page_size(bounds, padding, scale);
+
+                surface.set_size(width, height)?;
+                render_page(&surface, page, bounds[0], padding, scale)?.show_page()?;
+            }
+
+            surface.finish();
+            surface.status()?;
+        },
+        ExportFormat::Ps => {
+            let (width, height) = page_size(pages[0].1, padding, scale);
+            let surface = PsSurface::new(width, height, filename)?;
+
+            for &(page, bounds) in pages.iter() {
+                let (width, height) = page_size(bounds, padding, scale);
+
+                surface.set_size(width, height);
+                render_page(&surface, page, bounds[0], padding, scale)?.show_page()?;
+            }
+
+            surface.finish();
+            surface.status()?;
+        },
+    }
+
+    Ok(())
 }
 
 /// Implements the logic of the export feature
-pub fn export_logic<P: IsA<Window>>(window: &P, controller: Rc<RefCell<Pizarra>>) {
+pub fn export_logic<P: IsA<Window>>(window: &P, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>) {
     let export_file_chooser = FileChooserNative::new(Some("Exportar"), Some(window), FileChooserAction::Save, Some("Exportar"), Some("Cancelar"));
+    let filters: Vec<_> = ExportFormat::ALL.iter().map(|&format| {
+        let filter = FileFilter::new();
+
+        filter.set_name(Some(format.name()));
+        filter.add_pattern(&format!("*.{}", format.extension()));
+        export_file_chooser.add_filter(&filter);
+
+        (filter, format)
+    }).collect();
+
     let res = export_file_chooser.run();
 
     if res == ResponseType::Accept {
         if let Some(filename) = export_file_chooser.filename() {
-            if let Some([topleft, bottomright]) = controller.borrow().get_bounds() {
-                let export_padding = controller.borrow().config().export_padding;
-                let pngfilename = ensure_extension(&filename, "png");
-                let dimensions = (bottomright - topleft).abs() + Vec2D::new(export_padding * 2.0.into(), export_padding * 2.0.into());
-                let surface = ImageSurface::create(cairo::Format::ARgb32, dimensions.x.val() as i32, dimensions.y.val() as i32).unwrap();
-                let context = cairo::Context::new(&surface).unwrap();
-
-                render_drawing(&controller.borrow(), &context, topleft);
-
-                surface.write_to_png(&mut File::create(pngfilename).unwrap()).unwrap();
+            // An extension typed by hand wins over the selected filter
+            let (filename, format) = if let Some(format) = ExportFormat::from_path(&filename) {
+                (filename, format)
+            } else {
+                let selected = export_file_chooser.filter();
+                let format = filters.iter()
+                    .find(|(filter, _)| Some(filter) == selected.as_ref())
+                    .map(|&(_, format)| format)
+                    .unwrap_or(ExportFormat::Png);
+
+                (ensure_extension(&filename, format.extension()), format)
+            };
+
+            let controller = controller.borrow();
+            let pages = pages.borrow();
+            let export_padding = controller.config().export_padding;
+            let others = pages.other_pages(controller.config());
+            let all: Vec<ExportPage> = others.iter().map(|page| match page {
+                Some((other, extras)) => (other, extras),
+                None => (&*controller, &pages.extras),
+            }).collect();
+
+            if let Err(e) = export_drawing(&all, &filename, format, export_padding, 1.0) {
+                dialog(window, &format!("No pude exportar el dibujo:\n\n{}", e), MessageType::Error);
             }
         }
     }
@@ -171,7 +767,7 @@ pub fn export_logic<P: IsA<Window>>(window: &P, controller: Rc<RefCell<Pizarra>>
 ///
 /// Called on translate or rotate but not during the drawing phase of a new
 /// shape
-pub fn invalidate_and_redraw(controller: &Pizarra, surface: &RefCell<ImageSurface>, dw: &DrawingArea) {
+pub fn invalidate_and_redraw(controller: &Pizarra, extras: &Extras, surface: &RefCell<ImageSurface>, dw: &DrawingArea) {
     let t = controller.get_transform();
     let commands = controller.draw_commands_for_screen();
     let p = controller.get_dimensions();
@@ -187,29 +783,48 @@ pub fn invalidate_and_redraw(controller: &Pizarra, surface: &RefCell<ImageSurfac
     context.set_source_rgb(bgcolor.float_r(), bgcolor.float_g(), bgcolor.float_b());
     context.paint().unwrap();
 
-    // content
-    for cmd in commands {
-        cmd.draw(&context, t);
+    extras.layers.draw_beneath(&context, t);
+
+    // the active layer, unless it is hidden
+    if extras.layers.active_visible() {
+        extras.draw_beneath(&context, t);
+
+        for cmd in commands {
+            cmd.draw(&context, t);
+        }
+
+        extras.draw_above(&context, t);
     }
 
+    extras.layers.draw_above(&context, t);
+
     surface.replace(new_surface);
 
     dw.queue_draw();
 }
 
-/// Renders the entire drawing to a cairo context. Used for exporting to png and
-/// potentially other formats.
-fn render_drawing(controller: &Pizarra, ctx: &Context, topleft: Vec2D<WorldUnit>) {
-    let export_padding = controller.config().export_padding;
+/// Renders the entire drawing to a cairo context leaving `padding` space
+/// around it. Used for exporting to any of the [`ExportFormat`]s.
+pub fn render_drawing(controller: &Pizarra, extras: &Extras, ctx: &Context, topleft: Vec2D<WorldUnit>, padding: WorldUnit) {
     let t = Transform::new_translate(
-        ((topleft - Vec2D::new(export_padding, export_padding)) * -1.0).to_vec2d()
+        ((topleft - Vec2D::new(padding, padding)) * -1.0).to_vec2d()
     );
     let bgcolor = controller.bgcolor();
 
     ctx.set_source_rgb(bgcolor.float_r(), bgcolor.float_g(), bgcolor.float_b());
     ctx.paint().unwrap();
 
-    for cmd in controller.draw_commands_for_drawing() {
-        cmd.draw(ctx, t);
+    extras.layers.draw_beneath(ctx, t);
+
+    if extras.layers.active_visible() {
+        extras.draw_beneath(ctx, t);
+
+        for cmd in controller.draw_commands_for_drawing() {
+            cmd.draw(ctx, t);
+        }
+
+        extras.draw_above(ctx, t);
     }
+
+    extras.layers.draw_above(ctx, t);
 }
@@ -1,18 +1,20 @@
 #![windows_subsystem = "windows"]
 use std::rc::Rc;
-use std::cell::RefCell;
-use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use std::env;
+use std::fs;
 
 use gtk::{
     Application, ApplicationWindow, DrawingArea, Builder, ColorButton,
     Button, MenuItem, FileChooserNative, FileChooserAction, ResponseType,
     HeaderBar, MessageDialog, DialogFlags, MessageType, ButtonsType, Window,
-    ScaleButton, AboutDialog, Image,
+    ScaleButton, AboutDialog, Label, Menu, SeparatorMenuItem, FileFilter,
+    GestureZoom, GestureRotate, CheckMenuItem, Widget, FontButton,
+    ToggleButton,
 };
-use gdk::{EventMask, EventType, ModifierType, DeviceToolType};
+use gdk::{EventMask, EventType, ModifierType, DeviceToolType, AxisUse, InputSource};
 use gtk::prelude::*;
 use gio::ApplicationFlags;
 use glib::clone;
@@ -23,13 +25,64 @@ use pizarra::prelude::*;
 mod graphics;
 mod logic;
 mod config;
+mod cli;
+mod pages;
+mod autosave;
+mod recent;
+mod tools;
+mod project;
+mod diagnose;
+mod import;
+mod images;
+mod clipboard;
+mod extras;
+mod ink;
+mod touch;
+mod view;
+mod buttons;
+mod shortcuts;
+mod presentation;
+mod laser;
+mod highlight;
+mod text;
+mod selection;
+mod fill;
+mod layers;
+mod layers_panel;
+mod palette;
 
 use graphics::Drawable;
 use logic::*;
+use pages::Pages;
+use autosave::Autosave;
+use images::{ImageEditor, PlacedImage};
+use ink::{InkPoint, InkStroke};
+use config::PressureSettings;
+use buttons::ButtonAction;
+use tools::ToolEntry;
+use presentation::Presentation;
+use laser::Laser;
+use highlight::HighlightStroke;
+use text::{TextBox, TextStyle};
+use selection::{Restyle, Selector, SelectionEdit};
+use layers_panel::{LayersPanel, LayerAction};
+use palette::PaletteBar;
 
 const UNSAVED_CHANGES_SINCE_LAST_TIME: &str = "Hiciste algunos trazos desde la última vez\n\n¿Los quieres guardar?";
 const UNSAVED_CHANGES_NEW_FILE: &str = "Hay algunos trazos aquí\n\n¿Los quieres guardar?";
 
+/// Describes how long ago something happened, like `hace 5 minutos`
+fn time_ago(time: SystemTime) -> String {
+    let minutes = SystemTime::now().duration_since(time).unwrap_or_default().as_secs() / 60;
+
+    match minutes {
+        0 => "hace un momento".into(),
+        1..=59 => format!("hace {} minutos", minutes),
+        60..=1439 => format!("hace {} horas", minutes / 60),
+        _ => format!("hace {} días", minutes / 1440),
+    }
+}
+
 fn yes_no_cancel_dialog<F, G, H>(window: &ApplicationWindow, message: &str, yes_callback: F, no_callback: G, cancel_callback: H) -> Inhibit
     where
     F: Fn() -> Inhibit,
@@ -90,35 +143,378 @@ fn gtk_tool(tool: DeviceToolType) -> Option<SelectedTool> {
     }
 }
 
+/// The point of a pressure stroke under the pen, with its width already
+/// computed from the pressure and tilt of the pen.
+fn ink_point(controller: &Pizarra, pages: &Pages, settings: &PressureSettings, position: (f64, f64), pressure: f64, tilt: Option<(f64, f64)>) -> InkPoint {
+    let (x, y) = images::to_world(controller.get_transform(), position);
+
+    InkPoint {
+        x,
+        y,
+        width: ink::width(settings, pages.stroke, pressure, tilt),
+    }
+}
+
+/// Types in the text box being edited. Returns `true` if the key was used.
+fn type_text(pages: &mut Pages, event: &gdk::EventKey) -> bool {
+    if event.state().intersects(ModifierType::CONTROL_MASK | ModifierType::MOD1_MASK) {
+        return false;
+    }
+
+    let keyval = event.keyval();
+    let name = keyval.name();
+
+    if name.as_deref() == Some("Escape") {
+        pages.finish_text();
+
+        return true;
+    }
+
+    let text = match pages.editing_text_mut() {
+        Some(text) => text,
+        None => return false,
+    };
+
+    match name.as_deref() {
+        Some("BackSpace") => {
+            text.text.pop();
+        },
+        Some("Return" | "KP_Enter") => text.text.push('\n'),
+        _ => match keyval.to_unicode().filter(|c| !c.is_control()) {
+            Some(c) => text.text.push(c),
+            None => return false,
+        },
+    }
+
+    pages.mark_changed();
+
+    true
+}
+
+/// Asks to save the changes of the document, if there are any, before it is
+/// replaced by something else. Returns `false` if the user cancelled or the
+/// document could not be saved. Like opening always did, a document that
+/// has a file is only replaced if its changes are saved.
+fn save_changes_first(window: &ApplicationWindow, header_bar: &HeaderBar, controller: Rc<RefCell<Pizarra>>, pages: Rc<RefCell<Pages>>) -> bool {
+    let save_status = pages.borrow().save_status(controller.borrow().get_save_status());
+
+    let inhibit = match save_status {
+        SaveStatus::NewAndEmpty | SaveStatus::Saved(_) => Inhibit(false),
+        SaveStatus::NewAndChanged => yes_no_cancel_dialog(window, UNSAVED_CHANGES_NEW_FILE, || {
+            if save_as_with_error_dialog(window, header_bar, controller.clone(), pages.clone()).is_ok() {
+                Inhibit(false)
+            } else {
+                Inhibit(true)
+            }
+        }, || {
+            Inhibit(false)
+        }, || {
+            Inhibit(true)
+        }),
+        SaveStatus::Unsaved(path) => yes_no_cancel_dialog(window, UNSAVED_CHANGES_SINCE_LAST_TIME, || {
+            save_logic_with_error_dialog(window, controller.clone(), pages.clone(), &path)
+        }, || {
+            Inhibit(true)
+        }, || {
+            Inhibit(true)
+        }),
+    };
+
+    inhibit == Inhibit(false)
+}
+
+/// Replaces the items of the _open recent_ menu with the current list of
+/// recent files.
+fn fill_recent_menu(menu: &Menu, length: usize, open: Rc<dyn Fn(&Path)>) {
+    for child in menu.children() {
+        menu.remove(&child);
+    }
+
+    let files = recent::list();
+
+    if files.is_empty() {
+        let empty = MenuItem::with_label("No hay archivos recientes");
+
+        empty.set_sensitive(false);
+        menu.append(&empty);
+    }
+
+    for path in files.into_iter().take(length) {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let item = MenuItem::with_label(&name);
+
+        item.set_tooltip_text(Some(&path.display().to_string()));
+        item.connect_activate(clone!(@strong open => move |_item| {
+            open(&path);
+        }));
+        menu.append(&item);
+    }
+
+    menu.append(&SeparatorMenuItem::new());
+
+    let clear = MenuItem::with_label("Limpiar lista");
+
+    clear.connect_activate(|_item| {
+        recent::clear();
+    });
+    menu.append(&clear);
+
+    menu.show_all();
+}
+
+fn set_page_label(label: &Label, pages: &Pages) {
+    label.set_text(&format!("{}/{}", pages.current() + 1, pages.count()));
+}
+
+/// Makes the tool, thickness, alpha, color and fill widgets show what is
+/// selected, for when it changed without the user touching them, like when
+/// opening a `.pizarra` file.
+fn sync_tool_widgets(builder: &Builder, controller: &RefCell<Pizarra>, pages: &RefCell<Pages>) {
+    let tool_btn: Button = builder.object("tool-menu-btn").unwrap();
+    let presentation_tool_btn: Button = builder.object("presentation-tool-btn").unwrap();
+    let thickness_btn: ScaleButton = builder.object("thickness-scale").unwrap();
+    let alpha_btn: ScaleButton = builder.object("alpha-scale").unwrap();
+    let color_chooser: ColorButton = builder.object("color-chooser").unwrap();
+    let presentation_color_chooser: ColorButton = builder.object("presentation-color-chooser").unwrap();
+    let font_chooser: FontButton = builder.object("font-chooser").unwrap();
+    let fill_toggle: ToggleButton = builder.object("fill-toggle").unwrap();
+    let fill_chooser: ColorButton = builder.object("fill-chooser").unwrap();
+
+    // read before touching the widgets, as their handlers change the
+    // controller and pages
+    let (tool, color, stroke) = {
+        let pages = pages.borrow();
+
+        if pages.tool == tools::HIGHLIGHTER_TOOL {
+            (pages.tool, pages.highlighter.color(), pages.highlighter.width)
+        } else {
+            (pages.tool, controller.borrow().selected_color(), pages.stroke)
+        }
+    };
+    let rgba = gdk::RGBA::new(color.float_r(), color.float_g(), color.float_b(), 1.0);
+    let font = pages.borrow().text_style.font_name();
+    let fill = pages.borrow().fill.clone();
+    let [r, g, b] = fill.color;
+
+    if let Some(entry) = ToolEntry::by_name(tool) {
+        tool_btn.set_image(Some(&entry.image()));
+        presentation_tool_btn.set_image(Some(&entry.image()));
+    }
+
+    thickness_btn.set_value(stroke);
+    alpha_btn.set_value(color.alpha() as f64 / 255.0);
+    color_chooser.set_rgba(&rgba);
+    presentation_color_chooser.set_rgba(&rgba);
+    font_chooser.set_font(&font);
+    font_chooser.set_visible(tool == tools::TEXT_TOOL);
+    fill_toggle.set_active(fill.enabled);
+    fill_chooser.set_rgba(&gdk::RGBA::new(r, g, b, fill.alpha as f64 / 255.0));
+    fill_chooser.set_sensitive(fill.enabled);
+}
+
+/// Draws with the color of a color chooser, keeping the alpha
+fn choose_color(controller: &RefCell<Pizarra>, pages: &RefCell<Pages>, rgba: gdk::RGBA) {
+    if pages.borrow().tool == tools::HIGHLIGHTER_TOOL {
+        pages.borrow_mut().highlighter.color = [rgba.red(), rgba.green(), rgba.blue()];
+
+        return;
+    }
+
+    let prev_alpha = controller.borrow().selected_color().alpha();
+    let color = Color::from_float_rgb(rgba.red(), rgba.green(), rgba.blue()).with_alpha(prev_alpha);
+
+    controller.borrow_mut().set_color(color);
+
+    if let Some(text) = pages.borrow_mut().editing_text_mut() {
+        text.color = color;
+    }
+}
+
+/// Forgets the selection if the page changed since it was made
+fn validate_selection(pages: &RefCell<Pages>, selector: &RefCell<Selector>) {
+    if selector.borrow().is_idle() {
+        selector.borrow_mut().validate(pages.borrow().revision());
+    }
+}
+
+/// Applies a change to the selected shapes as a single step of the history
+/// and selects them again where they ended. Returns `false` if nothing
+/// changed.
+fn edit_selection(controller: &RefCell<Pizarra>, pages: &RefCell<Pages>, selector: &RefCell<Selector>, edit: SelectionEdit) -> bool {
+    let core_svg = fill::core_svg(&controller.borrow());
+    let edited = selector.borrow().selection.as_ref().and_then(|selection| selection.apply(&core_svg, &pages.borrow().extras, edit));
+    let (svg, extras, selected) = match edited {
+        Some(edited) => edited,
+        None => return false,
+    };
+    let restyle = match edit {
+        SelectionEdit::Restyle(restyle) => Some(restyle),
+        _ => None,
+    };
+    let amend = restyle.zip(selector.borrow().restyled).map(|(new, last)| new.same_kind(&last)).unwrap_or(false);
+    let replaced = if amend {
+        pages.borrow_mut().amend_current(&mut controller.borrow_mut(), svg, extras)
+    } else {
+        pages.borrow_mut().replace_current(&mut controller.borrow_mut(), svg, extras)
+    };
+
+    if !replaced {
+        return false;
+    }
+
+    let core_svg = fill::core_svg(&controller.borrow());
+    let revision = pages.borrow().revision();
+
+    selector.borrow_mut().select_last(revision, &core_svg, &pages.borrow().extras, selected);
+    selector.borrow_mut().restyled = restyle;
+
+    true
+}
+
+/// Gives the selected shapes the color, thickness or alpha just chosen in
+/// the header bar. Returns `false` if nothing is selected.
+fn restyle_selection(controller: &RefCell<Pizarra>, pages: &RefCell<Pages>, selector: &RefCell<Selector>, restyle: Restyle) -> bool {
+    if pages.borrow().tool != tools::SELECTION_TOOL {
+        return false;
+    }
+
+    validate_selection(pages, selector);
+
+    edit_selection(controller, pages, selector, SelectionEdit::Restyle(restyle))
+}
+
+/// Takes the selected shapes and strokes out of the active layer and puts
+/// them on top of another one. Returns `false` if nothing moved.
+fn move_selection_to_layer(controller: &RefCell<Pizarra>, pages: &RefCell<Pages>, selector: &RefCell<Selector>, index: usize) -> bool {
+    validate_selection(pages, selector);
+
+    let core_svg = fill::core_svg(&controller.borrow());
+    let moved = selector.borrow().selection.as_ref().and_then(|selection| {
+        let pages = pages.borrow();
+        let (svg, extras, _) = selection.apply(&core_svg, &pages.extras, SelectionEdit::Delete)?;
+        let (only_svg, only_extras) = selection.only(&core_svg, &pages.extras);
+
+        Some(((svg, extras), layers::active_content(&only_svg, &only_extras)))
+    });
+    let (rest, moved) = match moved {
+        Some(moved) => moved,
+        None => return false,
+    };
+
+    selector.borrow_mut().clear();
+
+    pages.borrow_mut().move_to_layer(&mut controller.borrow_mut(), rest, &moved, index)
+}
+
+/// Updates everything that depends on the page being shown
+fn page_changed(controller: &Pizarra, pages: &Pages, surface: &RefCell<ImageSurface>, dw: &DrawingArea, header_bar: &HeaderBar, label: &Label) {
+    invalidate_and_redraw(controller, &pages.extras, surface, dw);
+    set_subtitle(header_bar, controller, pages);
+    set_page_label(label, pages);
+}
+
 fn init(app: &Application, filename: Option<PathBuf>) {
     // Initialize layout from .glade file
     let builder = Builder::from_resource("/tk/categulario/pizarra/pizarra.glade");
-    let controller = Rc::new(RefCell::new(Pizarra::new(Vec2D::new_screen(1.0, 1.0), config::read())));
+    let (settings, mut settings_problems) = config::read_settings();
+    let core_config = config::read().unwrap_or_else(|e| {
+        // a file that can't be read at all was already reported
+        if !settings_problems.contains(&e) {
+            settings_problems.push(e);
+        }
+
+        Default::default()
+    });
+    let controller = Rc::new(RefCell::new(Pizarra::new(Vec2D::new_screen(1.0, 1.0), core_config)));
+    let pages = Rc::new(RefCell::new(Pages::new()));
     let window: ApplicationWindow = builder.object("main-window").expect("Couldn't get window");
     let header_bar: HeaderBar = builder.object("header-bar").expect("no header bar");
     let surface = Rc::new(RefCell::new(ImageSurface::create(cairo::Format::ARgb32, 1, 1).unwrap()));
     let about_dialog: AboutDialog = builder.object("about-dialog").unwrap();
-    let tool_btn: Button = builder.object("tool-menu-btn").unwrap();
+    let page_label: Label = builder.object("page-label").unwrap();
 
     window.set_application(Some(app));
 
-    if let Some(filename) = filename {
-        let mut svg = String::new();
-        let mut file = File::open(&filename).expect("Could not open given file");
+    // Keyboard shortcuts, problems with them are shown once the window is
+    // visible
+    let (shortcut_list, shortcut_problems) = shortcuts::resolve(&settings.shortcuts.0);
 
-        file.read_to_string(&mut svg).expect("could not read file contents");
-        {
-            let mut controller = controller.borrow_mut();
-            controller.open(&svg).expect("Could not parse given file");
-            controller.set_saved(filename);
+    shortcuts::install(&builder, &window, &shortcut_list);
+
+    // Typing in a text box. Goes before the shortcuts as most of them are
+    // plain letters
+    window.connect_key_press_event(clone!(@strong controller, @strong pages, @strong surface, @strong header_bar, @strong builder => move |_window, event| {
+        let editing = pages.borrow().editing_text().is_some();
+
+        if !editing || !type_text(&mut pages.borrow_mut(), event) {
+            return Inhibit(false);
+        }
+
+        let dw: DrawingArea = builder.object("drawing-area").expect("No drawing_area");
+
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dw);
+        set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+
+        Inhibit(true)
+    }));
+
+    // Fullscreen and presentation
+    let presentation = Presentation::new(&builder);
+
+    window.connect_window_state_event(clone!(@strong presentation => move |_window, event| {
+        presentation.set_fullscreen(event.new_window_state().contains(gdk::WindowState::FULLSCREEN));
+
+        Inhibit(false)
+    }));
+
+    window.connect_key_press_event(clone!(@strong presentation, @strong builder => move |_window, event| {
+        if !presentation.is_active() {
+            return Inhibit(false);
+        }
+
+        if event.keyval() == gdk::keys::constants::Escape {
+            presentation.exit();
+
+            return Inhibit(true);
+        }
+
+        match shortcuts::widget_for(&shortcut_list, *event.keyval(), event.state()) {
+            Some(id) => {
+                builder.object::<Widget>(id).expect("no widget for shortcut").activate();
+
+                Inhibit(true)
+            },
+            None => Inhibit(false),
         }
-        set_subtitle(&header_bar, controller.borrow().get_save_status());
+    }));
+
+    let fullscreen_menu: MenuItem = builder.object("fullscreen-btn").expect("no fullscreen menu");
+    fullscreen_menu.connect_activate(clone!(@strong presentation => move |_menu| {
+        presentation.toggle_fullscreen();
+    }));
+
+    let presentation_menu: MenuItem = builder.object("presentation-btn").expect("no presentation menu");
+    presentation_menu.connect_activate(clone!(@strong presentation => move |_menu| {
+        presentation.toggle();
+    }));
+
+    let presentation_exit_btn: Button = builder.object("presentation-exit-btn").expect("no presentation exit button");
+    presentation_exit_btn.connect_clicked(clone!(@strong presentation => move |_btn| {
+        presentation.exit();
+    }));
+
+    for (id, menu) in [("presentation-undo-btn", "undo-btn"), ("presentation-redo-btn", "redo-btn")] {
+        let btn: Button = builder.object(id).expect("no presentation button");
+
+        btn.connect_clicked(clone!(@strong builder => move |_btn| {
+            builder.object::<MenuItem>(menu).expect("no menu").emit_activate();
+        }));
     }
 
     // save on exit
-    window.connect_delete_event(clone!(@strong controller, @strong window => move |_window, _event| {
+    window.connect_delete_event(clone!(@strong controller, @strong pages, @strong window => move |_window, _event| {
         let old_save_status = {
-            controller.borrow().get_save_status().clone()
+            pages.borrow().save_status(controller.borrow().get_save_status())
         };
 
         match old_save_status {
@@ -129,7 +525,7 @@ fn init(app: &Application, filename: Option<PathBuf>) {
                 match res {
                     ResponseType::Accept => {
                         if let Some(filename) = save_file_chooser.filename() {
-                            save_to_svg_logic_with_error_dialg(&window, controller.clone(), &filename)
+                            save_logic_with_error_dialog(&window, controller.clone(), pages.clone(), &filename)
                         } else {
                             Inhibit(true)
                         }
@@ -146,7 +542,7 @@ fn init(app: &Application, filename: Option<PathBuf>) {
             SaveStatus::NewAndEmpty => Inhibit(false),
             SaveStatus::Saved(_path) => Inhibit(false),
             SaveStatus::Unsaved(path) => yes_no_cancel_dialog(&window, "Hay cambios desde la última vez que guardaste\n\n¿Quieres guardarlos?", || {
-                    save_to_svg_logic_with_error_dialg(&window, controller.clone(), &path)
+                    save_logic_with_error_dialog(&window, controller.clone(), pages.clone(), &path)
                 }, || {
                     Inhibit(false)
                 }, || {
@@ -156,6 +552,104 @@ fn init(app: &Application, filename: Option<PathBuf>) {
     }));
 
     // Drawing area
+    let image_editor = Rc::new(RefCell::new(ImageEditor::default()));
+    let selector = Rc::new(RefCell::new(Selector::default()));
+
+    // Layers
+    let layers_panel = LayersPanel::new(&builder, clone!(@strong controller, @strong pages, @strong surface, @strong header_bar, @strong selector, @strong builder => move |action| {
+        let changed = match action {
+            LayerAction::Activate(index) => pages.borrow_mut().activate_layer(&mut controller.borrow_mut(), index),
+            LayerAction::MoveSelection(index) => move_selection_to_layer(&controller, &pages, &selector, index),
+            LayerAction::SetVisible(index, visible) => pages.borrow_mut().edit_layers(&mut controller.borrow_mut(), |layers| layers.set_visible(index, visible)),
+            LayerAction::SetLocked(index, locked) => pages.borrow_mut().edit_layers(&mut controller.borrow_mut(), |layers| layers.set_locked(index, locked)),
+            LayerAction::Add => pages.borrow_mut().edit_layers(&mut controller.borrow_mut(), |layers| layers.add()),
+            LayerAction::Move(offset) => pages.borrow_mut().edit_layers(&mut controller.borrow_mut(), |layers| layers.move_layer(layers.active, offset)),
+            LayerAction::Remove => pages.borrow_mut().edit_layers(&mut controller.borrow_mut(), |layers| layers.remove(layers.active)),
+        };
+
+        if changed {
+            let dw: DrawingArea = builder.object("drawing-area").expect("No drawing_area");
+
+            // what was selected may be in another layer now
+            selector.borrow_mut().clear();
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dw);
+            set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        }
+    }));
+
+    let layers_menu: CheckMenuItem = builder.object("layers-btn").expect("no layers menu");
+    layers_menu.connect_toggled(clone!(@strong layers_panel => move |item| {
+        layers_panel.set_revealed(item.is_active());
+    }));
+
+    // last position of the pointer over the canvas, where things are pasted
+    let pointer: Rc<Cell<Option<(f64, f64)>>> = Rc::new(Cell::new(None));
+
+    // the stroke being drawn with a pen that reports pressure
+    let ink_stroke: Rc<RefCell<Option<InkStroke>>> = Rc::new(RefCell::new(None));
+    let pressure_settings = Rc::new(settings.pressure.clone());
+    let laser = Rc::new(RefCell::new(Laser::new(settings.laser.clone())));
+
+    // the stroke being drawn with the highlighter
+    let highlight_stroke: Rc<RefCell<Option<HighlightStroke>>> = Rc::new(RefCell::new(None));
+
+    // two finger gestures and the last position of the finger or button that
+    // pans
+    let gestures = Rc::new(RefCell::new(touch::Gestures::default()));
+    let finger_pan: Rc<Cell<Option<(f64, f64)>>> = Rc::new(Cell::new(None));
+    let draw_with_finger = Rc::new(Cell::new(settings.touch.draw_with_finger));
+    let palm = Rc::new(RefCell::new(touch::PalmRejection::new(settings.touch.palm_rejection)));
+
+    // Extra buttons of mice, pens and tablet pads
+    let bindings = Rc::new(RefCell::new(settings.buttons.0.clone()));
+
+    // Palette and recent colors of the header bar. Buttons can go through
+    // the colors of the palette too.
+    let palette_bar = PaletteBar::new(&builder, &settings.palette);
+
+    // the tool to go back to when the button that erases is released
+    let held_tool: Rc<Cell<Option<&'static str>>> = Rc::new(Cell::new(None));
+    let run_action: buttons::RunAction = Rc::new(clone!(@strong builder, @strong controller, @strong pages, @strong finger_pan, @strong held_tool, @strong palette_bar => move |action, pressed, position| {
+        let activate = |id: &str| builder.object::<MenuItem>(id).expect("no menu").emit_activate();
+        let activate_tool = |name: &str| activate(&ToolEntry::by_name(name).unwrap().menu_id());
+
+        match (action, pressed) {
+            (ButtonAction::Undo, true) => activate("undo-btn"),
+            (ButtonAction::Redo, true) => activate("redo-btn"),
+            (ButtonAction::Pan, true) => finger_pan.set(position),
+            (ButtonAction::Pan, false) => finger_pan.set(None),
+            (ButtonAction::Eraser, true) => {
+                let current = pages.borrow().tool;
+
+                held_tool.set(Some(current));
+                activate_tool(tools::ERASER_TOOL);
+            },
+            (ButtonAction::Eraser, false) => {
+                if let Some(tool) = held_tool.take() {
+                    activate_tool(tool);
+                }
+            },
+            (ButtonAction::NextColor, true) => {
+                let color = controller.borrow().selected_color();
+                let palette = palette_bar.colors();
+                let colors = if palette.is_empty() { buttons::COLORS } else { palette.as_slice() };
+                let next = colors.iter()
+                    .position(|&[r, g, b]| (color.float_r() - r).abs() < 0.01 && (color.float_g() - g).abs() < 0.01 && (color.float_b() - b).abs() < 0.01)
+                    .map(|i| (i + 1) % colors.len())
+                    .unwrap_or(0);
+                let [r, g, b] = colors[next];
+
+                controller.borrow_mut().set_color(Color::from_float_rgb(r, g, b).with_alpha(color.alpha()));
+                sync_tool_widgets(&builder, &controller, &pages);
+            },
+            (ButtonAction::NextTool, true) => {
+                let current = tools::TOOLS.iter().position(|t| t.name == pages.borrow().tool).unwrap_or(0);
+
+                activate(&tools::TOOLS[(current + 1) % tools::TOOLS.len()].menu_id());
+            },
+            _ => {},
+        }
+    }));
     let drawing_area: DrawingArea = builder.object("drawing-area").expect("No drawing_area");
 
     let event_mask = EventMask::POINTER_MOTION_MASK
@@ -165,33 +659,121 @@ fn init(app: &Application, filename: Option<PathBuf>) {
         | EventMask::SCROLL_MASK | EventMask::TOUCH_MASK
         | EventMask::POINTER_MOTION_MASK | EventMask::SMOOTH_SCROLL_MASK
         | EventMask::ENTER_NOTIFY_MASK | EventMask::LEAVE_NOTIFY_MASK
-        | EventMask::PROXIMITY_IN_MASK | EventMask::PROXIMITY_OUT_MASK;
+        | EventMask::PROXIMITY_IN_MASK | EventMask::PROXIMITY_OUT_MASK
+        | EventMask::TOUCHPAD_GESTURE_MASK;
 
     drawing_area.set_support_multidevice(true);
     drawing_area.set_can_focus(true);
     drawing_area.add_events(event_mask);
 
-    drawing_area.connect_draw(clone!(@strong controller, @strong surface => move |_dw, ctx| {
+    drawing_area.connect_draw(clone!(@strong controller, @strong pages, @strong surface, @strong image_editor, @strong selector, @strong ink_stroke, @strong highlight_stroke, @strong laser, @strong layers_panel => move |_dw, ctx| {
         ctx.set_source_surface(&surface.borrow(), 0.0, 0.0).unwrap();
         ctx.paint().unwrap();
 
+        // undoing, opening a file or changing the page may change the layers
+        layers_panel.sync(&pages.borrow().extras.layers);
+
         let t = controller.borrow().get_transform();
 
+        if pages.borrow().tool == tools::IMAGE_TOOL {
+            image_editor.borrow().draw(&pages.borrow().extras.images, ctx, t);
+        }
+
+        if pages.borrow().tool == tools::SELECTION_TOOL {
+            // undoing or changing the page leaves the selection behind
+            validate_selection(&pages, &selector);
+            selector.borrow().draw(ctx, t);
+        }
+
         if let Some(commands) = controller.borrow().draw_commands_for_current_shape() {
             for command in commands {
                 command.draw(ctx, t);
             }
         }
 
+        if let Some(stroke) = highlight_stroke.borrow().as_ref() {
+            stroke.draw(ctx, t);
+        }
+
+        if let Some(text) = pages.borrow().editing_text() {
+            text.draw_editing(ctx, t);
+        }
+
+        if let Some(stroke) = ink_stroke.borrow().as_ref() {
+            stroke.draw(ctx, t);
+        }
+
         for command in controller.borrow().draw_commands_for_tool() {
             command.draw(ctx, t);
         }
 
+        laser.borrow().draw(ctx, t);
+
         Inhibit(false)
     }));
 
-    drawing_area.connect_key_press_event(clone!(@strong controller => move |_dw, event| {
+    drawing_area.connect_key_press_event(clone!(@strong controller, @strong pages, @strong surface, @strong header_bar, @strong image_editor, @strong selector => move |dw, event| {
         if let Some(key_name) = event.keyval().name() {
+            let is_image_tool = pages.borrow().tool == tools::IMAGE_TOOL;
+            let is_selection_tool = pages.borrow().tool == tools::SELECTION_TOOL;
+
+            if is_image_tool && matches!(key_name.as_str(), "Delete" | "BackSpace") {
+                let selected = image_editor.borrow_mut().selected.take();
+
+                if selected.map(|index| pages.borrow_mut().remove_image(index)).unwrap_or(false) {
+                    pages.borrow_mut().mark_changed();
+                    invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+                    set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+                }
+
+                return Inhibit(false);
+            }
+
+            if is_selection_tool {
+                validate_selection(&pages, &selector);
+            }
+
+            if is_selection_tool && selector.borrow().selection.is_some() {
+                let t = controller.borrow().get_transform();
+                let pixels = if event.state().contains(ModifierType::SHIFT_MASK) { 10.0 } else { 1.0 };
+                let step = pixels / t.xx.hypot(t.yx);
+                let control = event.state().contains(ModifierType::CONTROL_MASK);
+                let nudge = |dx: f64, dy: f64| Some(SelectionEdit::Transform(cairo::Matrix::new(1.0, 0.0, 0.0, 1.0, dx * step, dy * step)));
+                let edit = match key_name.as_str() {
+                    "Delete" | "BackSpace" => Some(SelectionEdit::Delete),
+                    "d" if control => Some(SelectionEdit::Duplicate),
+                    "Left" => nudge(-1.0, 0.0),
+                    "Right" => nudge(1.0, 0.0),
+                    "Up" => nudge(0.0, -1.0),
+                    "Down" => nudge(0.0, 1.0),
+                    "Escape" => {
+                        selector.borrow_mut().clear();
+                        dw.queue_draw();
+
+                        return Inhibit(true);
+                    },
+                    _ => None,
+                };
+
+                if let Some(edit) = edit {
+                    if edit_selection(&controller, &pages, &selector, edit) {
+                        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+                        set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+                    }
+
+                    return Inhibit(true);
+                }
+            }
+
+            let no_modifiers = !event.state().intersects(ModifierType::CONTROL_MASK | ModifierType::MOD1_MASK);
+
+            if let Some(navigation) = view::Navigation::from_key(&key_name, event.state().contains(ModifierType::SHIFT_MASK)).filter(|_| no_modifiers) {
+                navigation.apply(&mut controller.borrow_mut());
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+
+                return Inhibit(true);
+            }
+
             let key = gtk_key(key_name.as_str());
 
             controller.borrow_mut().handle_key_pressed(key);
@@ -200,31 +782,157 @@ fn init(app: &Application, filename: Option<PathBuf>) {
         Inhibit(false)
     }));
 
-    drawing_area.connect_key_release_event(clone!(@strong controller, @strong surface => move |dw, event| {
+    drawing_area.connect_key_release_event(clone!(@strong controller, @strong pages, @strong surface => move |dw, event| {
         if let Some(key_name) = event.keyval().name() {
             let key = gtk_key(key_name.as_str());
             let redraw = controller.borrow_mut().handle_key_released(key);
 
             if let ShouldRedraw::All = redraw {
-                invalidate_and_redraw(&controller.borrow(), &surface, dw);
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
             }
         }
 
         Inhibit(false)
     }));
 
-    drawing_area.connect_scroll_event(clone!(@strong controller, @strong surface => move |dw, event| {
+    drawing_area.connect_scroll_event(clone!(@strong controller, @strong pages, @strong surface => move |dw, event| {
         let delta = event.scroll_deltas().unwrap_or_else(|| event.delta());
 
         controller.borrow_mut().scroll(delta.into(), gtk_flags(event.state()));
 
-        invalidate_and_redraw(&controller.borrow(), &surface, dw);
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
 
         Inhibit(false)
     }));
 
-    drawing_area.connect_button_press_event(clone!(@strong controller, @strong surface => move |dw, event| {
+    drawing_area.connect_button_press_event(clone!(@strong controller, @strong pages, @strong surface, @strong image_editor, @strong selector, @strong ink_stroke, @strong pressure_settings, @strong gestures, @strong finger_pan, @strong draw_with_finger, @strong palm, @strong bindings, @strong run_action, @strong laser, @strong highlight_stroke => move |dw, event| {
+        // clicking the header bar may have taken the focus away
+        dw.grab_focus();
+
+        let source = event.source_device().map(|d| d.source());
+
+        if !palm.borrow_mut().accepts(source) {
+            return Inhibit(true);
+        }
+
+        let action = buttons::button_name(source, event.button()).and_then(|name| bindings.borrow().get(&name).copied());
+
+        if let Some(action) = action {
+            if event.event_type() == EventType::ButtonPress {
+                run_action(action, true, Some(event.position()));
+            }
+
+            return Inhibit(true);
+        }
+
+        if gestures.borrow().active() {
+            return Inhibit(false);
+        }
+
+        if !draw_with_finger.get() && source == Some(InputSource::Touchscreen) {
+            finger_pan.set(Some(event.position()));
+
+            return Inhibit(false);
+        }
+
+        if pages.borrow().tool == tools::LASER_TOOL {
+            if event.event_type() == EventType::ButtonPress && event.button() == 1 {
+                laser.borrow_mut().press(controller.borrow().get_transform(), event.position());
+                laser::animate(&laser, dw);
+            }
+
+            return Inhibit(false);
+        }
+
+        // the active layer may be locked or hidden, but the view can still be
+        // moved
+        if !pages.borrow().extras.layers.active_editable() && event.button() == 1 {
+            return Inhibit(false);
+        }
+
+        if pages.borrow().tool == tools::TEXT_TOOL {
+            if event.event_type() == EventType::ButtonPress && event.button() == 1 {
+                let point = images::to_world(controller.borrow().get_transform(), event.position());
+
+                {
+                    let mut pages = pages.borrow_mut();
+
+                    pages.finish_text();
+
+                    // clicking on a text edits it, anywhere else starts a new one
+                    match pages.extras.texts.iter().rposition(|text| text.contains(point)) {
+                        Some(index) => pages.edit_text(index),
+                        None => {
+                            let text = TextBox::new(point, &pages.text_style, controller.borrow().selected_color());
+
+                            pages.add_text(text);
+                        },
+                    }
+                }
+
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+            }
+
+            return Inhibit(false);
+        }
+
+        if pages.borrow().tool == tools::HIGHLIGHTER_TOOL {
+            if event.event_type() == EventType::ButtonPress && event.button() == 1 {
+                let mut stroke = HighlightStroke::new(&pages.borrow().highlighter);
+
+                stroke.push(images::to_world(controller.borrow().get_transform(), event.position()));
+                highlight_stroke.replace(Some(stroke));
+                dw.queue_draw();
+            }
+
+            return Inhibit(false);
+        }
+
+        if pages.borrow().tool == tools::SELECTION_TOOL {
+            if event.event_type() == EventType::ButtonPress && event.button() == 1 {
+                let lasso = event.state().contains(ModifierType::SHIFT_MASK);
+
+                validate_selection(&pages, &selector);
+                selector.borrow_mut().press(controller.borrow().get_transform(), event.position(), lasso);
+                dw.queue_draw();
+            }
+
+            return Inhibit(false);
+        }
+
+        if pages.borrow().tool == tools::IMAGE_TOOL {
+            if event.event_type() == EventType::ButtonPress && event.button() == 1 {
+                image_editor.borrow_mut().press(&pages.borrow().extras.images, controller.borrow().get_transform(), event.position());
+                dw.queue_draw();
+            }
+
+            return Inhibit(false);
+        }
+
+        // the mouse has no pressure so it keeps drawing through the core
+        let pressure = event.axis(AxisUse::Pressure).filter(|_| {
+            pressure_settings.enabled
+                && event.event_type() == EventType::ButtonPress
+                && event.button() == 1
+                && pages.borrow().tool == tools::PEN_TOOL
+                && event.device_tool().map(|dt| dt.tool_type()) != Some(DeviceToolType::Eraser)
+        });
+
+        if let Some(pressure) = pressure {
+            let tilt = event.axis(AxisUse::Xtilt).zip(event.axis(AxisUse::Ytilt));
+            let controller = controller.borrow();
+            let mut stroke = InkStroke::new(controller.selected_color());
+
+            stroke.push(ink_point(&controller, &pages.borrow(), &pressure_settings, event.position(), pressure, tilt));
+            ink_stroke.replace(Some(stroke));
+            dw.queue_draw();
+
+            return Inhibit(false);
+        }
+
         if let EventType::ButtonPress = event.event_type() {
+            palm.borrow_mut().drawing_with(source);
+
             let redraw_hint = controller
                 .borrow_mut()
                 .handle_mouse_button_pressed_flags(
@@ -235,7 +943,7 @@ fn init(app: &Application, filename: Option<PathBuf>) {
 
             match redraw_hint {
                 ShouldRedraw::All => {
-                    invalidate_and_redraw(&controller.borrow(), &surface, dw);
+                    invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
                 }
                 ShouldRedraw::Shape => {
                     dw.queue_draw();
@@ -247,8 +955,75 @@ fn init(app: &Application, filename: Option<PathBuf>) {
         Inhibit(false)
     }));
 
-    drawing_area.connect_button_release_event(clone!(@strong controller, @strong surface, @strong header_bar => move |dw, event| {
-        if let EventType::ButtonRelease = event.event_type() {
+    drawing_area.connect_button_release_event(clone!(@strong controller, @strong pages, @strong surface, @strong header_bar, @strong image_editor, @strong selector, @strong ink_stroke, @strong gestures, @strong finger_pan, @strong palm, @strong bindings, @strong run_action, @strong laser, @strong highlight_stroke => move |dw, event| {
+        let source = event.source_device().map(|d| d.source());
+
+        if !palm.borrow_mut().accepts(source) {
+            return Inhibit(true);
+        }
+
+        let action = buttons::button_name(source, event.button()).and_then(|name| bindings.borrow().get(&name).copied());
+
+        if let Some(action) = action {
+            run_action(action, false, Some(event.position()));
+
+            return Inhibit(true);
+        }
+
+        if finger_pan.take().is_some() || gestures.borrow().active() {
+            return Inhibit(false);
+        }
+
+        palm.borrow_mut().drawing_with(None);
+
+        let is_image_tool = pages.borrow().tool == tools::IMAGE_TOOL;
+        let is_selection_tool = pages.borrow().tool == tools::SELECTION_TOOL;
+        let is_laser_tool = pages.borrow().tool == tools::LASER_TOOL;
+        let is_text_tool = pages.borrow().tool == tools::TEXT_TOOL;
+        let locked = !pages.borrow().extras.layers.active_editable() && event.button() == 1;
+        let finished_stroke = ink_stroke.borrow_mut().take();
+        let finished_highlight = highlight_stroke.borrow_mut().take();
+
+        // whatever the tool is now, the trail being drawn ends here
+        laser.borrow_mut().release();
+
+        if let Some(stroke) = finished_stroke {
+            pages.borrow_mut().add_ink(stroke);
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+        } else if let Some(stroke) = finished_highlight {
+            // with flat ends a single point has no area
+            if stroke.points.len() > 1 {
+                pages.borrow_mut().add_highlight(stroke);
+            }
+
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+        } else if is_image_tool {
+            let moved = image_editor.borrow_mut().release(&pages.borrow().extras.images);
+
+            if let Some((index, before)) = moved {
+                pages.borrow_mut().image_moved(index, before);
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+            }
+        } else if is_selection_tool {
+            let core_svg = fill::core_svg(&controller.borrow());
+            let revision = pages.borrow().revision();
+            let edit = selector.borrow_mut().release(revision, &core_svg, &pages.borrow().extras);
+
+            if let Some(edit) = edit {
+                edit_selection(&controller, &pages, &selector, edit);
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+            } else {
+                dw.queue_draw();
+            }
+        } else if is_laser_tool || is_text_tool || locked {
+            // these never reach the core, nor what happens on a locked layer
+        } else if let EventType::ButtonRelease = event.event_type() {
+            // the core can't fill the shapes it draws, so they get the fill
+            // once they are finished, which is when there is one more
+            let fill = pages.borrow().fill.color().filter(|_| {
+                ToolEntry::by_name(pages.borrow().tool).map(ToolEntry::closed).unwrap_or(false)
+            });
+            let shapes_before = controller.borrow().draw_commands_for_drawing().len();
             let redraw_hint = controller
                 .borrow_mut()
                 .handle_mouse_button_released_flags(
@@ -260,7 +1035,15 @@ fn init(app: &Application, filename: Option<PathBuf>) {
 
             match redraw_hint {
                 ShouldRedraw::All => {
-                    invalidate_and_redraw(&controller.borrow(), &surface, dw);
+                    pages.borrow_mut().record_core_edit();
+
+                    if let Some(fill) = fill {
+                        if controller.borrow().draw_commands_for_drawing().len() > shapes_before {
+                            pages.borrow_mut().fill_last_shape(&mut controller.borrow_mut(), fill);
+                        }
+                    }
+
+                    invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
                 }
                 ShouldRedraw::Shape => {
                     dw.queue_draw();
@@ -269,13 +1052,90 @@ fn init(app: &Application, filename: Option<PathBuf>) {
             }
         }
 
-        set_subtitle(&header_bar, controller.borrow().get_save_status());
+        set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
 
         Inhibit(false)
     }));
 
-    drawing_area.connect_motion_notify_event(clone!(@strong controller, @strong surface => move |dw, event| {
+    drawing_area.connect_motion_notify_event(clone!(@strong controller, @strong pages, @strong surface, @strong image_editor, @strong selector, @strong pointer, @strong ink_stroke, @strong pressure_settings, @strong gestures, @strong finger_pan, @strong palm, @strong presentation, @strong laser, @strong highlight_stroke => move |dw, event| {
+        presentation.poke();
+
+        if !palm.borrow_mut().accepts(event.source_device().map(|d| d.source())) {
+            return Inhibit(true);
+        }
+
+        pointer.set(Some(event.position()));
+
+        if gestures.borrow().active() {
+            return Inhibit(false);
+        }
+
+        if let Some((last_x, last_y)) = finger_pan.get() {
+            let (x, y) = event.position();
+
+            view::pan(&mut controller.borrow_mut(), x - last_x, y - last_y);
+            finger_pan.set(Some((x, y)));
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+
+            return Inhibit(false);
+        }
+
+        if let Some(stroke) = ink_stroke.borrow_mut().as_mut() {
+            if let Some(pressure) = event.axis(AxisUse::Pressure) {
+                let tilt = event.axis(AxisUse::Xtilt).zip(event.axis(AxisUse::Ytilt));
+
+                stroke.push(ink_point(&controller.borrow(), &pages.borrow(), &pressure_settings, event.position(), pressure, tilt));
+                dw.queue_draw();
+            }
+
+            return Inhibit(false);
+        }
+
+        if let Some(stroke) = highlight_stroke.borrow_mut().as_mut() {
+            stroke.push(images::to_world(controller.borrow().get_transform(), event.position()));
+            dw.queue_draw();
+
+            return Inhibit(false);
+        }
+
+        if laser.borrow_mut().motion(controller.borrow().get_transform(), event.position()) {
+            return Inhibit(false);
+        }
+
+        if pages.borrow().tool == tools::SELECTION_TOOL {
+            if selector.borrow_mut().motion(controller.borrow().get_transform(), event.position()) {
+                dw.queue_draw();
+            }
+
+            return Inhibit(false);
+        }
+
+        if pages.borrow().tool == tools::IMAGE_TOOL {
+            let t = controller.borrow().get_transform();
+            let moved = image_editor.borrow_mut().motion(&mut pages.borrow_mut().extras.images, t, event.position());
+
+            if moved {
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+            }
+
+            return Inhibit(false);
+        }
+
         let (x, y) = event.position();
+        let erasing = event.state().contains(ModifierType::BUTTON1_MASK) && pages.borrow().extras.layers.active_editable() && (
+            pages.borrow().tool == tools::ERASER_TOOL
+            || event.device_tool().map(|dt| dt.tool_type()) == Some(DeviceToolType::Eraser)
+        );
+
+        // the core doesn't know about pressure strokes, so they are erased here
+        if erasing {
+            let t = controller.borrow().get_transform();
+            let erased = pages.borrow_mut().erase_ink(images::to_world(t, (x, y)), ink::ERASE_RADIUS / t.xx.hypot(t.yx));
+
+            if erased {
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+            }
+        }
 
         let redraw_hint = controller
             .borrow_mut()
@@ -287,7 +1147,7 @@ fn init(app: &Application, filename: Option<PathBuf>) {
 
         match redraw_hint {
             ShouldRedraw::All => {
-                invalidate_and_redraw(&controller.borrow(), &surface, dw);
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
             }
             ShouldRedraw::Shape => {
                 dw.queue_draw();
@@ -300,111 +1160,457 @@ fn init(app: &Application, filename: Option<PathBuf>) {
         Inhibit(false)
     }));
 
-    drawing_area.connect_size_allocate(clone!(@strong controller, @strong surface => move |dw, allocation| {
+    drawing_area.connect_proximity_in_event(clone!(@strong controller, @strong pages, @strong surface, @strong palm => move |dw, _event| {
+        // the palm touched the screen before the pen came near
+        if palm.borrow_mut().pen_in() {
+            controller.borrow_mut().handle_key_pressed(Key::Escape);
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
+        }
+
+        Inhibit(false)
+    }));
+
+    drawing_area.connect_proximity_out_event(clone!(@strong palm => move |_dw, _event| {
+        palm.borrow_mut().pen_out();
+
+        Inhibit(false)
+    }));
+
+    drawing_area.connect_leave_notify_event(clone!(@strong pointer => move |_dw, _event| {
+        pointer.set(None);
+
+        Inhibit(false)
+    }));
+
+    drawing_area.connect_size_allocate(clone!(@strong controller, @strong pages, @strong surface => move |dw, allocation| {
         controller.borrow_mut().resize(Vec2D::new_screen(allocation.width() as f64, allocation.height() as f64));
-        invalidate_and_redraw(&controller.borrow(), &surface, dw);
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, dw);
     }));
 
     let dwb = Rc::new(RefCell::new(drawing_area));
 
+    // Touch gestures, which also recognize pinching on touchpads
+    let zoom_gesture = GestureZoom::new(&*dwb.borrow());
+    let rotate_gesture = GestureRotate::new(&*dwb.borrow());
+
+    zoom_gesture.connect_begin(clone!(@strong controller, @strong gestures, @strong ink_stroke => move |gesture, _sequence| {
+        if let Some(center) = gesture.bounding_box_center() {
+            gestures.borrow_mut().zoom_begin(center);
+        }
+
+        // the first finger may have started a shape before the second one
+        // arrived
+        controller.borrow_mut().handle_key_pressed(Key::Escape);
+        ink_stroke.replace(None);
+    }));
+
+    zoom_gesture.connect_scale_changed(clone!(@strong controller, @strong pages, @strong gestures, @strong surface, @strong dwb => move |gesture, scale| {
+        let center = match gesture.bounding_box_center() {
+            Some(center) => center,
+            None => return,
+        };
+        let moved = gestures.borrow_mut().zoom_update(&mut controller.borrow_mut(), scale, center);
+
+        if moved {
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+        }
+    }));
+
+    zoom_gesture.connect_end(clone!(@strong gestures => move |_gesture, _sequence| {
+        gestures.borrow_mut().zoom_end();
+    }));
+
+    rotate_gesture.connect_begin(clone!(@strong gestures => move |_gesture, _sequence| {
+        gestures.borrow_mut().rotate_begin();
+    }));
+
+    rotate_gesture.connect_angle_changed(clone!(@strong controller, @strong pages, @strong gestures, @strong surface, @strong dwb => move |gesture, _angle, angle_delta| {
+        let center = match gesture.bounding_box_center() {
+            Some(center) => center,
+            None => return,
+        };
+        let moved = gestures.borrow_mut().rotate_update(&mut controller.borrow_mut(), angle_delta, center);
+
+        if moved {
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+        }
+    }));
+
+    rotate_gesture.connect_end(clone!(@strong gestures => move |_gesture, _sequence| {
+        gestures.borrow_mut().rotate_end();
+    }));
+
+    // in GTK3 widgets don't own their gestures, so they are kept alive until
+    // the drawing area goes away
+    dwb.borrow().connect_destroy(move |_dw| {
+        let _ = (&zoom_gesture, &rotate_gesture);
+    });
+
+    let pen_only_menu: CheckMenuItem = builder.object("pen-only-btn").expect("no pen only menu");
+    pen_only_menu.set_active(!draw_with_finger.get());
+    pen_only_menu.connect_toggled(clone!(@strong draw_with_finger => move |item| {
+        draw_with_finger.set(!item.is_active());
+    }));
+
+    // Buttons of tablet pads, which are not pointers so they reach the window
+    window.add_events(EventMask::TABLET_PAD_MASK);
+    window.connect_event(clone!(@strong bindings, @strong run_action => move |_window, event| {
+        let pressed = match event.event_type() {
+            EventType::PadButtonPress => true,
+            EventType::PadButtonRelease => false,
+            _ => return Inhibit(false),
+        };
+        let action = event.downcast_ref::<gdk::EventPadButton>()
+            .and_then(|pad| bindings.borrow().get(&buttons::pad_button_name(pad.button())).copied());
+
+        if let Some(action) = action {
+            run_action(action, pressed, None);
+        }
+
+        Inhibit(true)
+    }));
+
+    let buttons_menu: MenuItem = builder.object("buttons-btn").expect("no buttons menu");
+    buttons_menu.connect_activate(clone!(@strong window, @strong bindings => move |_menu| {
+        buttons::settings_dialog(&window, bindings.clone());
+    }));
+
     // Color chooser
     let color_chooser: ColorButton = builder.object("color-chooser").expect("No color chooser");
 
-    color_chooser.connect_color_set(clone!(@strong controller, @strong dwb => move |chooser| {
+    let presentation_color_chooser: ColorButton = builder.object("presentation-color-chooser").expect("No presentation color chooser");
+
+    // Picking a color of the palette or a recent one keeps the alpha like the
+    // color chooser
+    palette_bar.connect_pick(clone!(@strong controller, @strong pages, @strong selector, @strong header_bar, @strong dwb, @strong surface, @strong color_chooser, @strong presentation_color_chooser => move |color| {
+        let [r, g, b] = color;
+        let rgba = gdk::RGBA::new(r, g, b, 1.0);
+
+        choose_color(&controller, &pages, rgba);
+        color_chooser.set_rgba(&rgba);
+        presentation_color_chooser.set_rgba(&rgba);
+
+        if restyle_selection(&controller, &pages, &selector, Restyle::Color([r, g, b])) {
+            set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        }
+
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+    }));
+
+    // the numbers choose the first colors of the palette
+    dwb.borrow().connect_key_press_event(clone!(@strong palette_bar => move |_dw, event| {
+        let no_modifiers = !event.state().intersects(ModifierType::CONTROL_MASK | ModifierType::MOD1_MASK);
+        let color = event.keyval().name()
+            .and_then(|key_name| palette::key_index(&key_name))
+            .and_then(|index| palette_bar.color(index))
+            .filter(|_| no_modifiers);
+
+        match color {
+            Some(color) => {
+                palette_bar.pick(color);
+
+                Inhibit(true)
+            },
+            None => Inhibit(false),
+        }
+    }));
+
+    let import_palette_menu: MenuItem = builder.object("import-palette-btn").expect("no import palette menu");
+    import_palette_menu.connect_activate(clone!(@strong window, @strong palette_bar => move |_menu| {
+        let palette_file_chooser = FileChooserNative::new(Some("Importar paleta"), Some(&window), FileChooserAction::Open, Some("Importar"), Some("Cancelar"));
+        let filter = FileFilter::new();
+
+        filter.set_name(Some("Paletas de GIMP"));
+        filter.add_pattern("*.gpl");
+        palette_file_chooser.add_filter(&filter);
+
+        if palette_file_chooser.run() != ResponseType::Accept {
+            return;
+        }
+
+        let filename = match palette_file_chooser.filename() {
+            Some(filename) => filename,
+            None => return,
+        };
+        let colors = fs::read_to_string(&filename)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| palette::parse_gpl(&contents));
+
+        match colors {
+            Ok(colors) => {
+                let mut palette = config::read_settings().0.palette;
+
+                palette.colors = colors.clone();
+
+                palette_bar.set_colors(colors);
+
+                // the palette is used anyway, it just won't be there next time
+                if let Err(e) = config::write_palette(&palette) {
+                    dialog(&window, &format!("No pude guardar la paleta en la configuración :(\n\n{}", e), MessageType::Warning);
+                }
+            },
+            Err(e) => dialog(&window, &format!("No pude importar la paleta :(\n\n{}", e), MessageType::Error),
+        }
+    }));
+
+    color_chooser.connect_color_set(clone!(@strong controller, @strong pages, @strong selector, @strong header_bar, @strong dwb, @strong surface, @strong presentation_color_chooser, @strong palette_bar => move |chooser| {
+        let rgba = chooser.rgba();
+
+        choose_color(&controller, &pages, rgba);
+        presentation_color_chooser.set_rgba(&rgba);
+        palette_bar.push_recent([rgba.red(), rgba.green(), rgba.blue()]);
+
+        if restyle_selection(&controller, &pages, &selector, Restyle::Color([rgba.red(), rgba.green(), rgba.blue()])) {
+            set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        }
+
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+    }));
+
+    presentation_color_chooser.connect_color_set(clone!(@strong controller, @strong pages, @strong selector, @strong header_bar, @strong dwb, @strong surface, @strong color_chooser, @strong palette_bar => move |chooser| {
+        let rgba = chooser.rgba();
+
+        choose_color(&controller, &pages, rgba);
+        color_chooser.set_rgba(&rgba);
+        palette_bar.push_recent([rgba.red(), rgba.green(), rgba.blue()]);
+
+        if restyle_selection(&controller, &pages, &selector, Restyle::Color([rgba.red(), rgba.green(), rgba.blue()])) {
+            set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        }
+
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+    }));
+
+    // Fill of closed shapes, also changes the one of the selected shapes
+    let fill_toggle: ToggleButton = builder.object("fill-toggle").expect("No fill toggle");
+    let fill_chooser: ColorButton = builder.object("fill-chooser").expect("No fill chooser");
+
+    fill_toggle.connect_toggled(clone!(@strong controller, @strong pages, @strong selector, @strong header_bar, @strong dwb, @strong surface, @strong fill_chooser => move |toggle| {
+        let fill = {
+            let mut pages = pages.borrow_mut();
+
+            pages.fill.enabled = toggle.is_active();
+            pages.fill.color()
+        };
+
+        fill_chooser.set_sensitive(toggle.is_active());
+
+        if restyle_selection(&controller, &pages, &selector, Restyle::Fill(fill)) {
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+            set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        }
+    }));
+
+    fill_chooser.connect_color_set(clone!(@strong controller, @strong pages, @strong selector, @strong header_bar, @strong dwb, @strong surface => move |chooser| {
         let rgba = chooser.rgba();
-        let prev_alpha = controller.borrow().selected_color().alpha();
-        controller.borrow_mut().set_color(Color::from_float_rgb(rgba.red(), rgba.green(), rgba.blue()).with_alpha(prev_alpha));
-        dwb.borrow().queue_draw();
+        let fill = {
+            let mut pages = pages.borrow_mut();
+
+            pages.fill.color = [rgba.red(), rgba.green(), rgba.blue()];
+            pages.fill.alpha = (rgba.alpha() * 255.0).round() as u8;
+            pages.fill.color()
+        };
+
+        if restyle_selection(&controller, &pages, &selector, Restyle::Fill(fill)) {
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+            set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        }
+    }));
+
+    // Font of the texts, also changes the one being typed
+    let font_chooser: FontButton = builder.object("font-chooser").expect("No font chooser");
+
+    font_chooser.connect_font_set(clone!(@strong controller, @strong pages, @strong dwb, @strong surface => move |chooser| {
+        let style = match chooser.font().and_then(|name| TextStyle::from_font_name(&name)) {
+            Some(style) => style,
+            None => return,
+        };
+
+        if let Some(text) = pages.borrow_mut().editing_text_mut() {
+            text.style = style.clone();
+        }
+
+        pages.borrow_mut().text_style = style;
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+        dwb.borrow().grab_focus();
     }));
 
     // Zoom buttons
     let zoom_in_btn: Button = builder.object("zoom-in-btn").expect("No zoom in btn");
-    zoom_in_btn.connect_clicked(clone!(@strong controller, @strong dwb, @strong surface => move |_btn| {
+    zoom_in_btn.connect_clicked(clone!(@strong controller, @strong pages, @strong dwb, @strong surface => move |_btn| {
         controller.borrow_mut().zoom_in();
-        invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
     }));
 
     let zoom_out_btn: Button = builder.object("zoom-out-btn").expect("No zoom out btn");
-    zoom_out_btn.connect_clicked(clone!(@strong controller, @strong dwb, @strong surface => move |_btn| {
+    zoom_out_btn.connect_clicked(clone!(@strong controller, @strong pages, @strong dwb, @strong surface => move |_btn| {
         controller.borrow_mut().zoom_out();
-        invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
     }));
 
     let zoom_home_btn: Button = builder.object("zoom-home-btn").expect("No zoom home btn");
-    zoom_home_btn.connect_clicked(clone!(@strong controller, @strong dwb, @strong surface => move |_btn| {
+    zoom_home_btn.connect_clicked(clone!(@strong controller, @strong pages, @strong dwb, @strong surface => move |_btn| {
         controller.borrow_mut().go_home();
-        invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
     }));
 
     // Thickness and alpha
     let thickness_btn: ScaleButton = builder.object("thickness-scale").unwrap();
-    thickness_btn.connect_value_changed(clone!(@strong controller => move |_btn, value| {
-        controller.borrow_mut().set_stroke(value.into());
+    thickness_btn.connect_value_changed(clone!(@strong controller, @strong pages, @strong selector, @strong header_bar, @strong dwb, @strong surface => move |_btn, value| {
+        {
+            let mut pages = pages.borrow_mut();
+
+            if pages.tool == tools::HIGHLIGHTER_TOOL {
+                pages.highlighter.width = value;
+            } else {
+                controller.borrow_mut().set_stroke(value.into());
+                pages.stroke = value;
+            }
+        }
+
+        if restyle_selection(&controller, &pages, &selector, Restyle::Width(value)) {
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+            set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        }
     }));
 
     let alpha_btn: ScaleButton = builder.object("alpha-scale").unwrap();
-    alpha_btn.connect_value_changed(clone!(@strong controller => move |_btn, value| {
-        controller.borrow_mut().set_alpha((value * 255.0) as u8);
+    alpha_btn.connect_value_changed(clone!(@strong controller, @strong pages, @strong selector, @strong header_bar, @strong dwb, @strong surface => move |_btn, value| {
+        let alpha = (value * 255.0) as u8;
+
+        {
+            let mut pages = pages.borrow_mut();
+
+            if pages.tool == tools::HIGHLIGHTER_TOOL {
+                pages.highlighter.alpha = alpha;
+            } else {
+                controller.borrow_mut().set_alpha(alpha);
+            }
+        }
+
+        if restyle_selection(&controller, &pages, &selector, Restyle::Alpha(alpha)) {
+            invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+            set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        }
     }));
 
     // Undo/Redo
     let undo_menu: MenuItem = builder.object("undo-btn").expect("No undo btn");
-    undo_menu.connect_activate(clone!(@strong controller, @strong header_bar, @strong dwb, @strong surface => move |_menu| {
-        controller.borrow_mut().undo();
-        invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
-        set_subtitle(&header_bar, controller.borrow().get_save_status());
+    undo_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong dwb, @strong surface => move |_menu| {
+        pages.borrow_mut().undo(&mut controller.borrow_mut());
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+        set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
     }));
 
     let redo_menu: MenuItem = builder.object("redo-btn").expect("No reundo btn");
-    redo_menu.connect_activate(clone!(@strong controller, @strong header_bar, @strong dwb, @strong surface => move |_menu| {
-        controller.borrow_mut().redo();
-        invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
-        set_subtitle(&header_bar, controller.borrow().get_save_status());
+    redo_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong dwb, @strong surface => move |_menu| {
+        pages.borrow_mut().redo(&mut controller.borrow_mut());
+        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+        set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+    }));
+
+    // Clipboard
+    let copy_menu: MenuItem = builder.object("copy-btn").expect("no copy menu");
+    copy_menu.connect_activate(clone!(@strong controller, @strong pages, @strong window, @strong selector => move |_menu| {
+        copy_logic(&window, controller.clone(), pages.clone(), selector.clone());
+    }));
+
+    let paste_menu: MenuItem = builder.object("paste-btn").expect("no paste menu");
+    paste_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong window, @strong dwb, @strong surface, @strong pointer => move |_menu| {
+        // at the pointer, or in the middle of the view when using the menu
+        let at = pointer.get().unwrap_or_else(|| {
+            let dimensions = controller.borrow().get_dimensions();
+
+            (dimensions.x.val() / 2.0, dimensions.y.val() / 2.0)
+        });
+
+        paste_logic(&window, &header_bar, controller.clone(), pages.clone(), surface.clone(), dwb.clone(), at);
+    }));
+
+    // Images
+    let insert_image_menu: MenuItem = builder.object("insert-image-btn").expect("no insert image menu");
+    insert_image_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong window, @strong dwb, @strong surface, @strong image_editor, @strong builder => move |_menu| {
+        let image_file_chooser = FileChooserNative::new(Some("Insertar imagen"), Some(&window), FileChooserAction::Open, Some("Insertar"), Some("Cancelar"));
+        let filter = FileFilter::new();
+
+        filter.set_name(Some("Imágenes PNG y JPEG"));
+        filter.add_mime_type("image/png");
+        filter.add_mime_type("image/jpeg");
+        image_file_chooser.add_filter(&filter);
+
+        if image_file_chooser.run() != ResponseType::Accept {
+            return;
+        }
+
+        let filename = match image_file_chooser.filename() {
+            Some(filename) => filename,
+            None => return,
+        };
+
+        match PlacedImage::from_file(&filename) {
+            Ok(image) => {
+                // centered in the current view, shrunk if it doesn't fit
+                let (t, dimensions, (width, height)) = {
+                    let controller = controller.borrow();
+
+                    (controller.get_transform(), controller.get_dimensions(), images::view_size(&controller))
+                };
+                let center = images::to_world(t, (dimensions.x.val() / 2.0, dimensions.y.val() / 2.0));
+                let image = image.fitted(center, width * 0.8, height * 0.8);
+
+                let index = pages.borrow_mut().add_image(image);
+
+                image_editor.borrow_mut().selected = Some(index);
+
+                // so it can be moved and scaled right away
+                let image_tool: MenuItem = builder.object(&ToolEntry::by_name(tools::IMAGE_TOOL).unwrap().menu_id()).unwrap();
+
+                image_tool.emit_activate();
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+                set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+            },
+            Err(e) => dialog(&window, &e.to_string(), MessageType::Error),
+        }
     }));
 
     // File management
     let open_menu: MenuItem = builder.object("open-btn").expect("no open menu");
-    open_menu.connect_activate(clone!(@strong controller, @strong header_bar, @strong window, @strong dwb, @strong surface => move |_menu| {
-        let save_status = controller.borrow().get_save_status().clone();
+    open_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong window, @strong dwb, @strong surface, @strong page_label, @strong builder => move |_menu| {
+        if save_changes_first(&window, &header_bar, controller.clone(), pages.clone()) {
+            open_logic(&window, &header_bar, controller.clone(), pages.clone(), surface.clone(), dwb.clone());
+            set_page_label(&page_label, &pages.borrow());
+            sync_tool_widgets(&builder, &controller, &pages);
+        }
+    }));
 
-        match save_status {
-            SaveStatus::NewAndEmpty => open_logic(&window, &header_bar, controller.clone(), surface.clone(), dwb.clone()),
-            SaveStatus::NewAndChanged => {
-                yes_no_cancel_dialog(&window, UNSAVED_CHANGES_NEW_FILE, clone!(@strong controller, @strong header_bar, @strong window, @strong dwb, @strong surface => move || {
-                    if save_as_with_error_dialog(&window, &header_bar, controller.clone()).is_ok() {
-                        open_logic(&window, &header_bar, controller.clone(), surface.clone(), dwb.clone());
-                        Inhibit(false)
-                    } else {
-                        Inhibit(true)
-                    }
-                }), clone!(@strong controller, @strong header_bar, @strong window, @strong dwb, @strong surface => move || {
-                    open_logic(&window, &header_bar, controller.clone(), surface.clone(), dwb.clone());
-                    Inhibit(false)
-                }), || {
-                    Inhibit(false)
-                });
-            },
-            SaveStatus::Unsaved(path) => {
-                yes_no_cancel_dialog(&window, UNSAVED_CHANGES_SINCE_LAST_TIME, clone!(@strong controller, @strong header_bar, @strong window, @strong dwb, @strong surface => move || {
-                    if let Inhibit(false) = save_to_svg_logic_with_error_dialg(&window, controller.clone(), &path) {
-                        open_logic(&window, &header_bar, controller.clone(), surface.clone(), dwb.clone());
-                        Inhibit(false)
-                    } else {
-                        Inhibit(true)
-                    }
-                }), || {
-                    Inhibit(false)
-                }, || {
-                    Inhibit(false)
-                });
-            },
-            SaveStatus::Saved(_path) => open_logic(&window, &header_bar, controller.clone(), surface.clone(), dwb.clone()),
+    let recent_menu: Menu = builder.object("open-recent-menu").expect("no recent menu");
+    let open_recent: Rc<dyn Fn(&Path)> = Rc::new(clone!(@strong controller, @strong pages, @strong header_bar, @strong window, @strong dwb, @strong surface, @strong page_label, @strong builder => move |path: &Path| {
+        if !path.is_file() {
+            recent::remove(path);
+            dialog(&window, &format!("{} ya no existe, lo quité de la lista de archivos recientes", path.display()), MessageType::Info);
+        } else if save_changes_first(&window, &header_bar, controller.clone(), pages.clone()) {
+            open_path_logic(&window, &header_bar, controller.clone(), pages.clone(), surface.clone(), dwb.clone(), path);
+            set_page_label(&page_label, &pages.borrow());
+            sync_tool_widgets(&builder, &controller, &pages);
         }
     }));
 
+    let import_menu: MenuItem = builder.object("import-btn").expect("no import menu");
+    import_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong window, @strong dwb, @strong surface, @strong page_label => move |_menu| {
+        import_logic(&window, &header_bar, controller.clone(), pages.clone(), surface.clone(), dwb.clone());
+        set_page_label(&page_label, &pages.borrow());
+    }));
+
+    // the list is shared with other windows, so it is read every time
+    let file_menu: Menu = builder.object("file-actions-menu").expect("no file menu");
+    let recent_length = settings.recent_files.length;
+    file_menu.connect_show(clone!(@strong recent_menu, @strong open_recent => move |_menu| {
+        fill_recent_menu(&recent_menu, recent_length, open_recent.clone());
+    }));
+
     let new_menu: MenuItem = builder.object("new-btn").expect("no new menu");
-    new_menu.connect_activate(clone!(@strong controller, @strong header_bar, @strong window, @strong dwb, @strong surface => move |_menu| {
-        let save_status = controller.borrow().get_save_status().clone();
+    new_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong window, @strong dwb, @strong surface, @strong page_label => move |_menu| {
+        let save_status = pages.borrow().save_status(controller.borrow().get_save_status());
 
         // TODO reconsider how this works. I think the existence of the reset()
         // method is broken by default and instead we should replace the
@@ -417,18 +1623,20 @@ fn init(app: &Application, filename: Option<PathBuf>) {
             SaveStatus::NewAndEmpty => {},
             SaveStatus::NewAndChanged => {
                 yes_no_cancel_dialog(&window, UNSAVED_CHANGES_NEW_FILE, || {
-                    if save_as_with_error_dialog(&window, &header_bar, controller.clone()).is_ok() {
+                    if save_as_with_error_dialog(&window, &header_bar, controller.clone(), pages.clone()).is_ok() {
                         controller.borrow_mut().reset();
-                        invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
-                        set_subtitle(&header_bar, controller.borrow().get_save_status());
+                        pages.borrow_mut().reset();
+                        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+                        set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
                         Inhibit(false)
                     } else {
                         Inhibit(true)
                     }
                 }, || {
                     controller.borrow_mut().reset();
-                    invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
-                    set_subtitle(&header_bar, controller.borrow().get_save_status());
+                    pages.borrow_mut().reset();
+                    invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+                    set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
                     Inhibit(false)
                 }, || {
                     Inhibit(false)
@@ -436,18 +1644,20 @@ fn init(app: &Application, filename: Option<PathBuf>) {
             },
             SaveStatus::Unsaved(path) => {
                 yes_no_cancel_dialog(&window, UNSAVED_CHANGES_SINCE_LAST_TIME, || {
-                    if let Inhibit(false) = save_to_svg_logic_with_error_dialg(&window, controller.clone(), &path) {
+                    if let Inhibit(false) = save_logic_with_error_dialog(&window, controller.clone(), pages.clone(), &path) {
                         controller.borrow_mut().reset();
-                        invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
-                        set_subtitle(&header_bar, controller.borrow().get_save_status());
+                        pages.borrow_mut().reset();
+                        invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+                        set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
                         Inhibit(false)
                     } else {
                         Inhibit(true)
                     }
                 }, || {
                     controller.borrow_mut().reset();
-                    invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
-                    set_subtitle(&header_bar, controller.borrow().get_save_status());
+                    pages.borrow_mut().reset();
+                    invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+                    set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
                     Inhibit(false)
                 }, || {
                     Inhibit(false)
@@ -455,27 +1665,31 @@ fn init(app: &Application, filename: Option<PathBuf>) {
             },
             SaveStatus::Saved(_path) => {
                 controller.borrow_mut().reset();
-                invalidate_and_redraw(&controller.borrow(), &surface, &dwb.borrow());
-                set_subtitle(&header_bar, controller.borrow().get_save_status());
+                pages.borrow_mut().reset();
+                invalidate_and_redraw(&controller.borrow(), &pages.borrow().extras, &surface, &dwb.borrow());
+                set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
             },
         }
+
+        set_page_label(&page_label, &pages.borrow());
     }));
 
     let save_menu: MenuItem = builder.object("save-btn").expect("no save menu");
-    save_menu.connect_activate(clone!(@strong controller, @strong header_bar, @strong window => move |_menu| {
-        let save_status = controller.borrow().get_save_status().clone();
+    save_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong window => move |_menu| {
+        let save_status = pages.borrow().save_status(controller.borrow().get_save_status());
 
         match save_status {
             SaveStatus::NewAndEmpty => {}, // nothing to save actually
             SaveStatus::NewAndChanged => {
-                save_as_with_error_dialog(&window, &header_bar, controller.clone()).ok();
+                save_as_with_error_dialog(&window, &header_bar, controller.clone(), pages.clone()).ok();
             },
             SaveStatus::Unsaved(path) => {
-                let inhibit = save_to_svg_logic_with_error_dialg(&window, controller.clone(), &path);
+                let inhibit = save_logic_with_error_dialog(&window, controller.clone(), pages.clone(), &path);
 
+                // saving already associates the document to the file that
+                // was written, whose extension may differ from `path`
                 if inhibit == Inhibit(false) {
-                    controller.borrow_mut().set_saved(path);
-                    set_subtitle(&header_bar, controller.borrow().get_save_status());
+                    set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
                 }
             },
             SaveStatus::Saved(_path) => {},
@@ -483,26 +1697,26 @@ fn init(app: &Application, filename: Option<PathBuf>) {
     }));
 
     let save_as_menu: MenuItem = builder.object("save-as-btn").expect("no save as menu");
-    save_as_menu.connect_activate(clone!(@strong controller, @strong header_bar, @strong window => move |_menu| {
-        let save_status = controller.borrow().get_save_status().clone();
+    save_as_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong window => move |_menu| {
+        let save_status = pages.borrow().save_status(controller.borrow().get_save_status());
 
         match save_status {
             SaveStatus::NewAndEmpty => {},
             SaveStatus::NewAndChanged => {
-                save_as_with_error_dialog(&window, &header_bar, controller.clone()).ok();
+                save_as_with_error_dialog(&window, &header_bar, controller.clone(), pages.clone()).ok();
             },
             SaveStatus::Unsaved(_path) => {
-                save_as_with_error_dialog(&window, &header_bar, controller.clone()).ok();
+                save_as_with_error_dialog(&window, &header_bar, controller.clone(), pages.clone()).ok();
             },
             SaveStatus::Saved(_path) => {
-                save_as_with_error_dialog(&window, &header_bar, controller.clone()).ok();
+                save_as_with_error_dialog(&window, &header_bar, controller.clone(), pages.clone()).ok();
             },
         }
     }));
 
     let export_menu: MenuItem = builder.object("export-btn").expect("no export menu");
-    export_menu.connect_activate(clone!(@strong controller, @strong window => move |_menu| {
-        export_logic(&window, controller.clone());
+    export_menu.connect_activate(clone!(@strong controller, @strong pages, @strong window => move |_menu| {
+        export_logic(&window, controller.clone(), pages.clone());
     }));
 
     let exit_menu: MenuItem = builder.object("exit-btn").expect("no save menu");
@@ -510,61 +1724,98 @@ fn init(app: &Application, filename: Option<PathBuf>) {
         window.close();
     }));
 
-    // Change shape
-    let set_pen_menu: MenuItem = builder.object("tool-pen-btn").expect("no pen menu");
-    set_pen_menu.connect_activate(clone!(@strong controller, @strong tool_btn => move |_menu| {
-        controller.borrow_mut().set_tool(SelectedTool::Shape(ShapeTool::Path));
-        tool_btn.set_image(Some(&Image::from_resource("/tk/categulario/pizarra/icons/line.svg")));
+    // Pages
+    let page_prev_menu: MenuItem = builder.object("page-prev-btn").expect("no previous page menu");
+    page_prev_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong dwb, @strong surface, @strong page_label => move |_menu| {
+        pages.borrow_mut().previous(&mut controller.borrow_mut());
+        page_changed(&controller.borrow(), &pages.borrow(), &surface, &dwb.borrow(), &header_bar, &page_label);
     }));
 
-    let set_rectangle_menu: MenuItem = builder.object("tool-rect-btn").expect("no ractangle menu");
-    set_rectangle_menu.connect_activate(clone!(@strong controller, @strong tool_btn => move |_menu| {
-        controller.borrow_mut().set_tool(SelectedTool::Shape(ShapeTool::Rectangle));
-        tool_btn.set_image(Some(&Image::from_resource("/tk/categulario/pizarra/icons/rectangle.svg")));
+    let page_next_menu: MenuItem = builder.object("page-next-btn").expect("no next page menu");
+    page_next_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong dwb, @strong surface, @strong page_label => move |_menu| {
+        pages.borrow_mut().next(&mut controller.borrow_mut());
+        page_changed(&controller.borrow(), &pages.borrow(), &surface, &dwb.borrow(), &header_bar, &page_label);
     }));
 
-    let set_polygon_menu: MenuItem = builder.object("tool-polygon-btn").expect("no polygon menu");
-    set_polygon_menu.connect_activate(clone!(@strong controller, @strong tool_btn => move |_menu| {
-        controller.borrow_mut().set_tool(SelectedTool::Shape(ShapeTool::Polygon));
-        tool_btn.set_image(Some(&Image::from_resource("/tk/categulario/pizarra/icons/polygon.svg")));
+    let page_prev_btn: Button = builder.object("page-prev-header-btn").expect("no previous page btn");
+    page_prev_btn.connect_clicked(clone!(@strong page_prev_menu => move |_btn| {
+        page_prev_menu.emit_activate();
     }));
 
-    let set_circle_menu: MenuItem = builder.object("tool-circle-btn").expect("no circle menu");
-    set_circle_menu.connect_activate(clone!(@strong controller, @strong tool_btn => move |_menu| {
-        controller.borrow_mut().set_tool(SelectedTool::Shape(ShapeTool::CircleByCenterAndPoint));
-        tool_btn.set_image(Some(&Image::from_resource("/tk/categulario/pizarra/icons/circle_by_center_and_point.svg")));
+    let page_next_btn: Button = builder.object("page-next-header-btn").expect("no next page btn");
+    page_next_btn.connect_clicked(clone!(@strong page_next_menu => move |_btn| {
+        page_next_menu.emit_activate();
     }));
 
-    let set_circle_by_three_points: MenuItem = builder.object("tool-circle3-btn").expect("no circle menu");
-    set_circle_by_three_points.connect_activate(clone!(@strong controller, @strong tool_btn => move |_menu| {
-        controller.borrow_mut().set_tool(SelectedTool::Shape(ShapeTool::CircleThroughThreePoints));
-        tool_btn.set_image(Some(&Image::from_resource("/tk/categulario/pizarra/icons/circle_by_three_points.svg")));
+    let page_add_menu: MenuItem = builder.object("page-add-btn").expect("no add page menu");
+    page_add_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong dwb, @strong surface, @strong page_label => move |_menu| {
+        pages.borrow_mut().add(&mut controller.borrow_mut());
+        page_changed(&controller.borrow(), &pages.borrow(), &surface, &dwb.borrow(), &header_bar, &page_label);
     }));
 
-    let set_ellipse_menu: MenuItem = builder.object("tool-ellipse-btn").expect("no ellipse menu");
-    set_ellipse_menu.connect_activate(clone!(@strong controller, @strong tool_btn => move |_menu| {
-        controller.borrow_mut().set_tool(SelectedTool::Shape(ShapeTool::ThreePointEllipse));
-        tool_btn.set_image(Some(&Image::from_resource("/tk/categulario/pizarra/icons/ellipse_by_foci_and_point.svg")));
+    let page_duplicate_menu: MenuItem = builder.object("page-duplicate-btn").expect("no duplicate page menu");
+    page_duplicate_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong dwb, @strong surface, @strong page_label => move |_menu| {
+        pages.borrow_mut().duplicate(&mut controller.borrow_mut());
+        page_changed(&controller.borrow(), &pages.borrow(), &surface, &dwb.borrow(), &header_bar, &page_label);
     }));
 
-    let set_grid_menu: MenuItem = builder.object("tool-grid-btn").expect("no grid menu");
-    set_grid_menu.connect_activate(clone!(@strong controller, @strong tool_btn => move |_menu| {
-        controller.borrow_mut().set_tool(SelectedTool::Shape(ShapeTool::Grid));
-        tool_btn.set_image(Some(&Image::from_resource("/tk/categulario/pizarra/icons/grid.svg")));
+    let page_move_up_menu: MenuItem = builder.object("page-move-up-btn").expect("no move page up menu");
+    page_move_up_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong page_label => move |_menu| {
+        pages.borrow_mut().move_current(-1);
+        set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        set_page_label(&page_label, &pages.borrow());
     }));
 
-    let set_free_grid_menu: MenuItem = builder.object("tool-free-grid-btn").expect("no free grid menu");
-    set_free_grid_menu.connect_activate(clone!(@strong controller, @strong tool_btn => move |_menu| {
-        controller.borrow_mut().set_tool(SelectedTool::Shape(ShapeTool::FreeGrid));
-        tool_btn.set_image(Some(&Image::from_resource("/tk/categulario/pizarra/icons/free_grid.svg")));
+    let page_move_down_menu: MenuItem = builder.object("page-move-down-btn").expect("no move page down menu");
+    page_move_down_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong page_label => move |_menu| {
+        pages.borrow_mut().move_current(1);
+        set_subtitle(&header_bar, &controller.borrow(), &pages.borrow());
+        set_page_label(&page_label, &pages.borrow());
     }));
 
-    let set_eraser_menu: MenuItem = builder.object("tool-eraser-btn").expect("no eraser menu");
-    set_eraser_menu.connect_activate(clone!(@strong controller, @strong tool_btn => move |_menu| {
-        controller.borrow_mut().set_tool(SelectedTool::Eraser);
-        tool_btn.set_image(Some(&Image::from_resource("/tk/categulario/pizarra/icons/eraser.svg")));
+    let page_delete_menu: MenuItem = builder.object("page-delete-btn").expect("no delete page menu");
+    page_delete_menu.connect_activate(clone!(@strong controller, @strong pages, @strong header_bar, @strong window, @strong dwb, @strong surface, @strong page_label => move |_menu| {
+        let delete = || {
+            pages.borrow_mut().delete(&mut controller.borrow_mut());
+            page_changed(&controller.borrow(), &pages.borrow(), &surface, &dwb.borrow(), &header_bar, &page_label);
+            Inhibit(false)
+        };
+
+        // deleting a page can't be undone, so ask first if there is something
+        // in it, even in hidden layers
+        let empty = {
+            let pages = pages.borrow();
+
+            controller.borrow().get_bounds().is_none() && pages.extras.bounds().next().is_none() && pages.extras.layers.others_empty()
+        };
+
+        if !empty {
+            yes_no_cancel_dialog(&window, "Esta página tiene algunos trazos\n\n¿Seguro que la quieres eliminar?", delete, || Inhibit(false), || Inhibit(false));
+        } else {
+            delete();
+        }
     }));
 
+    // Change shape
+    for entry in tools::TOOLS {
+        let tool_menu: MenuItem = builder.object(&entry.menu_id()).expect("no tool menu");
+
+        tool_menu.connect_activate(clone!(@strong controller, @strong pages, @strong builder, @strong dwb, @strong selector => move |_menu| {
+            // tools handled here leave the core with its last tool
+            if let Some(tool) = entry.tool() {
+                controller.borrow_mut().set_tool(tool);
+            }
+
+            pages.borrow_mut().finish_text();
+            pages.borrow_mut().tool = entry.name;
+            selector.borrow_mut().clear();
+
+            // the highlighter has its own color and width
+            sync_tool_widgets(&builder, &controller, &pages);
+            dwb.borrow().queue_draw();
+        }));
+    }
+
     let about_btn: MenuItem = builder.object("about-btn").unwrap();
     about_btn.connect_activate(move |_| {
         about_dialog.set_version(Some(env!("CARGO_PKG_VERSION")));
@@ -574,8 +1825,67 @@ fn init(app: &Application, filename: Option<PathBuf>) {
         }
     });
 
+    // Autosave
+    let autosave = Autosave::start(controller.clone(), pages.clone(), &settings.autosave);
+
+    window.connect_destroy(move |_window| {
+        autosave.borrow_mut().stop();
+    });
+
     // Show
     window.show_all();
+
+    // so the keyboard moves the view right away
+    dwb.borrow().grab_focus();
+
+    if !settings_problems.is_empty() {
+        dialog(&window, &format!("Hay problemas con el archivo de configuración:\n\n{}", settings_problems.join("\n")), MessageType::Warning);
+    }
+
+    if !shortcut_problems.is_empty() {
+        dialog(&window, &format!("Hay problemas con los atajos de teclado del archivo de configuración:\n\n{}", shortcut_problems.join("\n")), MessageType::Warning);
+    }
+
+    // The file given in the command line is opened once the window is visible
+    // so problems with it can be shown in a dialog
+    if let Some(filename) = &filename {
+        open_path_logic(&window, &header_bar, controller.clone(), pages.clone(), surface.clone(), dwb.clone(), filename);
+        set_page_label(&page_label, &pages.borrow());
+        sync_tool_widgets(&builder, &controller, &pages);
+    }
+
+    // Offer to recover the drawings of sessions that ended abruptly. Here
+    // Inhibit(true) means "stop asking"
+    if filename.is_none() {
+        for (path, modified) in autosave::abandoned_files(&settings.autosave) {
+            let message = format!("Encontré un dibujo sin guardar de una sesión que terminó de repente ({})\n\n¿Lo quieres recuperar?", time_ago(modified));
+
+            let stop = yes_no_cancel_dialog(&window, &message, || {
+                let ans = { open_file(&mut controller.borrow_mut(), &mut pages.borrow_mut(), &path) };
+
+                match ans {
+                    Ok(_) => {
+                        fs::remove_file(&path).ok();
+                        pages.borrow_mut().mark_changed();
+                        page_changed(&controller.borrow(), &pages.borrow(), &surface, &dwb.borrow(), &header_bar, &page_label);
+                    },
+                    Err(e) => log::warn!("Could not recover {}: {}", path.display(), e),
+                }
+
+                Inhibit(true)
+            }, || {
+                fs::remove_file(&path).ok();
+
+                Inhibit(false)
+            }, || {
+                Inhibit(true)
+            });
+
+            if stop == Inhibit(true) {
+                break;
+            }
+        }
+    }
 }
 
 fn pre_launch(app: &Application, file: Option<PathBuf>) {
@@ -595,6 +1905,14 @@ fn main() {
     #[cfg(not(windows))]
     env_logger::init();
 
+    let args: Vec<String> = env::args().collect();
+
+    // Commands that don't need a window are handled before gtk gets to see
+    // the arguments
+    if args.get(1).map(String::as_str) == Some("export") {
+        std::process::exit(cli::export(&args[2..]));
+    }
+
     let application = Application::new(
         Some("tk.categulario.pizarra"),
         ApplicationFlags::NON_UNIQUE | ApplicationFlags::HANDLES_OPEN,
@@ -0,0 +1,952 @@
+//! Documents with several pages (boards). Only one page at a time lives in the
+//! controller that the widgets talk to, the rest wait here as svg until they
+//! are shown again.
+//!
+//! A document with a single page is saved exactly as before, a plain svg.
+//! Documents with more pages are saved as an svg that nests the svg of every
+//! page between marker comments.
+use std::mem;
+
+use pizarra::config::Config;
+use pizarra::prelude::*;
+
+use crate::tools::{ToolEntry, DEFAULT_TOOL};
+use crate::extras::Extras;
+use crate::fill::{self, Fill};
+use crate::import;
+use crate::layers::{self, Layers};
+use crate::selection::{CoreSvg, Restyle};
+use crate::ink::InkStroke;
+use crate::highlight::{Highlighter, HighlightStroke};
+use crate::images::PlacedImage;
+use crate::text::{TextBox, TextStyle};
+use crate::view;
+
+const PAGE_START: &str = "<!-- pizarra:page -->";
+const PAGE_END: &str = "<!-- /pizarra:page -->";
+const BLANK_PAGE: &str = r#"<svg xmlns="http://www.w3.org/2000/svg"></svg>"#;
+
+/// Removes the `<?xml ... ?>` declaration from the beginning of an svg so it
+/// can be nested in another one.
+fn strip_prolog(svg: &str) -> &str {
+    let svg = svg.trim_start();
+
+    if svg.starts_with("<?xml") {
+        if let Some(end) = svg.find("?>") {
+            return svg[end + 2..].trim_start();
+        }
+    }
+
+    svg
+}
+
+/// Splits the contents of a file in the svg documents of its pages. Files
+/// that are not multi-page documents have a single page: the whole file.
+pub fn split_pages(contents: &str) -> Vec<&str> {
+    let mut pages = Vec::new();
+    let mut rest = contents;
+
+    while let Some(start) = rest.find(PAGE_START) {
+        let page = &rest[start + PAGE_START.len()..];
+        let end = page.find(PAGE_END).unwrap_or(page.len());
+
+        pages.push(page[..end].trim());
+        rest = &page[end..];
+    }
+
+    if pages.is_empty() {
+        vec![contents]
+    } else {
+        pages
+    }
+}
+
+/// Joins the svg documents of several pages in a single file that can be
+/// split back with [`split_pages`].
+pub fn join_pages(pages: &[String]) -> String {
+    let mut svg = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+
+    svg.push_str(&format!("<svg xmlns=\"http://www.w3.org/2000/svg\" data-pizarra-pages=\"{}\">\n", pages.len()));
+
+    for page in pages {
+        svg.push_str(PAGE_START);
+        svg.push('\n');
+        svg.push_str(strip_prolog(page).trim_end());
+        svg.push('\n');
+        svg.push_str(PAGE_END);
+        svg.push('\n');
+    }
+
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// What is between the opening and closing tags of the root element of an
+/// svg. Empty for a self closing `<svg/>`.
+fn svg_body(svg: &str) -> &str {
+    let start = svg.find("<svg").and_then(|start| svg[start..].find('>').map(|end| start + end + 1));
+
+    match (start, svg.rfind("</svg>")) {
+        (Some(start), Some(end)) if start <= end => &svg[start..end],
+        _ => "",
+    }
+}
+
+/// Puts the elements of `extra` at the end of `base`
+fn merge_svg(base: &str, extra: &str) -> String {
+    let extra = svg_body(extra);
+
+    match base.rfind("</svg>") {
+        Some(end) => format!("{}{}{}", &base[..end], extra, &base[end..]),
+        // an empty drawing may be a self closing `<svg/>`
+        None => match base.rfind("/>") {
+            Some(end) => format!("{}>{}</svg>{}", &base[..end], extra, &base[end + 2..]),
+            None => base.into(),
+        },
+    }
+}
+
+/// A text box or image that was added, edited or removed. `index` is its
+/// place among the texts or images of the page.
+struct Change<T> {
+    index: usize,
+    before: Option<T>,
+    after: Option<T>,
+}
+
+/// Puts the element at `index` as it was on one side of the change
+fn swap<T: Clone>(list: &mut Vec<T>, index: usize, from: &Option<T>, to: &Option<T>) {
+    if from.is_some() {
+        list.remove(index);
+    }
+
+    if let Some(element) = to {
+        list.insert(index, element.clone());
+    }
+}
+
+/// The whole current page, for changes that the core can't undo
+struct Snapshot {
+    svg: String,
+    extras: Extras,
+}
+
+/// Who made a change to the current page
+enum Edit {
+    Core,
+
+    /// Something the core did and forgot when the page was loaded again,
+    /// this is how its shapes were before
+    Shapes(String),
+
+    Ink,
+    Highlight,
+    Text(Change<TextBox>),
+    Image(Change<PlacedImage>),
+
+    /// The page was replaced, this is how it was before
+    Page(Snapshot),
+}
+
+enum Undone {
+    Core,
+
+    /// How the shapes of the core were before undoing something it forgot
+    Shapes(String),
+
+    Ink(InkStroke),
+    Highlight(HighlightStroke),
+    Text(Change<TextBox>),
+    Image(Change<PlacedImage>),
+
+    /// How the page was before undoing its replacement
+    Page(Snapshot),
+}
+
+/// The changes that can be undone and redone in a page that is not being
+/// shown
+#[derive(Default)]
+struct History {
+    done: Vec<Edit>,
+    undone: Vec<Undone>,
+}
+
+/// The text box being typed in. It is changed in place, so it is saved and
+/// exported as it is, and what it was before is kept to undo the edit as a
+/// whole.
+struct EditingText {
+    index: usize,
+    before: Option<TextBox>,
+}
+
+pub struct Pages {
+    /// svg of every page. The one of the current page is outdated, its real
+    /// contents are in the controller.
+    pages: Vec<String>,
+
+    /// Index of the page being shown
+    current: usize,
+
+    /// Images and pressure strokes of the page being shown
+    pub extras: Extras,
+
+    /// Whether each of the last changes to the current page was made by the
+    /// core or by adding a pressure stroke, so undo can tell what to undo
+    history: Vec<Edit>,
+
+    /// Changes that were undone, latest last
+    undone: Vec<Undone>,
+
+    /// History of every page, except the one of the current page which is in
+    /// `history` and `undone`
+    histories: Vec<History>,
+
+    /// Goes up with every change to the page being shown
+    revision: u64,
+
+    /// Pages were added, removed, reordered or some page was modified and then
+    /// left since the last time the document was saved.
+    changed: bool,
+
+    /// Name of the selected tool, which the controller can't tell. Kept with
+    /// the document so it can be saved in `.pizarra` files.
+    pub tool: &'static str,
+
+    /// Selected thickness, for the same reason
+    pub stroke: f64,
+
+    /// Color and width of the highlighter
+    pub highlighter: Highlighter,
+
+    /// Font of new text boxes
+    pub text_style: TextStyle,
+
+    /// Fill of new closed shapes
+    pub fill: Fill,
+
+    editing: Option<EditingText>,
+}
+
+impl Default for Pages {
+    fn default() -> Pages {
+        Pages::new()
+    }
+}
+
+impl Pages {
+    pub fn new() -> Pages {
+        Pages {
+            pages: vec![BLANK_PAGE.into()],
+            current: 0,
+            extras: Extras::default(),
+            history: Vec::new(),
+            undone: Vec::new(),
+            histories: vec![History::default()],
+            revision: 0,
+            changed: false,
+            tool: DEFAULT_TOOL,
+            stroke: 2.0,
+            highlighter: Highlighter::default(),
+            text_style: TextStyle::default(),
+            fill: Fill::default(),
+            editing: None,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Tells apart the contents of the page being shown: it is different
+    /// after every change, so what was computed from the page can tell if it
+    /// is outdated without comparing the page.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Replaces the document with the given pages. The first one must be
+    /// already loaded in the controller.
+    pub fn replace(&mut self, pages: Vec<String>) {
+        self.replace_at(pages, 0, Extras::default());
+    }
+
+    /// Like [`Pages::replace`] but the page at `current` is the one loaded
+    /// in the controller, and these are its extras.
+    pub fn replace_at(&mut self, pages: Vec<String>, current: usize, extras: Extras) {
+        self.histories = pages.iter().map(|_| History::default()).collect();
+        self.pages = pages;
+        self.current = current;
+        self.revision += 1;
+        self.extras = extras;
+        self.editing = None;
+        self.history.clear();
+        self.undone.clear();
+        self.changed = false;
+    }
+
+    /// Leaves a document with a single blank page, which must be already
+    /// loaded in the controller.
+    pub fn reset(&mut self) {
+        self.replace(vec![BLANK_PAGE.into()]);
+    }
+
+    /// To be called after the document is saved
+    pub fn mark_saved(&mut self) {
+        self.changed = false;
+    }
+
+    /// Flags the document as having unsaved changes, for example after
+    /// restoring it from a recovery file.
+    pub fn mark_changed(&mut self) {
+        self.revision += 1;
+        self.changed = true;
+    }
+
+    /// To be called after the core finished a shape, so it is undone in the
+    /// right order with the pressure strokes.
+    pub fn record_core_edit(&mut self) {
+        self.revision += 1;
+        self.history.push(Edit::Core);
+        self.undone.clear();
+    }
+
+    /// Adds a pressure stroke to the current page
+    pub fn add_ink(&mut self, stroke: InkStroke) {
+        self.extras.ink.push(stroke);
+        self.revision += 1;
+        self.history.push(Edit::Ink);
+        self.undone.clear();
+        self.changed = true;
+    }
+
+    /// Adds a highlighter stroke to the current page
+    pub fn add_highlight(&mut self, stroke: HighlightStroke) {
+        self.extras.highlights.push(stroke);
+        self.revision += 1;
+        self.history.push(Edit::Highlight);
+        self.undone.clear();
+        self.changed = true;
+    }
+
+    /// Removes the pressure and highlighter strokes that pass within `radius`
+    /// of the given point in world coordinates. Returns `true` if any was
+    /// removed.
+    pub fn erase_ink(&mut self, point: (f64, f64), radius: f64) -> bool {
+        let before = self.extras.ink.len() + self.extras.highlights.len();
+
+        self.extras.ink.retain(|stroke| !stroke.touches(point, radius));
+        self.extras.highlights.retain(|stroke| !stroke.touches(point, radius));
+
+        if self.extras.ink.len() + self.extras.highlights.len() == before {
+            return false;
+        }
+
+        // the erased strokes can't be brought back, so undo must not look
+        // for them
+        self.history.retain(|edit| !matches!(edit, Edit::Ink | Edit::Highlight));
+        self.undone.retain(|undone| !matches!(undone, Undone::Ink(_) | Undone::Highlight(_)));
+        self.revision += 1;
+        self.changed = true;
+
+        true
+    }
+
+    /// Adds an image to the current page. Returns its index.
+    pub fn add_image(&mut self, image: PlacedImage) -> usize {
+        self.extras.images.push(image.clone());
+
+        let index = self.extras.images.len() - 1;
+
+        self.record_image(Change { index, before: None, after: Some(image) });
+
+        index
+    }
+
+    /// To be called after the image at `index` was moved or scaled, with how
+    /// it was before
+    pub fn image_moved(&mut self, index: usize, before: PlacedImage) {
+        if let Some(after) = self.extras.images.get(index).cloned() {
+            self.record_image(Change { index, before: Some(before), after: Some(after) });
+        }
+    }
+
+    /// Removes the image at `index` from the current page. Returns `true` if
+    /// there was one.
+    pub fn remove_image(&mut self, index: usize) -> bool {
+        if index >= self.extras.images.len() {
+            return false;
+        }
+
+        let image = self.extras.images.remove(index);
+
+        self.record_image(Change { index, before: Some(image), after: None });
+
+        true
+    }
+
+    fn record_image(&mut self, change: Change<PlacedImage>) {
+        self.history.push(Edit::Image(change));
+        self.undone.clear();
+        self.revision += 1;
+        self.changed = true;
+    }
+
+    /// Adds an empty text box to the current page and starts typing in it
+    pub fn add_text(&mut self, text: TextBox) {
+        self.finish_text();
+        self.extras.texts.push(text);
+        self.editing = Some(EditingText {
+            index: self.extras.texts.len() - 1,
+            before: None,
+        });
+    }
+
+    /// Starts typing in the text box at the given index
+    pub fn edit_text(&mut self, index: usize) {
+        self.finish_text();
+
+        if let Some(text) = self.extras.texts.get(index) {
+            self.editing = Some(EditingText {
+                index,
+                before: Some(text.clone()),
+            });
+        }
+    }
+
+    /// The text box being typed in
+    pub fn editing_text(&self) -> Option<&TextBox> {
+        self.extras.texts.get(self.editing.as_ref()?.index)
+    }
+
+    pub fn editing_text_mut(&mut self) -> Option<&mut TextBox> {
+        self.extras.texts.get_mut(self.editing.as_ref()?.index)
+    }
+
+    /// Stops typing in the current text box, which is removed if it was left
+    /// empty. Returns `true` if the page changed.
+    pub fn finish_text(&mut self) -> bool {
+        let EditingText { index, before } = match self.editing.take() {
+            Some(editing) => editing,
+            None => return false,
+        };
+        let after = if self.extras.texts[index].text.is_empty() {
+            self.extras.texts.remove(index);
+
+            None
+        } else {
+            Some(self.extras.texts[index].clone())
+        };
+
+        if before.as_ref().map(TextBox::to_svg) == after.as_ref().map(TextBox::to_svg) {
+            return false;
+        }
+
+        self.history.push(Edit::Text(Change { index, before, after }));
+        self.undone.clear();
+        self.revision += 1;
+        self.changed = true;
+
+        true
+    }
+
+    fn snapshot(&self, controller: &mut Pizarra) -> Snapshot {
+        Snapshot {
+            svg: fill::core_svg(controller),
+            extras: self.extras.clone(),
+        }
+    }
+
+    /// Selects the tool of the document again in the controller, as opening
+    /// a page puts the core back on its default tool
+    pub fn restore_tool(&self, controller: &mut Pizarra) {
+        if let Some(tool) = ToolEntry::by_name(self.tool).and_then(ToolEntry::tool) {
+            controller.set_tool(tool);
+        }
+    }
+
+    /// The core forgets what it can undo and redo when a page is loaded in
+    /// it, so before that what it did is turned into the svg of its shapes
+    /// before and after each change, walking its history back and forth.
+    fn keep_core_history(&mut self, controller: &mut Pizarra) {
+        let done = self.history.iter().filter(|edit| matches!(edit, Edit::Core)).count();
+        let undone = self.undone.iter().filter(|undone| matches!(undone, Undone::Core)).count();
+        let mut before = Vec::with_capacity(done);
+        let mut after = Vec::with_capacity(undone);
+
+        for _ in 0..done {
+            controller.undo();
+            before.push(fill::core_svg(controller));
+        }
+
+        for _ in 0..done {
+            controller.redo();
+        }
+
+        for _ in 0..undone {
+            controller.redo();
+            after.push(fill::core_svg(controller));
+        }
+
+        for _ in 0..undone {
+            controller.undo();
+        }
+
+        // the latest changes are the first ones walked through
+        let done = self.history.iter_mut().rev().filter(|edit| matches!(edit, Edit::Core));
+
+        for (edit, svg) in done.zip(before) {
+            *edit = Edit::Shapes(svg);
+        }
+
+        let undone = self.undone.iter_mut().rev().filter(|undone| matches!(undone, Undone::Core));
+
+        for (undone, svg) in undone.zip(after) {
+            *undone = Undone::Shapes(svg);
+        }
+    }
+
+    /// Loads a snapshot in the controller keeping the view, the file of the
+    /// document and the history of the page
+    fn restore(&mut self, controller: &mut Pizarra, snapshot: Snapshot) -> bool {
+        let status = controller.get_save_status().clone();
+        let transform = controller.get_transform();
+
+        self.keep_core_history(controller);
+
+        if controller.open(&snapshot.svg).is_err() {
+            return false;
+        }
+
+        view::restore(controller, transform);
+        self.restore_tool(controller);
+
+        if let SaveStatus::Saved(path) | SaveStatus::Unsaved(path) = status {
+            controller.set_saved(path);
+        }
+
+        self.extras = snapshot.extras;
+        self.revision += 1;
+        self.changed = true;
+
+        true
+    }
+
+    /// Gives the fill to the shape that the core just finished, the last one
+    /// of the page. The core only takes the fill by loading the page again,
+    /// which keeps the history, and the fill is part of drawing the shape, so
+    /// undoing it removes the filled shape at once.
+    pub fn fill_last_shape(&mut self, controller: &mut Pizarra, fill: Color) -> bool {
+        let mut core = match CoreSvg::parse(&fill::core_svg(controller)) {
+            Some(core) => core,
+            None => return false,
+        };
+        let last = match core.shapes.pop() {
+            Some(last) => last,
+            None => return false,
+        };
+        let before = core.to_svg();
+        let drawn = matches!(self.history.last(), Some(Edit::Core));
+
+        core.shapes.push(import::restyle_fragment(&last, Restyle::Fill(Some(fill))).trim_end().into());
+
+        if !self.restore(controller, Snapshot { svg: core.to_svg(), extras: self.extras.clone() }) {
+            return false;
+        }
+
+        // the change of the core that drew the shape is already in the
+        // history as how the shapes were before it
+        if !drawn {
+            self.history.push(Edit::Shapes(before));
+            self.undone.clear();
+        }
+
+        true
+    }
+
+    /// Replaces the current page with the given svg, which the core must be
+    /// able to read, and extras, as a single change that can be undone.
+    /// Returns `false` if the svg could not be read.
+    pub fn replace_current(&mut self, controller: &mut Pizarra, svg: String, extras: Extras) -> bool {
+        self.finish_text();
+
+        let before = self.snapshot(controller);
+
+        if !self.restore(controller, Snapshot { svg, extras }) {
+            return false;
+        }
+
+        self.history.push(Edit::Page(before));
+        self.undone.clear();
+
+        true
+    }
+
+    /// Like [`Pages::replace_current`], but as part of the last change if it
+    /// was a replacement too, so both are undone at once
+    pub fn amend_current(&mut self, controller: &mut Pizarra, svg: String, extras: Extras) -> bool {
+        if !matches!(self.history.last(), Some(Edit::Page(_))) {
+            return self.replace_current(controller, svg, extras);
+        }
+
+        self.finish_text();
+        self.undone.clear();
+
+        self.restore(controller, Snapshot { svg, extras })
+    }
+
+    /// The current page with `change` made to its layers. `active` is what
+    /// the active layer holds, and while `change` runs every layer holds its
+    /// contents so any of them can become the active one. `None` if nothing
+    /// changed.
+    fn with_layers(&mut self, active: String, config: Config, change: impl FnOnce(&mut Layers) -> bool) -> Option<Snapshot> {
+        self.finish_text();
+
+        let mut layers = self.extras.layers.clone();
+
+        layers.ensure();
+        layers.list[layers.active].set_content(active, config);
+
+        if !change(&mut layers) {
+            return None;
+        }
+
+        let (svg, mut extras) = layers::open_content(&layers.list[layers.active].take_content(), config);
+
+        extras.layers = layers;
+
+        Some(Snapshot { svg, extras })
+    }
+
+    /// Changes the layers of the current page as a single change that can be
+    /// undone, like adding, removing, reordering, hiding or locking them.
+    /// `change` tells if it did anything.
+    pub fn edit_layers(&mut self, controller: &mut Pizarra, change: impl FnOnce(&mut Layers) -> bool) -> bool {
+        let active = layers::active_content(&fill::core_svg(controller), &self.extras);
+
+        match self.with_layers(active, controller.config(), change) {
+            Some(snapshot) => self.replace_current(controller, snapshot.svg, snapshot.extras),
+            None => false,
+        }
+    }
+
+    /// Moves shapes and extras of the active layer to another one. `rest` is
+    /// the current page without them, like [`crate::selection::Selection`]
+    /// leaves it, and `moved` what they look like inside a layer, see
+    /// [`layers::active_content`].
+    pub fn move_to_layer(&mut self, controller: &mut Pizarra, rest: (String, Extras), moved: &str, index: usize) -> bool {
+        let active = layers::active_content(&rest.0, &rest.1);
+        let config = controller.config();
+        let snapshot = self.with_layers(active, config, |layers| {
+            if index == layers.active || layers.list.get(index).map(|layer| layer.locked).unwrap_or(true) {
+                return false;
+            }
+
+            layers.list[index].append(moved, config);
+
+            true
+        });
+
+        match snapshot {
+            Some(snapshot) => self.replace_current(controller, snapshot.svg, snapshot.extras),
+            None => false,
+        }
+    }
+
+    /// Makes another layer the one that is drawn on. What was done before
+    /// was done on the other layer, so the switch goes in the history too and
+    /// undoing goes back to that layer before undoing it.
+    pub fn activate_layer(&mut self, controller: &mut Pizarra, index: usize) -> bool {
+        self.edit_layers(controller, |layers| {
+            if index == layers.active || index >= layers.list.len() {
+                return false;
+            }
+
+            layers.active = index;
+
+            true
+        })
+    }
+
+    /// Undoes the last change to the current page, be it a pressure stroke or
+    /// something the core did.
+    pub fn undo(&mut self, controller: &mut Pizarra) {
+        self.finish_text();
+        self.revision += 1;
+
+        match self.history.pop() {
+            Some(Edit::Ink) => if let Some(stroke) = self.extras.ink.pop() {
+                self.undone.push(Undone::Ink(stroke));
+            },
+            Some(Edit::Highlight) => if let Some(stroke) = self.extras.highlights.pop() {
+                self.undone.push(Undone::Highlight(stroke));
+            },
+            Some(Edit::Text(change)) => {
+                swap(&mut self.extras.texts, change.index, &change.after, &change.before);
+                self.undone.push(Undone::Text(change));
+            },
+            Some(Edit::Image(change)) => {
+                swap(&mut self.extras.images, change.index, &change.after, &change.before);
+                self.undone.push(Undone::Image(change));
+            },
+            Some(Edit::Shapes(before)) => {
+                let after = fill::core_svg(controller);
+                let extras = self.extras.clone();
+
+                if self.restore(controller, Snapshot { svg: before, extras }) {
+                    self.undone.push(Undone::Shapes(after));
+                }
+            },
+            Some(Edit::Page(before)) => {
+                let after = self.snapshot(controller);
+
+                if self.restore(controller, before) {
+                    self.undone.push(Undone::Page(after));
+                }
+            },
+            Some(Edit::Core) | None => {
+                controller.undo();
+                self.undone.push(Undone::Core);
+            },
+        }
+    }
+
+    pub fn redo(&mut self, controller: &mut Pizarra) {
+        self.finish_text();
+        self.revision += 1;
+
+        match self.undone.pop() {
+            Some(Undone::Ink(stroke)) => {
+                self.extras.ink.push(stroke);
+                self.history.push(Edit::Ink);
+            },
+            Some(Undone::Highlight(stroke)) => {
+                self.extras.highlights.push(stroke);
+                self.history.push(Edit::Highlight);
+            },
+            Some(Undone::Text(change)) => {
+                swap(&mut self.extras.texts, change.index, &change.before, &change.after);
+                self.history.push(Edit::Text(change));
+            },
+            Some(Undone::Image(change)) => {
+                swap(&mut self.extras.images, change.index, &change.before, &change.after);
+                self.history.push(Edit::Image(change));
+            },
+            Some(Undone::Shapes(after)) => {
+                let before = fill::core_svg(controller);
+                let extras = self.extras.clone();
+
+                if self.restore(controller, Snapshot { svg: after, extras }) {
+                    self.history.push(Edit::Shapes(before));
+                }
+            },
+            Some(Undone::Page(after)) => {
+                let before = self.snapshot(controller);
+
+                if self.restore(controller, after) {
+                    self.history.push(Edit::Page(before));
+                }
+            },
+            Some(Undone::Core) | None => {
+                controller.redo();
+                self.history.push(Edit::Core);
+            },
+        }
+    }
+
+    /// The save status of the whole document, given the one of the current
+    /// page.
+    pub fn save_status(&self, current: &SaveStatus) -> SaveStatus {
+        match (current, self.changed) {
+            (status, false) => status.clone(),
+            (SaveStatus::NewAndEmpty | SaveStatus::NewAndChanged, true) => SaveStatus::NewAndChanged,
+            (SaveStatus::Saved(path) | SaveStatus::Unsaved(path), true) => SaveStatus::Unsaved(path.clone()),
+        }
+    }
+
+    /// svg of the whole document
+    pub fn document_svg(&mut self, controller: &mut Pizarra) -> String {
+        if self.pages.len() == 1 {
+            return self.extras.embed(&fill::core_svg(controller));
+        }
+
+        self.store_current(controller);
+
+        join_pages(&self.pages)
+    }
+
+    /// svg of every page, in order
+    pub fn svgs(&mut self, controller: &mut Pizarra) -> Vec<String> {
+        self.store_current(controller);
+
+        self.pages.clone()
+    }
+
+    /// A controller with the contents of each page and its extras, except
+    /// the current one whose place is taken by `None`. Used to render every
+    /// page, so the configuration is the one of the controller.
+    pub fn other_pages(&self, config: Config) -> Vec<Option<(Pizarra, Extras)>> {
+        self.pages.iter().enumerate().map(|(i, svg)| {
+            if i == self.current {
+                None
+            } else {
+                let mut page = Pizarra::new(Vec2D::new_screen(1.0, 1.0), config);
+                let (svg, extras) = Extras::split(svg, config);
+
+                page.open(&svg).ok();
+
+                Some((page, extras))
+            }
+        }).collect()
+    }
+
+    /// Keeps the history of the current page until it is shown again
+    fn store_history(&mut self, controller: &mut Pizarra) {
+        self.keep_core_history(controller);
+        self.histories[self.current] = History {
+            done: mem::take(&mut self.history),
+            undone: mem::take(&mut self.undone),
+        };
+    }
+
+    fn store_current(&mut self, controller: &mut Pizarra) {
+        if matches!(controller.get_save_status(), SaveStatus::NewAndChanged | SaveStatus::Unsaved(_)) {
+            self.changed = true;
+        }
+
+        self.pages[self.current] = self.extras.embed(&fill::core_svg(controller));
+    }
+
+    /// Loads the current page in the controller with its history, keeping the
+    /// view, the tool and the file the document is associated to.
+    fn load_current(&mut self, controller: &mut Pizarra, status: SaveStatus) {
+        let (svg, extras) = Extras::split(&self.pages[self.current], controller.config());
+        let transform = controller.get_transform();
+        let history = mem::take(&mut self.histories[self.current]);
+
+        // pages were written by us so they should always open
+        if controller.open(&svg).is_err() {
+            controller.reset();
+        }
+
+        view::restore(controller, transform);
+        self.restore_tool(controller);
+
+        self.extras = extras;
+        self.revision += 1;
+        self.editing = None;
+        self.history = history.done;
+        self.undone = history.undone;
+
+        if let SaveStatus::Saved(path) | SaveStatus::Unsaved(path) = status {
+            controller.set_saved(path);
+        }
+    }
+
+    /// Adds shapes and extras to the current page as a single change that can
+    /// be undone. `shapes` is an svg that the core can read, like the ones
+    /// made by [`crate::import`]. The core only loads whole documents, so the
+    /// page is loaded again with the shapes at the end, keeping the view, the
+    /// tool, the file of the document and its history. Returns `false` if the
+    /// result could not be read, in which case nothing changes.
+    pub fn merge_into_current(&mut self, controller: &mut Pizarra, shapes: &str, mut extras: Extras) -> bool {
+        let merged = merge_svg(&fill::core_svg(controller), shapes);
+        let mut scratch = Pizarra::new(Vec2D::new_screen(1.0, 1.0), controller.config());
+
+        if scratch.open(&merged).is_err() {
+            return false;
+        }
+
+        let mut page_extras = self.extras.clone();
+
+        page_extras.append(&mut extras);
+
+        self.replace_current(controller, merged, page_extras)
+    }
+
+    /// Shows the page at the given index
+    pub fn goto(&mut self, controller: &mut Pizarra, index: usize) {
+        if index == self.current || index >= self.pages.len() {
+            return;
+        }
+
+        let status = controller.get_save_status().clone();
+
+        self.finish_text();
+        self.store_current(controller);
+        self.store_history(controller);
+        self.current = index;
+        self.load_current(controller, status);
+    }
+
+    pub fn next(&mut self, controller: &mut Pizarra) {
+        self.goto(controller, self.current + 1);
+    }
+
+    pub fn previous(&mut self, controller: &mut Pizarra) {
+        if self.current > 0 {
+            self.goto(controller, self.current - 1);
+        }
+    }
+
+    /// Adds a blank page after the current one and shows it
+    pub fn add(&mut self, controller: &mut Pizarra) {
+        self.insert(controller, BLANK_PAGE.into());
+    }
+
+    /// Adds a page with the given svg after the current one and shows it
+    pub fn insert(&mut self, controller: &mut Pizarra, svg: String) {
+        self.pages.insert(self.current + 1, svg);
+        self.histories.insert(self.current + 1, History::default());
+        self.changed = true;
+        self.next(controller);
+    }
+
+    /// Adds a copy of the current page after it and shows the copy
+    pub fn duplicate(&mut self, controller: &mut Pizarra) {
+        self.store_current(controller);
+
+        let copy = self.pages[self.current].clone();
+
+        self.pages.insert(self.current + 1, copy);
+        self.histories.insert(self.current + 1, History::default());
+        self.changed = true;
+        self.next(controller);
+    }
+
+    /// Removes the current page and shows the next one, or the previous one
+    /// if it was the last. Removing the only page leaves a blank one.
+    pub fn delete(&mut self, controller: &mut Pizarra) {
+        let status = controller.get_save_status().clone();
+
+        if self.pages.len() == 1 {
+            self.pages[0] = BLANK_PAGE.into();
+        } else {
+            self.pages.remove(self.current);
+            self.histories.remove(self.current);
+            self.current = self.current.min(self.pages.len() - 1);
+        }
+
+        self.changed = true;
+        self.load_current(controller, status);
+    }
+
+    /// Moves the current page one place before (`-1`) or after (`1`). It
+    /// remains the current page.
+    pub fn move_current(&mut self, offset: isize) {
+        let target = self.current as isize + offset;
+
+        if target < 0 || target as usize >= self.pages.len() {
+            return;
+        }
+
+        self.pages.swap(self.current, target as usize);
+        self.histories.swap(self.current, target as usize);
+        self.current = target as usize;
+        self.changed = true;
+    }
+}
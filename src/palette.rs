@@ -0,0 +1,267 @@
+//! The palette of the header bar: the colors of the `[palette]` section of the
+//! configuration, which can be imported from a GIMP palette, followed by the
+//! last colors that were chosen. Clicking a color draws with it, and the keys
+//! `1` to `9` choose the first nine colors of the palette. `0` is left to the
+//! zoom.
+//!
+//! Like the color chooser, the palette doesn't change the alpha.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::glib;
+use gtk::{Box as GtkBox, Builder, Button, DrawingArea};
+use gtk::prelude::*;
+
+use crate::config::PaletteSettings;
+
+/// Size of the swatches in pixels
+const SWATCH: i32 = 16;
+
+/// Red, green and blue from 0 to 1
+type Rgb = [f64; 3];
+
+/// What choosing a color does
+type OnPick = Box<dyn Fn(Rgb)>;
+
+/// Tells if both are the same color, as far as the eye can tell
+fn same(a: Rgb, b: Rgb) -> bool {
+    a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 0.01)
+}
+
+fn hex(color: Rgb) -> String {
+    let [r, g, b] = color.map(|c| (c * 255.0).round() as u8);
+
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Reads the colors of a GIMP palette (`.gpl`), lines of red, green and blue
+/// from 0 to 255 optionally followed by a name
+pub fn parse_gpl(contents: &str) -> Result<Vec<Rgb>, String> {
+    let mut lines = contents.lines();
+
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err("No es una paleta de GIMP".into());
+    }
+
+    let mut colors = Vec::new();
+
+    for (number, line) in lines.enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+
+        let channels: Vec<_> = line.split_whitespace().take(3).map(str::parse::<u8>).collect();
+
+        match channels.as_slice() {
+            [Ok(r), Ok(g), Ok(b)] => colors.push([*r as f64 / 255.0, *g as f64 / 255.0, *b as f64 / 255.0]),
+            _ => return Err(format!("La línea {} no es un color: {}", number + 2, line)),
+        }
+    }
+
+    if colors.is_empty() {
+        return Err("La paleta no tiene colores".into());
+    }
+
+    Ok(colors)
+}
+
+/// Index in the palette of the color chosen with a number key from `1` to
+/// `9`. Keys of the numeric keypad count too.
+pub fn key_index(key_name: &str) -> Option<usize> {
+    let digit: usize = key_name.strip_prefix("KP_").unwrap_or(key_name).parse().ok().filter(|n| (1..=9).contains(n))?;
+
+    Some(digit - 1)
+}
+
+pub struct PaletteBar {
+    colors_box: GtkBox,
+    recent_box: GtkBox,
+    colors: RefCell<Vec<Rgb>>,
+
+    /// Last chosen colors, most recent first
+    recent: RefCell<Vec<Rgb>>,
+
+    /// How many recent colors are shown
+    recent_length: usize,
+
+    /// What choosing a color does, see [`PaletteBar::connect_pick`]
+    on_pick: RefCell<Option<OnPick>>,
+}
+
+impl PaletteBar {
+    pub fn new(builder: &Builder, settings: &PaletteSettings) -> Rc<PaletteBar> {
+        let bar = Rc::new(PaletteBar {
+            colors_box: builder.object("palette-colors").expect("no palette"),
+            recent_box: builder.object("recent-colors").expect("no recent colors"),
+            colors: RefCell::new(Vec::new()),
+            recent: RefCell::new(Vec::new()),
+            recent_length: settings.recent,
+            on_pick: RefCell::new(None),
+        });
+
+        bar.set_colors(settings.colors.clone());
+
+        bar
+    }
+
+    /// Replaces the colors of the palette
+    pub fn set_colors(self: &Rc<Self>, colors: Vec<Rgb>) {
+        for child in self.colors_box.children() {
+            self.colors_box.remove(&child);
+        }
+
+        for (index, &color) in colors.iter().enumerate() {
+            let tooltip = match index {
+                0..=8 => format!("{} (tecla {})", hex(color), index + 1),
+                _ => hex(color),
+            };
+
+            self.colors_box.add(&self.swatch(color, &tooltip));
+        }
+
+        self.colors_box.set_visible(!colors.is_empty());
+        *self.colors.borrow_mut() = colors;
+    }
+
+    /// Sets what happens when a color is chosen
+    pub fn connect_pick(&self, on_pick: impl Fn(Rgb) + 'static) {
+        *self.on_pick.borrow_mut() = Some(Box::new(on_pick));
+    }
+
+    /// The color at the given place of the palette
+    pub fn color(&self, index: usize) -> Option<Rgb> {
+        self.colors.borrow().get(index).copied()
+    }
+
+    /// Every color of the palette, without the recent ones
+    pub fn colors(&self) -> Vec<Rgb> {
+        self.colors.borrow().clone()
+    }
+
+    /// Draws with the given color, like clicking it
+    pub fn pick(self: &Rc<Self>, color: Rgb) {
+        self.push_recent(color);
+
+        if let Some(on_pick) = self.on_pick.borrow().as_ref() {
+            on_pick(color);
+        }
+    }
+
+    /// Puts a color that was just chosen first among the recent ones
+    pub fn push_recent(self: &Rc<Self>, color: Rgb) {
+        {
+            let mut recent = self.recent.borrow_mut();
+
+            recent.retain(|&c| !same(c, color));
+            recent.insert(0, color);
+            recent.truncate(self.recent_length);
+        }
+
+        // the swatch that was clicked may be one of the recent ones, so they
+        // are replaced once its handler is done
+        let weak = Rc::downgrade(self);
+
+        glib::idle_add_local_once(move || {
+            if let Some(bar) = weak.upgrade() {
+                bar.build_recent();
+            }
+        });
+    }
+
+    fn build_recent(self: &Rc<Self>) {
+        for child in self.recent_box.children() {
+            self.recent_box.remove(&child);
+        }
+
+        let recent = self.recent.borrow().clone();
+
+        for &color in recent.iter() {
+            self.recent_box.add(&self.swatch(color, &format!("{} (reciente)", hex(color))));
+        }
+
+        self.recent_box.set_visible(!recent.is_empty());
+    }
+
+    /// A button that shows a color and chooses it when clicked
+    fn swatch(self: &Rc<Self>, color: Rgb, tooltip: &str) -> Button {
+        let btn = Button::new();
+        let area = DrawingArea::new();
+        let weak = Rc::downgrade(self);
+
+        area.set_size_request(SWATCH, SWATCH);
+        area.connect_draw(move |_area, ctx| {
+            let [r, g, b] = color;
+
+            ctx.set_source_rgb(r, g, b);
+            ctx.paint().unwrap();
+
+            Inhibit(false)
+        });
+
+        btn.add(&area);
+        btn.set_can_focus(false);
+        btn.set_tooltip_text(Some(tooltip));
+        btn.connect_clicked(move |_btn| {
+            if let Some(bar) = weak.upgrade() {
+                bar.pick(color);
+            }
+        });
+        btn.show_all();
+
+        btn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palettes_start_with_their_header() {
+        assert_eq!(parse_gpl("255 0 0 Rojo\n"), Err("No es una paleta de GIMP".into()));
+        assert_eq!(parse_gpl(""), Err("No es una paleta de GIMP".into()));
+    }
+
+    #[test]
+    fn comments_names_and_columns_are_skipped() {
+        let gpl = "GIMP Palette\nName: Primarios\nColumns: 3\n# un comentario\n\n255 0 0 Rojo\n  0 255   0\t Verde\n0 0 255\n";
+
+        assert_eq!(parse_gpl(gpl), Ok(vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]));
+    }
+
+    #[test]
+    fn bad_lines_are_told_by_their_number() {
+        let gpl = "GIMP Palette\nName: Rota\n255 0 0\n255 0 nada\n";
+
+        assert_eq!(parse_gpl(gpl), Err("La línea 4 no es un color: 255 0 nada".into()));
+        assert_eq!(parse_gpl("GIMP Palette\n300 0 0\n"), Err("La línea 2 no es un color: 300 0 0".into()));
+    }
+
+    #[test]
+    fn palettes_have_colors() {
+        assert_eq!(parse_gpl("GIMP Palette\nName: Vacía\n"), Err("La paleta no tiene colores".into()));
+    }
+
+    #[test]
+    fn keys_from_one_to_nine_choose_colors() {
+        assert_eq!(key_index("1"), Some(0));
+        assert_eq!(key_index("9"), Some(8));
+        assert_eq!(key_index("KP_1"), Some(0));
+        assert_eq!(key_index("KP_9"), Some(8));
+
+        // `0` is left to the zoom
+        assert_eq!(key_index("0"), None);
+        assert_eq!(key_index("KP_0"), None);
+    }
+
+    #[test]
+    fn other_keys_choose_nothing() {
+        assert_eq!(key_index("10"), None);
+        assert_eq!(key_index("KP_10"), None);
+        assert_eq!(key_index("a"), None);
+        assert_eq!(key_index("KP_Add"), None);
+        assert_eq!(key_index("-1"), None);
+    }
+}
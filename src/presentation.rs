@@ -0,0 +1,139 @@
+//! Fullscreen and presentation mode. Presenting hides the header bar and
+//! leaves only the drawing and a small floating bar with the color, the tool
+//! and undo, which hides itself when the pointer stays still. Leaving puts the
+//! window back as it was.
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk::glib::{self, SourceId};
+use gtk::{ApplicationWindow, Builder, HeaderBar, Menu, MenuButton, Revealer};
+use gtk::prelude::*;
+
+/// Time the floating bar stays visible after the pointer stops
+const HIDE_AFTER: Duration = Duration::from_secs(2);
+
+pub struct Presentation {
+    window: ApplicationWindow,
+    header_bar: HeaderBar,
+    bar: Revealer,
+    tool_btn: MenuButton,
+    bar_tool_btn: MenuButton,
+    tool_menu: Menu,
+
+    /// Tells if the window is fullscreen, as reported by the window manager
+    fullscreen: Cell<bool>,
+
+    active: Cell<bool>,
+
+    /// If the window was fullscreen before presenting
+    was_fullscreen: Cell<bool>,
+
+    hide_source: RefCell<Option<SourceId>>,
+}
+
+impl Presentation {
+    pub fn new(builder: &Builder) -> Rc<Presentation> {
+        Rc::new(Presentation {
+            window: builder.object("main-window").expect("no window"),
+            header_bar: builder.object("header-bar").expect("no header bar"),
+            bar: builder.object("presentation-bar").expect("no presentation bar"),
+            tool_btn: builder.object("tool-menu-btn").expect("no tool button"),
+            bar_tool_btn: builder.object("presentation-tool-btn").expect("no presentation tool button"),
+            tool_menu: builder.object("tool-menu").expect("no tool menu"),
+            fullscreen: Cell::new(false),
+            active: Cell::new(false),
+            was_fullscreen: Cell::new(false),
+            hide_source: RefCell::new(None),
+        })
+    }
+
+    /// Must be called when the window state changes
+    pub fn set_fullscreen(&self, fullscreen: bool) {
+        self.fullscreen.set(fullscreen);
+    }
+
+    pub fn toggle_fullscreen(&self) {
+        if self.fullscreen.get() {
+            self.window.unfullscreen();
+        } else {
+            self.window.fullscreen();
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.get()
+    }
+
+    pub fn toggle(self: &Rc<Self>) {
+        if self.active.get() {
+            self.exit();
+        } else {
+            self.enter();
+        }
+    }
+
+    pub fn enter(self: &Rc<Self>) {
+        if self.active.replace(true) {
+            return;
+        }
+
+        self.was_fullscreen.set(self.fullscreen.get());
+        self.header_bar.hide();
+
+        // a menu can only be attached to one button
+        self.tool_btn.set_popup(None::<&Menu>);
+        self.bar_tool_btn.set_popup(Some(&self.tool_menu));
+
+        self.window.fullscreen();
+        self.poke();
+    }
+
+    pub fn exit(&self) {
+        if !self.active.replace(false) {
+            return;
+        }
+
+        if let Some(source) = self.hide_source.borrow_mut().take() {
+            source.remove();
+        }
+
+        self.bar.set_reveal_child(false);
+        // the menu may still be open from the floating bar
+        self.tool_menu.popdown();
+        self.bar_tool_btn.set_popup(None::<&Menu>);
+        self.tool_btn.set_popup(Some(&self.tool_menu));
+        self.header_bar.show();
+
+        if !self.was_fullscreen.get() {
+            self.window.unfullscreen();
+        }
+    }
+
+    /// Shows the floating bar for a while, called when the pointer moves
+    pub fn poke(self: &Rc<Self>) {
+        if !self.active.get() {
+            return;
+        }
+
+        self.bar.set_reveal_child(true);
+
+        if let Some(source) = self.hide_source.borrow_mut().take() {
+            source.remove();
+        }
+
+        let weak = Rc::downgrade(self);
+        let source = glib::timeout_add_local_once(HIDE_AFTER, move || {
+            if let Some(presentation) = weak.upgrade() {
+                presentation.hide_source.borrow_mut().take();
+
+                // not while a menu of the bar is open
+                if !presentation.tool_menu.is_visible() {
+                    presentation.bar.set_reveal_child(false);
+                }
+            }
+        });
+
+        *self.hide_source.borrow_mut() = Some(source);
+    }
+}
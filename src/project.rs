@@ -0,0 +1,180 @@
+//! The native `.pizarra` format. It is a zip archive with the svg of every
+//! page and a json manifest with what svg can't hold: the page being shown,
+//! the camera and the tool, color and thickness that were selected. Opening it
+//! restores the session exactly as it was saved.
+use std::io::{self, Cursor, Read, Write};
+use std::fs::File;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use zip::{ZipArchive, ZipWriter, CompressionMethod};
+use zip::write::FileOptions;
+use pizarra::prelude::*;
+
+use crate::pages::Pages;
+use crate::highlight::Highlighter;
+use crate::text::TextStyle;
+use crate::fill::Fill;
+use crate::tools::{ToolEntry, DEFAULT_TOOL};
+use crate::view;
+
+pub const EXTENSION: &str = "pizarra";
+const MIMETYPE: &str = "application/x-pizarra";
+const VERSION: u32 = 1;
+
+/// What the user had selected when the document was saved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// Name of the tool, see [`crate::tools`]
+    pub tool: String,
+    pub color: [f64; 3],
+    pub alpha: u8,
+    pub stroke: f64,
+
+    /// Transform of the current page as `[xx, yx, xy, yy, x0, y0]`
+    pub transform: [f64; 6],
+
+    /// Missing in files saved before there was a highlighter
+    #[serde(default)]
+    pub highlighter: Highlighter,
+
+    /// Font of new text boxes, missing in older files too
+    #[serde(default)]
+    pub text_style: TextStyle,
+
+    /// Fill of closed shapes, missing in older files too
+    #[serde(default)]
+    pub fill: Fill,
+}
+
+impl Session {
+    fn capture(controller: &Pizarra, pages: &Pages) -> Session {
+        let color = controller.selected_color();
+        let t = controller.get_transform();
+
+        Session {
+            tool: pages.tool.into(),
+            color: [color.float_r(), color.float_g(), color.float_b()],
+            alpha: color.alpha(),
+            stroke: pages.stroke,
+            transform: [t.xx, t.yx, t.xy, t.yy, t.x0, t.y0],
+            highlighter: pages.highlighter.clone(),
+            text_style: pages.text_style.clone(),
+            fill: pages.fill.clone(),
+        }
+    }
+
+    /// Puts the session back in the controller and pages
+    pub fn restore(&self, controller: &mut Pizarra, pages: &mut Pages) {
+        let tool = ToolEntry::by_name(&self.tool)
+            .or_else(|| ToolEntry::by_name(DEFAULT_TOOL))
+            .unwrap();
+        let [r, g, b] = self.color;
+        let [xx, yx, xy, yy, x0, y0] = self.transform;
+
+        if let Some(core_tool) = tool.tool() {
+            controller.set_tool(core_tool);
+        }
+
+        controller.set_color(Color::from_float_rgb(r, g, b).with_alpha(self.alpha));
+        controller.set_stroke(self.stroke.into());
+        view::restore(controller, Transform { xx, yx, xy, yy, x0, y0 });
+
+        pages.tool = tool.name;
+        pages.stroke = self.stroke;
+        pages.highlighter = self.highlighter.clone();
+        pages.text_style = self.text_style.clone();
+        pages.fill = self.fill.clone();
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    pages: usize,
+    current_page: usize,
+    session: Session,
+}
+
+/// The contents of a `.pizarra` file
+pub struct Project {
+    pub pages: Vec<String>,
+    pub current_page: usize,
+    pub session: Session,
+}
+
+fn page_name(index: usize) -> String {
+    format!("page-{}.svg", index + 1)
+}
+
+/// Tells if the given file contents are a `.pizarra` file, whatever its name
+pub fn is_project(contents: &[u8]) -> bool {
+    contents.starts_with(b"PK\x03\x04")
+}
+
+/// Writes the whole document and the current session to the given path
+pub fn write(filename: &Path, controller: &mut Pizarra, pages: &mut Pages) -> io::Result<()> {
+    let svgs = pages.svgs(controller);
+    let manifest = Manifest {
+        version: VERSION,
+        pages: svgs.len(),
+        current_page: pages.current(),
+        session: Session::capture(controller, pages),
+    };
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(File::create(filename)?);
+
+    // like in OpenDocument the mimetype goes first and uncompressed so the
+    // type can be sniffed from the first bytes
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(MIMETYPE.as_bytes())?;
+
+    zip.start_file("manifest.json", deflated)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+
+    for (i, svg) in svgs.iter().enumerate() {
+        zip.start_file(page_name(i), deflated)?;
+        zip.write_all(svg.as_bytes())?;
+    }
+
+    zip.finish()?;
+
+    Ok(())
+}
+
+/// Reads the contents of a `.pizarra` file. The svg of the pages is not
+/// parsed here.
+pub fn read(contents: &[u8]) -> io::Result<Project> {
+    let mut zip = ZipArchive::new(Cursor::new(contents))?;
+
+    let manifest: Manifest = {
+        let mut json = String::new();
+
+        zip.by_name("manifest.json")?.read_to_string(&mut json)?;
+
+        serde_json::from_str(&json)?
+    };
+
+    if manifest.version > VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "this file was written by a newer version of pizarra"));
+    }
+
+    let pages = (0..manifest.pages).map(|i| {
+        let mut svg = String::new();
+
+        zip.by_name(&page_name(i))?.read_to_string(&mut svg)?;
+
+        Ok(svg)
+    }).collect::<io::Result<Vec<_>>>()?;
+
+    if pages.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the file has no pages"));
+    }
+
+    Ok(Project {
+        current_page: manifest.current_page.min(pages.len() - 1),
+        pages,
+        session: manifest.session,
+    })
+}
@@ -0,0 +1,70 @@
+//! List of recently opened and saved files. It lives in a file in the data
+//! directory, one path per line with the most recent first, so every window
+//! sees the changes made by the others.
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+/// How many files are kept in the list. The menu shows as many of them as
+/// the settings say.
+const STORED: usize = 50;
+
+fn storage() -> PathBuf {
+    let mut path = config::data_dir();
+
+    path.push("recent");
+
+    path
+}
+
+/// The recent files, most recent first
+pub fn list() -> Vec<PathBuf> {
+    fs::read_to_string(storage())
+        .map(|contents| {
+            contents
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn write(files: &[PathBuf]) {
+    let result = File::create(storage()).and_then(|mut file| {
+        for path in files.iter().take(STORED) {
+            writeln!(file, "{}", path.display())?;
+        }
+
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        log::warn!("Could not write the list of recent files: {}", e);
+    }
+}
+
+/// Puts the given file at the top of the list
+pub fn add(file: &Path) {
+    let file = file.canonicalize().unwrap_or_else(|_| file.into());
+    let mut files = list();
+
+    files.retain(|f| *f != file);
+    files.insert(0, file);
+
+    write(&files);
+}
+
+pub fn remove(file: &Path) {
+    let mut files = list();
+
+    files.retain(|f| f != file);
+
+    write(&files);
+}
+
+pub fn clear() {
+    write(&[]);
+}
@@ -0,0 +1,543 @@
+//! The selection tool. Shapes are selected by dragging a rectangle around
+//! them, or a lasso with `Shift` held, and then moved by dragging them, scaled
+//! with the corners of the box around them and rotated with the handle above
+//! it. The keyboard moves, duplicates and deletes them.
+//!
+//! The core can't change a shape once it is drawn, so the selected shapes are
+//! taken out of its svg, where each shape is an element, rewritten with the
+//! change by [`crate::import`] and the page is loaded again, see
+//! [`crate::pages::Pages::replace_current`]. Strokes drawn with pressure or
+//! the highlighter are selected too, images and texts have their own tools.
+use std::f64::consts::PI;
+
+use cairo::{Context, Matrix};
+use pizarra::prelude::*;
+
+use crate::extras::Extras;
+use crate::images::to_world;
+use crate::import;
+
+/// Size of the handles in pixels
+const HANDLE: f64 = 8.0;
+
+/// Distance in pixels from the top of the box to the handle that rotates
+const ROTATE_HANDLE: f64 = 24.0;
+
+/// Distance in world units from the originals to their duplicates
+const DUPLICATE_OFFSET: f64 = 20.0;
+
+type Point = (f64, f64);
+
+/// The svg of the core split in the elements of its shapes. The core writes
+/// them inside a `<g id="storage">` after the rectangle of the background.
+pub struct CoreSvg {
+    pub head: String,
+    pub shapes: Vec<String>,
+    pub tail: String,
+}
+
+impl CoreSvg {
+    /// `None` if the svg can't be read or has no shapes
+    pub fn parse(svg: &str) -> Option<CoreSvg> {
+        let doc = roxmltree::Document::parse(svg).ok()?;
+        let storage = doc.descendants().find(|n| n.attribute("id") == Some("storage")).unwrap_or_else(|| doc.root_element());
+        let children: Vec<_> = storage.children()
+            .filter(|n| matches!(n.tag_name().name(), "path" | "ellipse"))
+            .collect();
+        let first = children.first()?.range().start;
+        let last = children.last()?.range().end;
+
+        Some(CoreSvg {
+            head: svg[..first].into(),
+            shapes: children.iter().map(|n| svg[n.range()].to_owned()).collect(),
+            tail: svg[last..].into(),
+        })
+    }
+
+    pub fn to_svg(&self) -> String {
+        format!("{}{}{}", self.head, self.shapes.join("\n"), self.tail)
+    }
+}
+
+/// A change to the stroke of shapes that are already drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Restyle {
+    /// Red, green and blue from 0 to 1, the alpha is kept
+    Color([f64; 3]),
+
+    /// Width in world units
+    Width(f64),
+
+    Alpha(u8),
+
+    /// Fill of the shapes, `None` to remove it. Strokes are never filled.
+    Fill(Option<Color>),
+}
+
+impl Restyle {
+    /// Tells if both change the same thing, so dragging a slider can be
+    /// undone at once
+    pub fn same_kind(&self, other: &Restyle) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// What can be done with the selection
+#[derive(Debug, Clone, Copy)]
+pub enum SelectionEdit {
+    Transform(Matrix),
+    Restyle(Restyle),
+    Duplicate,
+    Delete,
+}
+
+/// How many of the last shapes and strokes of a page are selected
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Selected {
+    pub shapes: usize,
+    pub ink: usize,
+    pub highlights: usize,
+}
+
+/// Shapes and strokes of a page, by their index
+pub struct Selection {
+    /// Revision of the page when the selection was made, see
+    /// [`crate::pages::Pages::revision`]. It means nothing once the page
+    /// changes.
+    revision: u64,
+
+    shapes: Vec<usize>,
+    ink: Vec<usize>,
+    highlights: Vec<usize>,
+
+    /// Corners of the box around the selection, in world coordinates
+    min: Point,
+    max: Point,
+}
+
+fn inside_polygon((x, y): Point, polygon: &[Point]) -> bool {
+    let mut inside = false;
+
+    for (i, &(x1, y1)) in polygon.iter().enumerate() {
+        let (x2, y2) = polygon[(i + 1) % polygon.len()];
+
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+fn matrix_array(m: &Matrix) -> [f64; 6] {
+    [m.xx, m.yx, m.xy, m.yy, m.x0, m.y0]
+}
+
+/// `m` applied around the given center
+fn around((x, y): Point, m: Matrix) -> Matrix {
+    let to_origin = Matrix::new(1.0, 0.0, 0.0, 1.0, -x, -y);
+    let back = Matrix::new(1.0, 0.0, 0.0, 1.0, x, y);
+
+    Matrix::multiply(&Matrix::multiply(&to_origin, &m), &back)
+}
+
+fn to_screen(t: Transform, (x, y): Point) -> Point {
+    Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0).transform_point(x, y)
+}
+
+impl Selection {
+    /// Selects what is among the given indices, if there is something
+    fn new(revision: u64, core: Option<&CoreSvg>, extras: &Extras, shapes: Vec<usize>, ink: Vec<usize>, highlights: Vec<usize>) -> Option<Selection> {
+        let shape_points = shapes.iter()
+            .filter_map(|&i| core?.shapes.get(i))
+            .flat_map(|shape| import::fragment_points(shape));
+        let stroke_bounds = ink.iter().filter_map(|&i| extras.ink.get(i)?.bounds())
+            .chain(highlights.iter().filter_map(|&i| extras.highlights.get(i)?.bounds()))
+            .flat_map(|[a, b]| [(a.x.val(), a.y.val()), (b.x.val(), b.y.val())]);
+        let (min, max) = shape_points.chain(stroke_bounds).fold(None, |bounds: Option<(Point, Point)>, (x, y)| {
+            Some(match bounds {
+                Some(((x1, y1), (x2, y2))) => ((x1.min(x), y1.min(y)), (x2.max(x), y2.max(y))),
+                None => ((x, y), (x, y)),
+            })
+        })?;
+
+        Some(Selection { revision, shapes, ink, highlights, min, max })
+    }
+
+    fn center(&self) -> Point {
+        ((self.min.0 + self.max.0) / 2.0, (self.min.1 + self.max.1) / 2.0)
+    }
+
+    fn corners(&self) -> [Point; 4] {
+        let ((x1, y1), (x2, y2)) = (self.min, self.max);
+
+        [(x1, y1), (x2, y1), (x2, y2), (x1, y2)]
+    }
+
+    fn contains(&self, (x, y): Point) -> bool {
+        x >= self.min.0 && x <= self.max.0 && y >= self.min.1 && y <= self.max.1
+    }
+
+    /// The page after the edit, as the svg for the core and the extras, and
+    /// how many of the last shapes and strokes are the edited ones. The
+    /// edited shapes go above the rest.
+    pub fn apply(&self, core_svg: &str, extras: &Extras, edit: SelectionEdit) -> Option<(String, Extras, Selected)> {
+        let matrix = match edit {
+            SelectionEdit::Transform(m) => m,
+            SelectionEdit::Duplicate => Matrix::new(1.0, 0.0, 0.0, 1.0, DUPLICATE_OFFSET, DUPLICATE_OFFSET),
+            SelectionEdit::Restyle(_) | SelectionEdit::Delete => Matrix::identity(),
+        };
+        let restyle = match edit {
+            SelectionEdit::Restyle(restyle) => Some(restyle),
+            _ => None,
+        };
+        let keep_originals = matches!(edit, SelectionEdit::Duplicate);
+        let add_edited = !matches!(edit, SelectionEdit::Delete);
+        let scale = (matrix.xx * matrix.yy - matrix.yx * matrix.xy).abs().sqrt();
+        let mut extras = extras.clone();
+        let mut selected = Selected::default();
+
+        let svg = match CoreSvg::parse(core_svg) {
+            Some(mut core) if !self.shapes.is_empty() => {
+                let edited: Vec<String> = self.shapes.iter()
+                    .filter_map(|&i| core.shapes.get(i))
+                    .map(|shape| match restyle {
+                        Some(restyle) => import::restyle_fragment(shape, restyle),
+                        None => import::transform_fragment(shape, matrix_array(&matrix)),
+                    })
+                    .collect();
+
+                if !keep_originals {
+                    core.shapes = core.shapes.into_iter().enumerate()
+                        .filter(|(i, _)| !self.shapes.contains(i))
+                        .map(|(_, shape)| shape)
+                        .collect();
+                }
+
+                if add_edited {
+                    selected.shapes = edited.iter().map(|shape| shape.lines().count()).sum();
+                    core.shapes.extend(edited.iter().map(|shape| shape.trim_end().to_owned()));
+                }
+
+                core.to_svg()
+            },
+            _ => core_svg.into(),
+        };
+
+        let edited_ink: Vec<_> = self.ink.iter().filter_map(|&i| extras.ink.get(i).cloned()).map(|mut stroke| {
+            for point in stroke.points.iter_mut() {
+                let (x, y) = matrix.transform_point(point.x, point.y);
+
+                point.x = x;
+                point.y = y;
+                point.width *= scale;
+            }
+
+            if let Some(restyle) = restyle {
+                stroke.restyle(restyle);
+            }
+
+            stroke
+        }).collect();
+        let edited_highlights: Vec<_> = self.highlights.iter().filter_map(|&i| extras.highlights.get(i).cloned()).map(|mut stroke| {
+            for point in stroke.points.iter_mut() {
+                *point = matrix.transform_point(point.0, point.1);
+            }
+
+            stroke.width *= scale;
+
+            if let Some(restyle) = restyle {
+                stroke.restyle(restyle);
+            }
+
+            stroke
+        }).collect();
+
+        if !keep_originals {
+            let mut index = 0;
+
+            extras.ink.retain(|_| {
+                index += 1;
+
+                !self.ink.contains(&(index - 1))
+            });
+
+            let mut index = 0;
+
+            extras.highlights.retain(|_| {
+                index += 1;
+
+                !self.highlights.contains(&(index - 1))
+            });
+        }
+
+        if add_edited {
+            selected.ink = edited_ink.len();
+            selected.highlights = edited_highlights.len();
+            extras.ink.extend(edited_ink);
+            extras.highlights.extend(edited_highlights);
+        }
+
+        Some((svg, extras, selected))
+    }
+
+    /// A page with only the selection, to copy it
+    pub fn only(&self, core_svg: &str, extras: &Extras) -> (String, Extras) {
+        let svg = match CoreSvg::parse(core_svg) {
+            Some(mut core) => {
+                core.shapes = self.shapes.iter().filter_map(|&i| core.shapes.get(i).cloned()).collect();
+
+                core.to_svg()
+            },
+            None => core_svg.into(),
+        };
+
+        (svg, Extras {
+            ink: self.ink.iter().filter_map(|&i| extras.ink.get(i).cloned()).collect(),
+            highlights: self.highlights.iter().filter_map(|&i| extras.highlights.get(i).cloned()).collect(),
+            ..Default::default()
+        })
+    }
+}
+
+/// What the pointer is doing with the selection tool, in world coordinates
+enum Gesture {
+    Rectangle(Point, Point),
+    Lasso(Vec<Point>),
+    Move { start: Point, current: Point },
+    Scale { anchor: Point, start: Point, current: Point },
+    Rotate { center: Point, start: Point, current: Point },
+}
+
+impl Gesture {
+    /// The change to the selection so far
+    fn matrix(&self) -> Option<Matrix> {
+        match *self {
+            Gesture::Rectangle(..) | Gesture::Lasso(_) => None,
+            Gesture::Move { start, current } => Some(Matrix::new(1.0, 0.0, 0.0, 1.0, current.0 - start.0, current.1 - start.1)),
+            Gesture::Scale { anchor, start, current } => {
+                let factor = |s: f64, c: f64, a: f64| if (s - a).abs() > 1e-6 { (c - a) / (s - a) } else { 1.0 };
+
+                Some(around(anchor, Matrix::new(factor(start.0, current.0, anchor.0), 0.0, 0.0, factor(start.1, current.1, anchor.1), 0.0, 0.0)))
+            },
+            Gesture::Rotate { center, start, current } => {
+                let angle = (current.1 - center.1).atan2(current.0 - center.0) - (start.1 - center.1).atan2(start.0 - center.0);
+                let (sin, cos) = angle.sin_cos();
+
+                Some(around(center, Matrix::new(cos, sin, -sin, cos, 0.0, 0.0)))
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Selector {
+    pub selection: Option<Selection>,
+
+    /// The last change of style made to this selection, the next one of the
+    /// same kind is part of the same step of the history
+    pub restyled: Option<Restyle>,
+
+    gesture: Option<Gesture>,
+}
+
+impl Selector {
+    /// Forgets the selection if the page changed since it was made
+    pub fn validate(&mut self, revision: u64) {
+        if self.selection.as_ref().map(|s| s.revision != revision).unwrap_or(false) {
+            self.selection = None;
+            self.restyled = None;
+        }
+    }
+
+    /// Tells if there is a selection that isn't being dragged, so the page
+    /// may have changed since it was made
+    pub fn is_idle(&self) -> bool {
+        self.selection.is_some() && self.gesture.is_none()
+    }
+
+    /// Starts moving, scaling or rotating the selection if the pointer is on
+    /// it or its handles, or a new selection otherwise
+    pub fn press(&mut self, t: Transform, screen: Point, lasso: bool) {
+        let point = to_world(t, screen);
+        let near = |corner: Point| {
+            let (x, y) = to_screen(t, corner);
+
+            (x - screen.0).abs() <= HANDLE && (y - screen.1).abs() <= HANDLE
+        };
+
+        self.gesture = Some(match &self.selection {
+            Some(selection) => {
+                let corners = selection.corners();
+
+                if near(rotate_handle(t, selection)) {
+                    Gesture::Rotate { center: selection.center(), start: point, current: point }
+                } else if let Some(i) = (0..4).find(|&i| near(corners[i])) {
+                    Gesture::Scale { anchor: corners[(i + 2) % 4], start: point, current: point }
+                } else if selection.contains(point) {
+                    Gesture::Move { start: point, current: point }
+                } else if lasso {
+                    Gesture::Lasso(vec![point])
+                } else {
+                    Gesture::Rectangle(point, point)
+                }
+            },
+            None if lasso => Gesture::Lasso(vec![point]),
+            None => Gesture::Rectangle(point, point),
+        });
+
+        if matches!(self.gesture, Some(Gesture::Rectangle(..) | Gesture::Lasso(_))) {
+            self.selection = None;
+        }
+
+        self.restyled = None;
+    }
+
+    /// Returns `false` if there is no gesture in progress
+    pub fn motion(&mut self, t: Transform, screen: Point) -> bool {
+        let point = to_world(t, screen);
+
+        match self.gesture.as_mut() {
+            Some(Gesture::Rectangle(_, current) | Gesture::Move { current, .. } | Gesture::Scale { current, .. } | Gesture::Rotate { current, .. }) => *current = point,
+            Some(Gesture::Lasso(points)) => points.push(point),
+            None => return false,
+        }
+
+        true
+    }
+
+    /// Ends the gesture. A rectangle or lasso selects what is inside it in
+    /// the page, whose revision is `revision`. Returns the change to apply to
+    /// the selection, if any.
+    pub fn release(&mut self, revision: u64, core_svg: &str, extras: &Extras) -> Option<SelectionEdit> {
+        let inside: Box<dyn Fn(Point) -> bool> = match self.gesture.take()? {
+            Gesture::Rectangle(a, b) => {
+                let (x1, x2, y1, y2) = (a.0.min(b.0), a.0.max(b.0), a.1.min(b.1), a.1.max(b.1));
+
+                Box::new(move |(x, y)| x >= x1 && x <= x2 && y >= y1 && y <= y2)
+            },
+            Gesture::Lasso(points) if points.len() > 2 => Box::new(move |p| inside_polygon(p, &points)),
+            Gesture::Lasso(_) => return None,
+            gesture => {
+                // a click on the selection doesn't move it
+                return gesture.matrix()
+                    .filter(|m| matrix_array(m) != matrix_array(&Matrix::identity()))
+                    .map(SelectionEdit::Transform);
+            },
+        };
+        let all_inside = |points: Vec<Point>| !points.is_empty() && points.into_iter().all(&inside);
+        let core = CoreSvg::parse(core_svg);
+        let shapes = core.iter().flat_map(|core| core.shapes.iter().enumerate())
+            .filter(|(_, shape)| all_inside(import::fragment_points(shape)))
+            .map(|(i, _)| i)
+            .collect();
+        let ink = extras.ink.iter().enumerate()
+            .filter(|(_, stroke)| all_inside(stroke.points.iter().map(|p| (p.x, p.y)).collect()))
+            .map(|(i, _)| i)
+            .collect();
+        let highlights = extras.highlights.iter().enumerate()
+            .filter(|(_, stroke)| all_inside(stroke.points.clone()))
+            .map(|(i, _)| i)
+            .collect();
+
+        self.selection = Selection::new(revision, core.as_ref(), extras, shapes, ink, highlights)
+            .filter(|s| !(s.shapes.is_empty() && s.ink.is_empty() && s.highlights.is_empty()));
+
+        None
+    }
+
+    /// Selects the last shapes and strokes of the page, the ones that were
+    /// just edited
+    pub fn select_last(&mut self, revision: u64, core_svg: &str, extras: &Extras, selected: Selected) {
+        let core = CoreSvg::parse(core_svg);
+        let count = core.as_ref().map(|core| core.shapes.len()).unwrap_or(0);
+        let last = |count: usize, selected: usize| -> Vec<usize> { (count.saturating_sub(selected)..count).collect() };
+
+        self.selection = Selection::new(
+            revision,
+            core.as_ref(),
+            extras,
+            last(count, selected.shapes),
+            last(extras.ink.len(), selected.ink),
+            last(extras.highlights.len(), selected.highlights),
+        );
+    }
+
+    pub fn clear(&mut self) {
+        self.selection = None;
+        self.restyled = None;
+        self.gesture = None;
+    }
+
+    /// Draws the rectangle or lasso being dragged and the box around the
+    /// selection with its handles
+    pub fn draw(&self, ctx: &Context, t: Transform) {
+        ctx.save().unwrap();
+        ctx.set_line_width(1.0);
+        ctx.set_source_rgba(0.2, 0.5, 0.9, 0.9);
+
+        match &self.gesture {
+            Some(Gesture::Rectangle(a, b)) => {
+                let corners = [*a, (b.0, a.1), *b, (a.0, b.1)];
+
+                polygon(ctx, t, &corners);
+                ctx.set_dash(&[4.0, 4.0], 0.0);
+                ctx.stroke().unwrap();
+            },
+            Some(Gesture::Lasso(points)) => {
+                polygon(ctx, t, points);
+                ctx.set_dash(&[4.0, 4.0], 0.0);
+                ctx.stroke().unwrap();
+            },
+            _ => {},
+        }
+
+        if let Some(selection) = &self.selection {
+            let m = self.gesture.as_ref().and_then(Gesture::matrix).unwrap_or_else(Matrix::identity);
+            let corners = selection.corners().map(|(x, y)| m.transform_point(x, y));
+
+            polygon(ctx, t, &corners);
+            ctx.set_dash(&[4.0, 4.0], 0.0);
+            ctx.stroke().unwrap();
+            ctx.set_dash(&[], 0.0);
+
+            if self.gesture.is_none() {
+                for corner in corners {
+                    let (x, y) = to_screen(t, corner);
+
+                    ctx.rectangle(x - HANDLE / 2.0, y - HANDLE / 2.0, HANDLE, HANDLE);
+                }
+
+                let (x, y) = to_screen(t, rotate_handle(t, selection));
+
+                ctx.new_sub_path();
+                ctx.arc(x, y, HANDLE / 2.0, 0.0, 2.0 * PI);
+                ctx.fill().unwrap();
+            }
+        }
+
+        ctx.restore().unwrap();
+    }
+}
+
+/// Where the handle that rotates the selection is, in world coordinates
+fn rotate_handle(t: Transform, selection: &Selection) -> Point {
+    let (x, y) = to_screen(t, ((selection.min.0 + selection.max.0) / 2.0, selection.min.1));
+
+    to_world(t, (x, y - ROTATE_HANDLE))
+}
+
+/// Adds a closed polygon in world coordinates to the path, in pixels
+fn polygon(ctx: &Context, t: Transform, points: &[Point]) {
+    for (i, &point) in points.iter().enumerate() {
+        let (x, y) = to_screen(t, point);
+
+        if i == 0 {
+            ctx.move_to(x, y);
+        } else {
+            ctx.line_to(x, y);
+        }
+    }
+
+    ctx.close_path();
+}
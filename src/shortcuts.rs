@@ -0,0 +1,135 @@
+//! Keyboard shortcuts. Every menu action and tool has a name, and the key that
+//! triggers it is read from the `[shortcuts]` section of the configuration
+//! file using the syntax of GTK accelerators, like `undo = "<Control>z"`. An
+//! empty string leaves an action without a key.
+use std::collections::BTreeMap;
+
+use gdk::ModifierType;
+use gtk::{AccelFlags, AccelGroup, Builder, Widget};
+use gtk::prelude::*;
+
+use crate::tools::TOOLS;
+
+/// Name of each action, the id of the widget it activates and its default
+/// key. Tools are added from [`TOOLS`].
+const ACTIONS: &[(&str, &str, &str)] = &[
+    ("new", "new-btn", "<Control>n"),
+    ("open", "open-btn", "<Control>o"),
+    ("import", "import-btn", "<Control>i"),
+    ("save", "save-btn", "<Control>s"),
+    ("save-as", "save-as-btn", "<Control><Shift>s"),
+    ("export", "export-btn", "<Control><Shift>e"),
+    ("quit", "exit-btn", "<Control>q"),
+    ("undo", "undo-btn", "<Control>z"),
+    ("redo", "redo-btn", "<Control><Shift>z"),
+    ("copy", "copy-btn", "<Control>c"),
+    ("paste", "paste-btn", "<Control>v"),
+    ("insert-image", "insert-image-btn", ""),
+    ("buttons", "buttons-btn", ""),
+    ("import-palette", "import-palette-btn", ""),
+    ("previous-page", "page-prev-btn", "<Control>Page_Up"),
+    ("next-page", "page-next-btn", "<Control>Page_Down"),
+    ("add-page", "page-add-btn", "<Control><Shift>n"),
+    ("duplicate-page", "page-duplicate-btn", "<Control><Shift>d"),
+    ("move-page-up", "page-move-up-btn", "<Control><Shift>Page_Up"),
+    ("move-page-down", "page-move-down-btn", "<Control><Shift>Page_Down"),
+    ("delete-page", "page-delete-btn", ""),
+    ("zoom-in", "zoom-in-btn", "plus"),
+    ("zoom-out", "zoom-out-btn", "minus"),
+    ("zoom-home", "zoom-home-btn", "0"),
+    ("fullscreen", "fullscreen-btn", "F11"),
+    ("presentation", "presentation-btn", "F5"),
+    ("pen-only", "pen-only-btn", ""),
+    ("layers", "layers-btn", "F7"),
+    ("color", "color-chooser", "<Alt>c"),
+    ("about", "about-btn", ""),
+];
+
+/// Every action with the id of its widget and its default key
+fn actions() -> Vec<(String, String, &'static str)> {
+    ACTIONS.iter()
+        .map(|&(name, widget, key)| (name.to_owned(), widget.to_owned(), key))
+        .chain(TOOLS.iter().map(|tool| (tool.action(), tool.menu_id(), tool.shortcut)))
+        .collect()
+}
+
+/// The keys of every action as they are written in a new configuration file
+pub fn defaults() -> BTreeMap<String, String> {
+    actions().into_iter().map(|(name, _, key)| (name, key.to_owned())).collect()
+}
+
+/// A key assigned to the widget of an action
+pub struct Shortcut {
+    widget: String,
+    key: u32,
+    modifiers: ModifierType,
+}
+
+/// The shortcuts to install, taking the configured key of each action or its
+/// default, and a description of every problem found in the configuration.
+pub fn resolve(configured: &BTreeMap<String, String>) -> (Vec<Shortcut>, Vec<String>) {
+    let actions = actions();
+    let mut problems: Vec<String> = configured.keys()
+        .filter(|name| !actions.iter().any(|(action, _, _)| action == *name))
+        .map(|name| format!("«{}» no es una acción", name))
+        .collect();
+    let mut shortcuts = Vec::new();
+
+    // the action that got each key first, to tell about duplicates
+    let mut taken: BTreeMap<(u32, u32), String> = BTreeMap::new();
+
+    for (name, widget, default) in actions {
+        let accelerator = configured.get(&name).map(String::as_str).unwrap_or(default);
+
+        if accelerator.is_empty() {
+            continue;
+        }
+
+        let (key, modifiers) = gtk::accelerator_parse(accelerator);
+
+        if key == 0 {
+            problems.push(format!("«{}» de «{}» no es un atajo válido", accelerator, name));
+            continue;
+        }
+
+        let key = *gdk::keys::Key::from(key).to_lower();
+
+        if let Some(other) = taken.get(&(key, modifiers.bits())) {
+            problems.push(format!("«{}» está asignado a «{}» y a «{}», solo funciona para «{}»", accelerator, other, name, other));
+            continue;
+        }
+
+        taken.insert((key, modifiers.bits()), name);
+        shortcuts.push(Shortcut { widget, key, modifiers });
+    }
+
+    (shortcuts, problems)
+}
+
+/// Id of the widget whose shortcut is the given key. GTK ignores the keys of
+/// menus that can't be seen, like the ones of the hidden header bar while
+/// presenting, so the window looks them up here.
+pub fn widget_for(shortcuts: &[Shortcut], key: u32, modifiers: ModifierType) -> Option<&str> {
+    let key = *gdk::keys::Key::from(key).to_lower();
+    let modifiers = modifiers & gtk::accelerator_get_default_mod_mask();
+
+    shortcuts.iter()
+        .find(|shortcut| shortcut.key == key && shortcut.modifiers == modifiers)
+        .map(|shortcut| shortcut.widget.as_str())
+}
+
+/// Makes the keys of the shortcuts activate their widgets in the window
+pub fn install<W: IsA<gtk::Window>>(builder: &Builder, window: &W, shortcuts: &[Shortcut]) {
+    let group = AccelGroup::new();
+
+    for shortcut in shortcuts {
+        let widget: Widget = builder.object(&shortcut.widget).expect("no widget for shortcut");
+
+        // buttons react to `clicked`, menu items to `activate`
+        let signal = if widget.is::<gtk::Button>() { "clicked" } else { "activate" };
+
+        widget.add_accelerator(signal, &group, shortcut.key, shortcut.modifiers, AccelFlags::VISIBLE);
+    }
+
+    window.add_accel_group(&group);
+}
@@ -0,0 +1,204 @@
+//! Typed text. Each text box is placed with a click of the text tool, is
+//! drawn with Pango and can be edited again by clicking on it. The core has no
+//! text, so the boxes live with the page like the other extras.
+//!
+//! In files they are `<text>` elements with a `<tspan>` per line, so other
+//! programs show them, and the exact text in an attribute so it can be read
+//! back as it was typed.
+use cairo::{Context, Format, ImageSurface, Matrix};
+use gtk::pango::{self, FontDescription, Layout};
+use pizarra::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::ink::{hex_color, parse_hex_color};
+
+/// Marks the `<text>` elements written by us
+pub const MARKER: &str = "<text data-pizarra-text=";
+
+/// Distance between the baselines of two lines, relative to the font size.
+/// Only used for the `<tspan>` elements, Pango places the lines on its own.
+const LINE_HEIGHT: f64 = 1.2;
+
+/// Font of new text boxes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextStyle {
+    pub family: String,
+
+    /// Size of the font in world units
+    pub size: f64,
+}
+
+impl Default for TextStyle {
+    fn default() -> TextStyle {
+        TextStyle {
+            family: "Sans".into(),
+            size: 24.0,
+        }
+    }
+}
+
+impl TextStyle {
+    /// As understood by a `GtkFontButton`, like `Sans 24`
+    pub fn font_name(&self) -> String {
+        format!("{} {}", self.family, self.size)
+    }
+
+    /// Reads the font of a `GtkFontButton`
+    pub fn from_font_name(name: &str) -> Option<TextStyle> {
+        let font = FontDescription::from_string(name);
+        let family = font.family()?;
+
+        if font.size() <= 0 {
+            return None;
+        }
+
+        Some(TextStyle {
+            family: family.to_string(),
+            size: font.size() as f64 / pango::SCALE as f64,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TextBox {
+    /// Top left corner in world coordinates
+    pub x: f64,
+    pub y: f64,
+
+    /// Lines are separated by `\n`
+    pub text: String,
+
+    pub style: TextStyle,
+    pub color: Color,
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
+/// A context to measure text outside of any drawing
+fn measuring_context() -> Context {
+    let surface = ImageSurface::create(Format::ARgb32, 1, 1).unwrap();
+
+    Context::new(&surface).unwrap()
+}
+
+impl TextBox {
+    pub fn new((x, y): (f64, f64), style: &TextStyle, color: Color) -> TextBox {
+        TextBox {
+            x,
+            y,
+            text: String::new(),
+            style: style.clone(),
+            color,
+        }
+    }
+
+    /// The text laid out for the given context, in world units
+    pub fn layout(&self, ctx: &Context) -> Layout {
+        let layout = pangocairo::create_layout(ctx).expect("no pango layout");
+        let mut font = FontDescription::new();
+
+        font.set_family(&self.style.family);
+        font.set_absolute_size(self.style.size * pango::SCALE as f64);
+        layout.set_font_description(Some(&font));
+        layout.set_text(&self.text);
+
+        layout
+    }
+
+    /// Width and height in world units. An empty box is as tall as a line.
+    pub fn size(&self) -> (f64, f64) {
+        let (width, height) = self.layout(&measuring_context()).size();
+
+        (width as f64 / pango::SCALE as f64, height as f64 / pango::SCALE as f64)
+    }
+
+    pub fn bounds(&self) -> Option<[Vec2D<WorldUnit>; 2]> {
+        if self.text.is_empty() {
+            return None;
+        }
+
+        let (width, height) = self.size();
+
+        Some([Vec2D::new_world(self.x, self.y), Vec2D::new_world(self.x + width, self.y + height)])
+    }
+
+    /// Tells if the point in world coordinates is inside the box
+    pub fn contains(&self, (x, y): (f64, f64)) -> bool {
+        let (width, height) = self.size();
+
+        x >= self.x && x <= self.x + width && y >= self.y && y <= self.y + height
+    }
+
+    /// Draws a frame around the box and a caret at the end of the text, to
+    /// show that it is being edited
+    pub fn draw_editing(&self, ctx: &Context, t: Transform) {
+        ctx.save().unwrap();
+        ctx.transform(Matrix::new(t.xx, t.yx, t.xy, t.yy, t.x0, t.y0));
+
+        let layout = self.layout(ctx);
+        let (width, height) = layout.size();
+        let (caret, _) = layout.cursor_pos(self.text.len() as i32);
+        let scale = pango::SCALE as f64;
+        let pixel = 1.0 / t.xx.hypot(t.yx);
+        let margin = 4.0 * pixel;
+
+        ctx.rectangle(self.x - margin, self.y - margin, width as f64 / scale + margin * 2.0, height as f64 / scale + margin * 2.0);
+        ctx.set_source_rgba(0.2, 0.5, 0.9, 0.8);
+        ctx.set_line_width(pixel);
+        ctx.set_dash(&[4.0 * pixel, 4.0 * pixel], 0.0);
+        ctx.stroke().unwrap();
+
+        ctx.set_dash(&[], 0.0);
+        ctx.set_line_width(2.0 * pixel);
+        ctx.move_to(self.x + caret.x() as f64 / scale, self.y + caret.y() as f64 / scale);
+        ctx.rel_line_to(0.0, caret.height() as f64 / scale);
+        ctx.stroke().unwrap();
+        ctx.restore().unwrap();
+    }
+
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        self.x += dx;
+        self.y += dy;
+    }
+
+    pub fn to_svg(&self) -> String {
+        let size = self.style.size;
+        let lines: String = self.text.split('\n').enumerate().map(|(i, line)| {
+            format!("<tspan x=\"{}\" y=\"{}\">{}</tspan>", self.x, self.y + size * (0.9 + LINE_HEIGHT * i as f64), escape(line))
+        }).collect();
+
+        format!(
+            "{}\"{}\" x=\"{}\" y=\"{}\" font-family=\"{}\" font-size=\"{}\" fill=\"{}\" fill-opacity=\"{}\" xml:space=\"preserve\">{}</text>",
+            MARKER, escape(&self.text), self.x, self.y, escape(&self.style.family), size, hex_color(self.color), self.color.float_alpha(), lines,
+        )
+    }
+
+    pub fn from_svg(element: &str) -> Option<TextBox> {
+        let doc = roxmltree::Document::parse(element).ok()?;
+        let node = doc.root_element();
+        let opacity = node.attribute("fill-opacity").and_then(|o| o.parse().ok()).unwrap_or(1.0);
+        let text = node.attribute("data-pizarra-text")?;
+
+        if text.is_empty() {
+            return None;
+        }
+
+        Some(TextBox {
+            x: node.attribute("x")?.parse().ok()?,
+            y: node.attribute("y")?.parse().ok()?,
+            text: text.into(),
+            style: TextStyle {
+                family: node.attribute("font-family")?.into(),
+                size: node.attribute("font-size")?.parse().ok()?,
+            },
+            color: parse_hex_color(node.attribute("fill")?, opacity)?,
+        })
+    }
+}
@@ -0,0 +1,98 @@
+//! The tools of the tool menu, known by a name that is used in menu ids,
+//! settings and saved files.
+use gtk::Image;
+use pizarra::prelude::*;
+
+pub struct ToolEntry {
+    /// Used in the id of the menu item: `tool-{name}-btn`
+    pub name: &'static str,
+
+    /// Icon shown in the tool button when the tool is selected
+    pub icon: &'static str,
+
+    /// Default key that selects the tool, see [`crate::shortcuts`]
+    pub shortcut: &'static str,
+}
+
+pub const TOOLS: &[ToolEntry] = &[
+    ToolEntry { name: PEN_TOOL, icon: "line.svg", shortcut: "l" },
+    ToolEntry { name: HIGHLIGHTER_TOOL, icon: "highlighter.svg", shortcut: "h" },
+    ToolEntry { name: "rect", icon: "rectangle.svg", shortcut: "r" },
+    ToolEntry { name: "polygon", icon: "polygon.svg", shortcut: "p" },
+    ToolEntry { name: "circle", icon: "circle_by_center_and_point.svg", shortcut: "c" },
+    ToolEntry { name: "circle3", icon: "circle_by_three_points.svg", shortcut: "k" },
+    ToolEntry { name: "ellipse", icon: "ellipse_by_foci_and_point.svg", shortcut: "e" },
+    ToolEntry { name: "grid", icon: "grid.svg", shortcut: "q" },
+    ToolEntry { name: "free-grid", icon: "free_grid.svg", shortcut: "<Alt>q" },
+    ToolEntry { name: ERASER_TOOL, icon: "eraser.svg", shortcut: "g" },
+    ToolEntry { name: SELECTION_TOOL, icon: "select.svg", shortcut: "s" },
+    ToolEntry { name: IMAGE_TOOL, icon: "image.svg", shortcut: "m" },
+    ToolEntry { name: TEXT_TOOL, icon: "text.svg", shortcut: "t" },
+    ToolEntry { name: LASER_TOOL, icon: "laser.svg", shortcut: "x" },
+];
+
+/// Free hand drawing. With a pen that reports pressure the strokes are
+/// recorded by this frontend, see [`crate::ink`].
+pub const PEN_TOOL: &str = "pen";
+
+/// Wide translucent strokes with their own color and width, see
+/// [`crate::highlight`]
+pub const HIGHLIGHTER_TOOL: &str = "highlighter";
+
+pub const ERASER_TOOL: &str = "eraser";
+
+/// The tool selected when the application starts
+pub const DEFAULT_TOOL: &str = PEN_TOOL;
+
+/// Selects shapes and strokes to move, scale, rotate, duplicate or delete
+/// them, see [`crate::selection`]
+pub const SELECTION_TOOL: &str = "select";
+
+/// Moves and scales the images of the page. Handled by this frontend.
+pub const IMAGE_TOOL: &str = "image";
+
+/// Places text boxes and edits them, see [`crate::text`]
+pub const TEXT_TOOL: &str = "text";
+
+/// Points at things with a trail that fades away, see [`crate::laser`]
+pub const LASER_TOOL: &str = "laser";
+
+impl ToolEntry {
+    pub fn by_name(name: &str) -> Option<&'static ToolEntry> {
+        TOOLS.iter().find(|t| t.name == name)
+    }
+
+    /// The tool of the core, `None` for the ones handled by this frontend
+    pub fn tool(&self) -> Option<SelectedTool> {
+        Some(match self.name {
+            "rect" => SelectedTool::Shape(ShapeTool::Rectangle),
+            "polygon" => SelectedTool::Shape(ShapeTool::Polygon),
+            "circle" => SelectedTool::Shape(ShapeTool::CircleByCenterAndPoint),
+            "circle3" => SelectedTool::Shape(ShapeTool::CircleThroughThreePoints),
+            "ellipse" => SelectedTool::Shape(ShapeTool::ThreePointEllipse),
+            "grid" => SelectedTool::Shape(ShapeTool::Grid),
+            "free-grid" => SelectedTool::Shape(ShapeTool::FreeGrid),
+            ERASER_TOOL => SelectedTool::Eraser,
+            HIGHLIGHTER_TOOL | SELECTION_TOOL | IMAGE_TOOL | TEXT_TOOL | LASER_TOOL => return None,
+            _ => SelectedTool::Shape(ShapeTool::Path),
+        })
+    }
+
+    /// Tells if the tool draws closed shapes, the ones that get a fill
+    pub fn closed(&self) -> bool {
+        matches!(self.name, "rect" | "polygon" | "circle" | "circle3" | "ellipse")
+    }
+
+    /// Name of the action that selects the tool, used in the configuration
+    pub fn action(&self) -> String {
+        format!("tool-{}", self.name)
+    }
+
+    pub fn menu_id(&self) -> String {
+        format!("tool-{}-btn", self.name)
+    }
+
+    pub fn image(&self) -> Image {
+        Image::from_resource(&format!("/tk/categulario/pizarra/icons/{}", self.icon))
+    }
+}
@@ -0,0 +1,144 @@
+//! Input from fingers and pens. Navigation with the fingers on touchscreens
+//! and touchpads: pinch to zoom, drag with two fingers to pan and twist them
+//! to rotate, and palm rejection while writing with a pen.
+//!
+//! The gestures report how much they changed since they began, so the last
+//! values are kept here to move the view only by the difference on each
+//! update.
+use gdk::InputSource;
+use pizarra::prelude::*;
+
+use crate::view;
+
+/// State of the gestures in progress
+#[derive(Default)]
+pub struct Gestures {
+    /// Scale the view was last zoomed at and center of the fingers in the
+    /// last update of the zoom
+    zoom: Option<(f64, (f64, f64))>,
+
+    /// Angle in the last update of the rotation
+    angle: Option<f64>,
+}
+
+impl Gestures {
+    pub fn zoom_begin(&mut self, center: (f64, f64)) {
+        self.zoom = Some((1.0, center));
+    }
+
+    /// Follows the fingers when they move together and zooms around them.
+    /// `scale` is relative to the beginning of the gesture. The core zooms by
+    /// doubles and halves, so the view zooms each time the fingers spread or
+    /// pinch that much. Returns `true` if the view moved.
+    pub fn zoom_update(&mut self, controller: &mut Pizarra, scale: f64, center: (f64, f64)) -> bool {
+        let (mut zoomed, last_center) = match self.zoom {
+            Some(zoom) => zoom,
+            None => return false,
+        };
+
+        if scale <= 0.0 {
+            return false;
+        }
+
+        view::pan(controller, center.0 - last_center.0, center.1 - last_center.1);
+
+        while scale / zoomed >= 2.0 {
+            view::zoom(controller, center, true);
+            zoomed *= 2.0;
+        }
+
+        while scale / zoomed <= 0.5 {
+            view::zoom(controller, center, false);
+            zoomed /= 2.0;
+        }
+
+        self.zoom = Some((zoomed, center));
+
+        true
+    }
+
+    pub fn zoom_end(&mut self) {
+        self.zoom = None;
+    }
+
+    pub fn rotate_begin(&mut self) {
+        self.angle = Some(0.0);
+    }
+
+    /// Rotates around the fingers. `angle` is relative to the beginning of
+    /// the gesture, in radians. Returns `true` if the view moved.
+    pub fn rotate_update(&mut self, controller: &mut Pizarra, angle: f64, center: (f64, f64)) -> bool {
+        match self.angle.replace(angle) {
+            Some(last) => {
+                view::rotate(controller, center, angle - last);
+
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn rotate_end(&mut self) {
+        self.angle = None;
+    }
+
+    /// Tells if the fingers are moving the view
+    pub fn active(&self) -> bool {
+        self.zoom.is_some() || self.angle.is_some()
+    }
+}
+
+/// Tells if the device is a pen, or its eraser end
+pub fn is_pen(source: Option<InputSource>) -> bool {
+    matches!(source, Some(InputSource::Pen | InputSource::Eraser))
+}
+
+/// Keeps track of the pen to ignore the hand that rests on the screen while
+/// writing. While the pen is near the screen only the pen is heard.
+#[derive(Default)]
+pub struct PalmRejection {
+    pub enabled: bool,
+    pen_near: bool,
+
+    /// Device that started the shape being drawn
+    drawing_with: Option<InputSource>,
+}
+
+impl PalmRejection {
+    pub fn new(enabled: bool) -> PalmRejection {
+        PalmRejection {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    /// The pen came near the screen. Returns `true` if a shape started by
+    /// something else must be cancelled, as it was most likely the palm.
+    pub fn pen_in(&mut self) -> bool {
+        let drawing_with = self.drawing_with.take();
+
+        self.pen_near = true;
+
+        self.enabled && drawing_with.is_some() && !is_pen(drawing_with)
+    }
+
+    pub fn pen_out(&mut self) {
+        self.pen_near = false;
+    }
+
+    /// Tells if an event from the given device must be handled
+    pub fn accepts(&mut self, source: Option<InputSource>) -> bool {
+        if is_pen(source) {
+            // not every driver reports proximity
+            self.pen_near = true;
+
+            return true;
+        }
+
+        !self.enabled || !self.pen_near
+    }
+
+    pub fn drawing_with(&mut self, source: Option<InputSource>) {
+        self.drawing_with = source;
+    }
+}
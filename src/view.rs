@@ -0,0 +1,150 @@
+//! Moving the view of the page around: panning, zooming and rotating. Used by
+//! the gestures of [`crate::touch`] and by the keyboard.
+//!
+//! The core can't be given a transform, it only zooms in and out by a factor
+//! of two around the center of the screen and scrolls, which with shift
+//! rotates around the center too. Everything here is made out of those.
+use std::f64::consts::PI;
+
+use pizarra::config::Config;
+use pizarra::prelude::*;
+
+/// Pixels the view moves with an arrow key, five times as much with shift
+const PAN_STEP: f64 = 40.0;
+
+/// Degrees the view rotates with a bracket key, six times as much with shift
+const ROTATE_STEP: f64 = 15.0;
+
+/// Scrolls the core with a factor of one, so it moves the view by exactly
+/// `delta` pixels, or with `shift` rotates it by `delta.y` degrees.
+fn scroll(controller: &mut Pizarra, delta: Vec2D<ScreenUnit>, shift: bool) {
+    let config = controller.config();
+
+    controller.set_config(Config {
+        scroll_factor: 1.0,
+        ..config
+    });
+    controller.scroll(delta, Flags {
+        shift,
+        ..Default::default()
+    });
+    controller.set_config(config);
+}
+
+fn center(controller: &Pizarra) -> (f64, f64) {
+    let dimensions = controller.get_dimensions();
+
+    (dimensions.x.val() / 2.0, dimensions.y.val() / 2.0)
+}
+
+/// Moves the view by the given amount of pixels
+pub fn pan(controller: &mut Pizarra, dx: f64, dy: f64) {
+    scroll(controller, Vec2D::new_screen(dx, dy), false);
+}
+
+/// Rotates the view `angle` radians around a point of the screen
+pub fn rotate(controller: &mut Pizarra, (x, y): (f64, f64), angle: f64) {
+    let (cx, cy) = center(controller);
+    let (sin, cos) = angle.sin_cos();
+
+    scroll(controller, Vec2D::new_screen(0.0, angle.to_degrees()), true);
+
+    // the core turned around the center, which moved the point
+    let (dx, dy) = (x - cx, y - cy);
+
+    pan(controller, dx - (dx * cos - dy * sin), dy - (dx * sin + dy * cos));
+}
+
+/// Zooms the view in (a factor of two) or out (a half) around a point of the
+/// screen
+pub fn zoom(controller: &mut Pizarra, (x, y): (f64, f64), zoom_in: bool) {
+    let (cx, cy) = center(controller);
+    let factor = if zoom_in { 2.0 } else { 0.5 };
+
+    if zoom_in {
+        controller.zoom_in();
+    } else {
+        controller.zoom_out();
+    }
+
+    // the core zoomed around the center, which moved the point
+    pan(controller, (x - cx) * (1.0 - factor), (y - cy) * (1.0 - factor));
+}
+
+/// Puts the view back as `t`, for example when a page is loaded again. The
+/// zoom is the closest power of two to the one of `t`, and what was in the
+/// center of the screen stays there.
+pub fn restore(controller: &mut Pizarra, t: Transform) {
+    let (cx, cy) = center(controller);
+    let middle = Vec2D::new_screen(cx, cy);
+    let scale = (t.xx * t.yy - t.xy * t.yx).abs().sqrt();
+
+    if !scale.is_normal() {
+        return;
+    }
+
+    let seen = t.to_world_coordinates(middle);
+
+    controller.go_home();
+
+    for _ in 0..scale.log2().round().abs() as usize {
+        zoom(controller, (cx, cy), scale > 1.0);
+    }
+
+    rotate(controller, (cx, cy), t.yx.atan2(t.xx));
+
+    let now = controller.get_transform().to_screen_coordinates(seen);
+
+    pan(controller, cx - now.x.val(), cy - now.y.val());
+}
+
+/// A movement of the view made with the keyboard
+#[derive(Debug, Clone, Copy)]
+pub enum Navigation {
+    /// Pixels to move the view
+    Pan(f64, f64),
+    ZoomIn,
+    ZoomOut,
+
+    /// Radians to rotate the view around the center of the screen
+    Rotate(f64),
+}
+
+impl Navigation {
+    /// The movement of a key with the given name, if it moves the view. The
+    /// arrows move the view, not the drawing, so the drawing goes the other
+    /// way.
+    pub fn from_key(name: &str, shift: bool) -> Option<Navigation> {
+        let step = if shift { PAN_STEP * 5.0 } else { PAN_STEP };
+        let angle = ROTATE_STEP * PI / 180.0;
+
+        Some(match name {
+            "Left" => Navigation::Pan(step, 0.0),
+            "Right" => Navigation::Pan(-step, 0.0),
+            "Up" => Navigation::Pan(0.0, step),
+            "Down" => Navigation::Pan(0.0, -step),
+            "Page_Up" => Navigation::ZoomIn,
+            "Page_Down" => Navigation::ZoomOut,
+            "bracketleft" => Navigation::Rotate(-angle),
+            "bracketright" => Navigation::Rotate(angle),
+            // shift turns brackets into braces
+            "braceleft" => Navigation::Rotate(-angle * 6.0),
+            "braceright" => Navigation::Rotate(angle * 6.0),
+            _ => return None,
+        })
+    }
+
+    /// Moves the view of the controller
+    pub fn apply(self, controller: &mut Pizarra) {
+        match self {
+            Navigation::Pan(dx, dy) => pan(controller, dx, dy),
+            Navigation::ZoomIn => controller.zoom_in(),
+            Navigation::ZoomOut => controller.zoom_out(),
+            Navigation::Rotate(angle) => {
+                let center = center(controller);
+
+                rotate(controller, center, angle);
+            },
+        }
+    }
+}